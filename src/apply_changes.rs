@@ -0,0 +1,157 @@
+//! Merging of osmChange diffs into a PBF file, like `osmium apply-changes`
+
+use std::io::{self, Read, Write};
+
+use crate::owned::Element;
+use crate::pbf_writer::PbfWriter;
+use crate::xml::ChangeAction;
+use crate::{BlobReader, PrimitiveBlock};
+
+fn element_version(element: &Element) -> u32 {
+    element.version().unwrap_or_default()
+}
+
+/// Orders changes by type, id and version, keeping only the newest change of
+/// each element. Of changes with the same version the last one wins, so
+/// change files should be passed oldest first.
+fn newest_changes<I>(changes: I) -> Vec<(ChangeAction, Element)>
+where
+    I: IntoIterator<Item = (ChangeAction, Element)>,
+{
+    let mut changes: Vec<_> = changes.into_iter().collect();
+    changes.sort_by_key(|(_, element)| (element.sort_key(), element_version(element)));
+    let mut newest: Vec<(ChangeAction, Element)> = Vec::with_capacity(changes.len());
+    for change in changes {
+        match newest.last_mut() {
+            Some(last) if last.1.sort_key() == change.1.sort_key() => *last = change,
+            _ => newest.push(change),
+        }
+    }
+    newest
+}
+
+/// Writes the elements of `reader`, which must be sorted by type and id,
+/// with `changes` applied
+///
+/// A change replaces the element of the same type and id, unless the file
+/// already has a newer version of it; deletions drop it. Created elements
+/// are inserted in order.
+pub fn apply_changes<R, W, I>(
+    reader: &mut BlobReader<R>,
+    changes: I,
+    writer: &mut PbfWriter<W>,
+) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+    I: IntoIterator<Item = (ChangeAction, Element)>,
+{
+    let mut changes = newest_changes(changes).into_iter().peekable();
+    let write_change = |writer: &mut PbfWriter<W>, (action, element)| match action {
+        ChangeAction::Delete => Ok(()),
+        ChangeAction::Create | ChangeAction::Modify => writer.write_element(element),
+    };
+
+    for blob in reader {
+        let data = blob.into_data();
+        let primitive_block = PrimitiveBlock::parse(&data);
+        for primitive in primitive_block.primitives() {
+            let element = Element::from(&primitive);
+            let key = element.sort_key();
+            while let Some(change) = changes.next_if(|(_, change)| change.sort_key() < key) {
+                write_change(writer, change)?;
+            }
+            match changes.next_if(|(_, change)| change.sort_key() == key) {
+                Some(change) if element_version(&change.1) >= element_version(&element) => {
+                    write_change(writer, change)?
+                }
+                _ => writer.write_element(element)?,
+            }
+        }
+    }
+    for change in changes {
+        write_change(writer, change)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::owned::{Info, Node};
+    use crate::xml::OscReader;
+
+    fn node(id: u64, version: u32, lat: f64) -> Element {
+        Element::Node(Node {
+            id,
+            lat,
+            lon: 0.0,
+            info: Some(Info {
+                version: Some(version),
+                ..Info::default()
+            }),
+            tags: vec![],
+        })
+    }
+
+    #[test]
+    fn test_newest_changes() {
+        let changes = newest_changes(vec![
+            (ChangeAction::Modify, node(2, 3, 1.0)),
+            (ChangeAction::Create, node(1, 1, 1.0)),
+            (ChangeAction::Delete, node(2, 4, 1.0)),
+            (ChangeAction::Modify, node(2, 2, 1.0)),
+            (ChangeAction::Modify, node(1, 1, 2.0)),
+        ]);
+        assert_eq!(
+            changes,
+            vec![
+                (ChangeAction::Modify, node(1, 1, 2.0)),
+                (ChangeAction::Delete, node(2, 4, 1.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let header = crate::HeaderBlock::default();
+        let mut writer = PbfWriter::new(vec![], &header).unwrap();
+        for element in [node(1, 1, 1.0), node(3, 1, 1.0), node(5, 2, 1.0)] {
+            writer.write_element(element).unwrap();
+        }
+        let input = writer.finish().unwrap();
+
+        let osc = r#"<osmChange version="0.6">
+  <create><node id="2" version="1" lat="2" lon="0"/></create>
+  <modify><node id="3" version="2" lat="2" lon="0"/></modify>
+  <modify><node id="3" version="3" lat="3" lon="0"/></modify>
+  <modify><node id="5" version="1" lat="2" lon="0"/></modify>
+  <delete><node id="1" version="2" visible="false"/></delete>
+  <create><node id="6" version="1" lat="2" lon="0"/></create>
+</osmChange>"#;
+        let changes = OscReader::new(osc.as_bytes())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        let mut writer = PbfWriter::new(vec![], &header).unwrap();
+        apply_changes(&mut BlobReader::new(&input[..]), changes, &mut writer).unwrap();
+        let output = writer.finish().unwrap();
+
+        let mut result = vec![];
+        for blob in BlobReader::new(&output[..]) {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            for primitive in primitive_block.primitives() {
+                let element = Element::from(&primitive);
+                let Element::Node(node) = &element else {
+                    panic!("unexpected {:?}", element);
+                };
+                result.push((node.id, element_version(&element), node.lat.round()));
+            }
+        }
+        // Node 5 keeps its newer version from the file
+        assert_eq!(
+            result,
+            vec![(2, 1, 2.0), (3, 3, 3.0), (5, 2, 1.0), (6, 1, 2.0)]
+        );
+    }
+}
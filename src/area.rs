@@ -0,0 +1,594 @@
+//! Assembly of areas from multipolygon relations and closed ways
+//!
+//! Needs two passes: the first one, driven with `apply()`, collects the
+//! `type=multipolygon` and `type=boundary` relations. The second one,
+//! `AreaAssembler::assemble()`, stores node locations, builds areas from
+//! closed ways right away, and from relations once their member ways have
+//! been seen.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::geometry::{MultiPolygon, Polygon, Ring, ring_contains, segments_cross, signed_area};
+use crate::id_set::IdSet;
+use crate::{
+    BlobReader, Handler, Location, NodeLocationStore, Primitive, PrimitiveBlock, Relation,
+};
+use crate::{RelationMemberType, Way};
+
+/// Keys that make a closed way an area, unless tagged `area=no`
+const AREA_KEYS: &[&str] = &[
+    "aeroway",
+    "amenity",
+    "building",
+    "building:part",
+    "craft",
+    "historic",
+    "landuse",
+    "leisure",
+    "man_made",
+    "military",
+    "natural",
+    "office",
+    "place",
+    "public_transport",
+    "shop",
+    "sport",
+    "tourism",
+    "water",
+];
+
+/// Whether a closed way with these tags describes an area
+pub fn is_area_way<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(tags: I) -> bool {
+    let mut result = false;
+    for (key, value) in tags {
+        match (key, value) {
+            ("area", "no") => return false,
+            ("area", "yes") => result = true,
+            ("natural", "coastline") => (),
+            (key, _) if AREA_KEYS.contains(&key) => result = true,
+            _ => (),
+        }
+    }
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AreaSource {
+    Way(u64),
+    Relation(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Area {
+    pub source: AreaSource,
+    pub tags: Vec<(String, String)>,
+    /// Outer rings counterclockwise, inner rings clockwise
+    pub geometry: MultiPolygon,
+}
+
+/// Why no area could be built; all variants carry the offending way IDs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AreaError {
+    /// Member ways that did not appear in the file
+    MissingWays(Vec<u64>),
+    /// A way references nodes that did not appear in the file
+    MissingNodes { way_id: u64, node_ids: Vec<u64> },
+    /// These ways form a chain that does not close into a ring
+    OpenRing(Vec<u64>),
+    /// These ways form a ring of less than three distinct nodes
+    DegenerateRing(Vec<u64>),
+    /// Segments of these ways cross each other
+    SelfIntersection(Vec<u64>),
+}
+
+impl AreaError {
+    pub fn way_ids(&self) -> Vec<u64> {
+        match self {
+            AreaError::MissingNodes { way_id, .. } => vec![*way_id],
+            AreaError::MissingWays(way_ids)
+            | AreaError::OpenRing(way_ids)
+            | AreaError::DegenerateRing(way_ids)
+            | AreaError::SelfIntersection(way_ids) => way_ids.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenArea {
+    pub source: AreaSource,
+    pub error: AreaError,
+}
+
+struct PendingRelation {
+    tags: Vec<(String, String)>,
+    way_ids: Vec<u64>,
+}
+
+/// Nodes of a way: IDs for stitching, locations for the geometry
+type WayNodes = Vec<(u64, Location)>;
+
+pub struct AreaAssembler {
+    relations: Vec<(u64, PendingRelation)>,
+    member_ways: IdSet,
+}
+
+impl Default for AreaAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AreaAssembler {
+    pub fn new() -> Self {
+        AreaAssembler {
+            relations: vec![],
+            member_ways: IdSet::dense(),
+        }
+    }
+
+    /// Number of relations collected in the first pass
+    pub fn relations_len(&self) -> usize {
+        self.relations.len()
+    }
+
+    /// Second pass over the same file; calls `f` for every area, broken or not
+    ///
+    /// Locations are taken from nodes seen in this pass, so the file must
+    /// have nodes before ways, as sorted files do.
+    pub fn assemble<R, S, F>(&self, reader: &mut BlobReader<R>, store: &mut S, mut f: F)
+    where
+        R: Read,
+        S: NodeLocationStore,
+        F: FnMut(Result<Area, BrokenArea>),
+    {
+        let mut way_nodes: HashMap<u64, Result<WayNodes, AreaError>> = HashMap::new();
+        let mut prepared = false;
+
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            for primitive in primitive_block.primitives() {
+                match primitive {
+                    Primitive::Node(node) => store.add_node(&node),
+                    Primitive::Way(way) => {
+                        if !prepared {
+                            store.prepare();
+                            prepared = true;
+                        }
+                        let is_member = self.member_ways.contains(way.id);
+                        let is_area = is_closed(&way) && is_area_way(way.tags());
+                        if !is_member && !is_area {
+                            continue;
+                        }
+                        let nodes = resolve_way(store, &way);
+                        if is_area {
+                            let tags = way
+                                .tags()
+                                .map(|(k, v)| (k.to_string(), v.to_string()))
+                                .collect();
+                            f(build_area(
+                                AreaSource::Way(way.id),
+                                tags,
+                                vec![(way.id, nodes.clone())],
+                            ));
+                        }
+                        if is_member {
+                            way_nodes.insert(way.id, nodes);
+                        }
+                    }
+                    Primitive::Relation(_) => (),
+                }
+            }
+        }
+
+        for (id, relation) in &self.relations {
+            let source = AreaSource::Relation(*id);
+            let missing: Vec<u64> = relation
+                .way_ids
+                .iter()
+                .filter(|way_id| !way_nodes.contains_key(way_id))
+                .copied()
+                .collect();
+            if !missing.is_empty() {
+                f(Err(BrokenArea {
+                    source,
+                    error: AreaError::MissingWays(missing),
+                }));
+                continue;
+            }
+            let ways = relation
+                .way_ids
+                .iter()
+                .map(|way_id| (*way_id, way_nodes[way_id].clone()))
+                .collect();
+            f(build_area(source, relation.tags.clone(), ways));
+        }
+    }
+}
+
+impl Handler for AreaAssembler {
+    fn relation(&mut self, relation: &Relation) {
+        if !relation
+            .tags()
+            .any(|(k, v)| k == "type" && (v == "multipolygon" || v == "boundary"))
+        {
+            return;
+        }
+        let mut way_ids = vec![];
+        for (role, id, member_type) in relation.members() {
+            if member_type == RelationMemberType::Way
+                && matches!(role, "outer" | "inner" | "")
+                && !way_ids.contains(&id)
+            {
+                way_ids.push(id);
+            }
+        }
+        if way_ids.is_empty() {
+            return;
+        }
+        self.member_ways.extend(way_ids.iter().copied());
+        let tags = relation
+            .tags()
+            .filter(|(k, _)| *k != "type")
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        self.relations
+            .push((relation.id, PendingRelation { tags, way_ids }));
+    }
+}
+
+fn is_closed(way: &Way) -> bool {
+    let mut refs = way.refs();
+    let first = refs.next();
+    first.is_some() && refs.last() == first
+}
+
+fn resolve_way<S: NodeLocationStore>(store: &S, way: &Way) -> Result<WayNodes, AreaError> {
+    let mut nodes = vec![];
+    let mut missing = vec![];
+    for id in way.refs() {
+        let id = id as u64;
+        match store.get(id) {
+            Some(location) => nodes.push((id, location)),
+            None => missing.push(id),
+        }
+    }
+    if missing.is_empty() {
+        Ok(nodes)
+    } else {
+        Err(AreaError::MissingNodes {
+            way_id: way.id,
+            node_ids: missing,
+        })
+    }
+}
+
+/// A closed ring with the way each of its segments came from
+struct AssembledRing {
+    nodes: WayNodes,
+    segment_ways: Vec<u64>,
+}
+
+impl AssembledRing {
+    fn locations(&self) -> Ring {
+        self.nodes.iter().map(|&(_, location)| location).collect()
+    }
+
+    /// A location of this ring that is not a node of `other`
+    fn test_location(&self, other: &AssembledRing) -> Location {
+        self.nodes
+            .iter()
+            .find(|(id, _)| !other.nodes.iter().any(|(other_id, _)| other_id == id))
+            .map(|&(_, location)| location)
+            .unwrap_or_else(|| {
+                let (a, b) = (self.nodes[0].1, self.nodes[1].1);
+                Location::new((a.lat + b.lat) / 2.0, (a.lon + b.lon) / 2.0)
+            })
+    }
+}
+
+/// Joins ways at their end nodes until every chain is closed
+fn stitch_rings(mut ways: Vec<(u64, WayNodes)>) -> Result<Vec<AssembledRing>, AreaError> {
+    // Ways without a segment have no ends to join
+    if let Some((way_id, _)) = ways.iter().find(|(_, nodes)| nodes.len() < 2) {
+        return Err(AreaError::DegenerateRing(vec![*way_id]));
+    }
+    let mut rings = vec![];
+    ways.reverse();
+    while let Some((way_id, nodes)) = ways.pop() {
+        let mut ring = AssembledRing {
+            segment_ways: vec![way_id; nodes.len() - 1],
+            nodes,
+        };
+        let mut chain = vec![way_id];
+        while ring.nodes[0].0 != ring.nodes[ring.nodes.len() - 1].0 {
+            let end = ring.nodes.last().map(|&(id, _)| id);
+            let next = ways.iter().rposition(|(_, nodes)| {
+                nodes.first().map(|&(id, _)| id) == end || nodes.last().map(|&(id, _)| id) == end
+            });
+            let Some(next) = next else {
+                return Err(AreaError::OpenRing(chain));
+            };
+            let (way_id, mut nodes) = ways.remove(next);
+            if nodes.first().map(|&(id, _)| id) != end {
+                nodes.reverse();
+            }
+            ring.segment_ways
+                .extend(std::iter::repeat_n(way_id, nodes.len() - 1));
+            ring.nodes.extend(nodes.into_iter().skip(1));
+            chain.push(way_id);
+        }
+        let mut distinct: Vec<u64> = ring.nodes.iter().map(|&(id, _)| id).collect();
+        distinct.sort();
+        distinct.dedup();
+        if distinct.len() < 3 {
+            return Err(AreaError::DegenerateRing(chain));
+        }
+        rings.push(ring);
+    }
+    Ok(rings)
+}
+
+/// Looks for crossing segments, sweeping over segments sorted by longitude
+fn find_intersection(rings: &[AssembledRing]) -> Option<Vec<u64>> {
+    let mut segments = vec![];
+    for ring in rings {
+        for (i, nodes) in ring.nodes.windows(2).enumerate() {
+            segments.push((nodes[0].1, nodes[1].1, ring.segment_ways[i]));
+        }
+    }
+    let min_lon = |&(a, b, _): &(Location, Location, u64)| a.lon.min(b.lon);
+    let max_lon = |&(a, b, _): &(Location, Location, u64)| a.lon.max(b.lon);
+    segments.sort_by(|a, b| min_lon(a).total_cmp(&min_lon(b)));
+    for (i, &(a, b, way_a)) in segments.iter().enumerate() {
+        let max = max_lon(&segments[i]);
+        for &(c, d, way_b) in segments[i + 1..]
+            .iter()
+            .take_while(|segment| min_lon(segment) <= max)
+        {
+            if segments_cross(a, b, c, d) {
+                let mut way_ids = vec![way_a, way_b];
+                way_ids.dedup();
+                return Some(way_ids);
+            }
+        }
+    }
+    None
+}
+
+/// Builds polygons from rings, nesting them by containment
+fn nest_rings(rings: &[AssembledRing]) -> MultiPolygon {
+    let depths: Vec<usize> = rings
+        .iter()
+        .enumerate()
+        .map(|(i, ring)| {
+            rings
+                .iter()
+                .enumerate()
+                .filter(|&(j, other)| {
+                    j != i && ring_contains(&other.locations(), ring.test_location(other))
+                })
+                .count()
+        })
+        .collect();
+
+    let mut polygons = vec![];
+    let mut outer_indices = HashMap::new();
+    for (i, ring) in rings.iter().enumerate() {
+        if depths[i].is_multiple_of(2) {
+            let mut outer = ring.locations();
+            if signed_area(&outer) < 0.0 {
+                outer.reverse();
+            }
+            outer_indices.insert(i, polygons.len());
+            polygons.push(Polygon {
+                outer,
+                inners: vec![],
+            });
+        }
+    }
+    for (i, ring) in rings.iter().enumerate() {
+        if !depths[i].is_multiple_of(2) {
+            let mut inner = ring.locations();
+            if signed_area(&inner) > 0.0 {
+                inner.reverse();
+            }
+            // Attach to the outer ring directly containing this one
+            let outer = (0..rings.len()).find(|&j| {
+                depths[j] + 1 == depths[i]
+                    && ring_contains(&rings[j].locations(), ring.test_location(&rings[j]))
+            });
+            if let Some(polygon) = outer.and_then(|j| outer_indices.get(&j)) {
+                polygons[*polygon].inners.push(inner);
+            }
+        }
+    }
+    MultiPolygon { polygons }
+}
+
+fn build_area(
+    source: AreaSource,
+    tags: Vec<(String, String)>,
+    ways: Vec<(u64, Result<WayNodes, AreaError>)>,
+) -> Result<Area, BrokenArea> {
+    let broken = |error| BrokenArea { source, error };
+    let ways = ways
+        .into_iter()
+        .map(|(way_id, nodes)| nodes.map(|nodes| (way_id, nodes)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(broken)?;
+    let rings = stitch_rings(ways).map_err(broken)?;
+    if let Some(way_ids) = find_intersection(&rings) {
+        return Err(broken(AreaError::SelfIntersection(way_ids)));
+    }
+    Ok(Area {
+        source,
+        tags,
+        geometry: nest_rings(&rings),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A way from `(id, lon, lat)` triples
+    fn way(way_id: u64, nodes: &[(u64, f64, f64)]) -> (u64, Result<WayNodes, AreaError>) {
+        (
+            way_id,
+            Ok(nodes
+                .iter()
+                .map(|&(id, lon, lat)| (id, Location::new(lat, lon)))
+                .collect()),
+        )
+    }
+
+    fn source() -> AreaSource {
+        AreaSource::Relation(1)
+    }
+
+    #[test]
+    fn test_stitch_and_nest() {
+        // Outer square from two halves, one of them reversed, with a hole
+        let area = build_area(
+            source(),
+            vec![],
+            vec![
+                way(10, &[(1, 0.0, 0.0), (2, 4.0, 0.0), (3, 4.0, 4.0)]),
+                way(11, &[(1, 0.0, 0.0), (4, 0.0, 4.0), (3, 4.0, 4.0)]),
+                way(
+                    12,
+                    &[
+                        (5, 1.0, 1.0),
+                        (6, 2.0, 1.0),
+                        (7, 2.0, 2.0),
+                        (8, 1.0, 2.0),
+                        (5, 1.0, 1.0),
+                    ],
+                ),
+            ],
+        )
+        .unwrap();
+        let polygons = &area.geometry.polygons;
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].outer.len(), 5);
+        assert!(signed_area(&polygons[0].outer) > 0.0);
+        assert_eq!(polygons[0].inners.len(), 1);
+        assert!(signed_area(&polygons[0].inners[0]) < 0.0);
+        assert!(area.geometry.contains(Location::new(3.0, 3.0)));
+        assert!(!area.geometry.contains(Location::new(1.5, 1.5)));
+    }
+
+    #[test]
+    fn test_island_in_hole() {
+        let square = |way_id, first_id, min: f64, max: f64| {
+            way(
+                way_id,
+                &[
+                    (first_id, min, min),
+                    (first_id + 1, max, min),
+                    (first_id + 2, max, max),
+                    (first_id + 3, min, max),
+                    (first_id, min, min),
+                ],
+            )
+        };
+        let area = build_area(
+            source(),
+            vec![],
+            vec![
+                square(1, 10, 2.0, 3.0),
+                square(2, 20, 0.0, 5.0),
+                square(3, 30, 1.0, 4.0),
+            ],
+        )
+        .unwrap();
+        let polygons = &area.geometry.polygons;
+        assert_eq!(polygons.len(), 2);
+        assert_eq!(polygons[0].inners.len(), 0);
+        assert_eq!(polygons[1].inners.len(), 1);
+        assert!(area.geometry.contains(Location::new(2.5, 2.5)));
+        assert!(!area.geometry.contains(Location::new(1.5, 1.5)));
+    }
+
+    #[test]
+    fn test_open_ring() {
+        let result = build_area(
+            source(),
+            vec![],
+            vec![
+                way(10, &[(1, 0.0, 0.0), (2, 4.0, 0.0), (3, 4.0, 4.0)]),
+                way(11, &[(3, 4.0, 4.0), (4, 0.0, 4.0)]),
+            ],
+        );
+        assert_eq!(result.unwrap_err().error, AreaError::OpenRing(vec![10, 11]));
+    }
+
+    #[test]
+    fn test_self_intersection() {
+        let result = build_area(
+            source(),
+            vec![],
+            vec![way(
+                10,
+                &[
+                    (1, 0.0, 0.0),
+                    (2, 2.0, 2.0),
+                    (3, 2.0, 0.0),
+                    (4, 0.0, 2.0),
+                    (1, 0.0, 0.0),
+                ],
+            )],
+        );
+        assert_eq!(
+            result.unwrap_err().error,
+            AreaError::SelfIntersection(vec![10])
+        );
+    }
+
+    #[test]
+    fn test_degenerate_ring() {
+        let result = build_area(
+            source(),
+            vec![],
+            vec![way(10, &[(1, 0.0, 0.0), (2, 2.0, 2.0), (1, 0.0, 0.0)])],
+        );
+        assert_eq!(
+            result.unwrap_err().error,
+            AreaError::DegenerateRing(vec![10])
+        );
+    }
+
+    #[test]
+    fn test_short_ways() {
+        let result = build_area(source(), vec![], vec![way(10, &[]), way(11, &[])]);
+        assert_eq!(
+            result.unwrap_err().error,
+            AreaError::DegenerateRing(vec![10])
+        );
+
+        let result = build_area(
+            source(),
+            vec![],
+            vec![
+                way(10, &[(1, 0.0, 0.0), (2, 4.0, 0.0), (3, 4.0, 4.0)]),
+                way(11, &[(3, 4.0, 4.0)]),
+                way(12, &[(3, 4.0, 4.0), (1, 0.0, 0.0)]),
+            ],
+        );
+        assert_eq!(
+            result.unwrap_err().error,
+            AreaError::DegenerateRing(vec![11])
+        );
+    }
+
+    #[test]
+    fn test_is_area_way() {
+        assert!(is_area_way([("building", "yes")]));
+        assert!(is_area_way([("highway", "pedestrian"), ("area", "yes")]));
+        assert!(!is_area_way([("building", "yes"), ("area", "no")]));
+        assert!(!is_area_way([("natural", "coastline")]));
+        assert!(!is_area_way([("highway", "residential")]));
+    }
+}
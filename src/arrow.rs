@@ -0,0 +1,401 @@
+//! Conversion of primitive blocks into Arrow `RecordBatch`es
+//!
+//! Every element kind has a fixed schema. The builders can be fed from many
+//! blocks before calling `finish()`, so the batch size is up to the caller.
+
+use std::sync::{Arc, LazyLock};
+
+pub use arrow_array;
+pub use arrow_schema;
+
+use arrow_array::builder::{
+    ArrayBuilder, BooleanBuilder, Float64Builder, Int64Builder, ListBuilder, MapBuilder,
+    MapFieldNames, StringBuilder, StructBuilder, TimestampMillisecondBuilder, UInt32Builder,
+    UInt64Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+
+use crate::info::Info;
+use crate::{Node, Primitive, PrimitiveBlock, Relation, RelationMemberType, Way};
+
+fn tags_field() -> Field {
+    let entries = Fields::from(vec![
+        Field::new("key", DataType::Utf8, false),
+        Field::new("value", DataType::Utf8, false),
+    ]);
+    Field::new(
+        "tags",
+        DataType::Map(
+            Arc::new(Field::new("entries", DataType::Struct(entries), false)),
+            false,
+        ),
+        false,
+    )
+}
+
+fn info_fields() -> Vec<Field> {
+    vec![
+        Field::new("version", DataType::UInt32, true),
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            true,
+        ),
+        Field::new("changeset", DataType::UInt64, true),
+        Field::new("uid", DataType::UInt32, true),
+        Field::new("user", DataType::Utf8, true),
+        Field::new("visible", DataType::Boolean, true),
+    ]
+}
+
+fn member_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("type", DataType::Utf8, false),
+        Field::new("ref", DataType::Int64, false),
+        Field::new("role", DataType::Utf8, false),
+    ])
+}
+
+static NODE_SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    let mut fields = vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("lat", DataType::Float64, false),
+        Field::new("lon", DataType::Float64, false),
+        tags_field(),
+    ];
+    fields.extend(info_fields());
+    Arc::new(Schema::new(fields))
+});
+
+static WAY_SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    let mut fields = vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new(
+            "refs",
+            DataType::List(Arc::new(Field::new("item", DataType::Int64, false))),
+            false,
+        ),
+        tags_field(),
+    ];
+    fields.extend(info_fields());
+    Arc::new(Schema::new(fields))
+});
+
+static RELATION_SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    let mut fields = vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new(
+            "members",
+            DataType::List(Arc::new(Field::new(
+                "item",
+                DataType::Struct(member_fields()),
+                false,
+            ))),
+            false,
+        ),
+        tags_field(),
+    ];
+    fields.extend(info_fields());
+    Arc::new(Schema::new(fields))
+});
+
+/// Schema of the batches produced by `NodesBuilder`
+pub fn node_schema() -> SchemaRef {
+    NODE_SCHEMA.clone()
+}
+
+/// Schema of the batches produced by `WaysBuilder`
+pub fn way_schema() -> SchemaRef {
+    WAY_SCHEMA.clone()
+}
+
+/// Schema of the batches produced by `RelationsBuilder`
+pub fn relation_schema() -> SchemaRef {
+    RELATION_SCHEMA.clone()
+}
+
+fn new_tags_builder() -> MapBuilder<StringBuilder, StringBuilder> {
+    let field_names = MapFieldNames {
+        entry: "entries".to_string(),
+        key: "key".to_string(),
+        value: "value".to_string(),
+    };
+    MapBuilder::new(
+        Some(field_names),
+        StringBuilder::new(),
+        StringBuilder::new(),
+    )
+    .with_values_field(Field::new("value", DataType::Utf8, false))
+}
+
+fn append_tags<'a, I: Iterator<Item = (&'a str, &'a str)>>(
+    builder: &mut MapBuilder<StringBuilder, StringBuilder>,
+    tags: I,
+) -> Result<(), ArrowError> {
+    for (key, value) in tags {
+        builder.keys().append_value(key);
+        builder.values().append_value(value);
+    }
+    builder.append(true)
+}
+
+struct InfoBuilder {
+    versions: UInt32Builder,
+    timestamps: TimestampMillisecondBuilder,
+    changesets: UInt64Builder,
+    uids: UInt32Builder,
+    users: StringBuilder,
+    visibles: BooleanBuilder,
+}
+
+impl InfoBuilder {
+    fn new() -> Self {
+        InfoBuilder {
+            versions: UInt32Builder::new(),
+            timestamps: TimestampMillisecondBuilder::new().with_timezone("UTC"),
+            changesets: UInt64Builder::new(),
+            uids: UInt32Builder::new(),
+            users: StringBuilder::new(),
+            visibles: BooleanBuilder::new(),
+        }
+    }
+
+    fn append(&mut self, info: Option<&Info>) {
+        self.versions
+            .append_option(info.and_then(|info| info.version));
+        self.timestamps.append_option(
+            info.and_then(|info| info.timestamp)
+                .map(|timestamp| timestamp.millis() as i64),
+        );
+        self.changesets
+            .append_option(info.and_then(|info| info.changeset));
+        self.uids.append_option(info.and_then(|info| info.uid));
+        self.users.append_option(info.and_then(|info| info.user));
+        self.visibles
+            .append_option(info.and_then(|info| info.visible));
+    }
+
+    fn finish(&mut self, columns: &mut Vec<ArrayRef>) {
+        columns.push(Arc::new(self.versions.finish()));
+        columns.push(Arc::new(self.timestamps.finish()));
+        columns.push(Arc::new(self.changesets.finish()));
+        columns.push(Arc::new(self.uids.finish()));
+        columns.push(Arc::new(self.users.finish()));
+        columns.push(Arc::new(self.visibles.finish()));
+    }
+}
+
+/// Accumulates nodes into a `RecordBatch` with `node_schema()`
+pub struct NodesBuilder {
+    ids: Int64Builder,
+    lats: Float64Builder,
+    lons: Float64Builder,
+    tags: MapBuilder<StringBuilder, StringBuilder>,
+    infos: InfoBuilder,
+}
+
+impl Default for NodesBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodesBuilder {
+    pub fn new() -> Self {
+        NodesBuilder {
+            ids: Int64Builder::new(),
+            lats: Float64Builder::new(),
+            lons: Float64Builder::new(),
+            tags: new_tags_builder(),
+            infos: InfoBuilder::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn append(&mut self, node: &Node) -> Result<(), ArrowError> {
+        self.ids.append_value(node.id as i64);
+        self.lats.append_value(node.lat);
+        self.lons.append_value(node.lon);
+        append_tags(&mut self.tags, node.tags.iter().copied())?;
+        self.infos.append(node.info.as_ref());
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.ids.finish()),
+            Arc::new(self.lats.finish()),
+            Arc::new(self.lons.finish()),
+            Arc::new(self.tags.finish()),
+        ];
+        self.infos.finish(&mut columns);
+        RecordBatch::try_new(node_schema(), columns)
+    }
+}
+
+/// Accumulates ways into a `RecordBatch` with `way_schema()`
+pub struct WaysBuilder {
+    ids: Int64Builder,
+    refs: ListBuilder<Int64Builder>,
+    tags: MapBuilder<StringBuilder, StringBuilder>,
+    infos: InfoBuilder,
+}
+
+impl Default for WaysBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaysBuilder {
+    pub fn new() -> Self {
+        WaysBuilder {
+            ids: Int64Builder::new(),
+            refs: ListBuilder::new(Int64Builder::new()).with_field(Field::new(
+                "item",
+                DataType::Int64,
+                false,
+            )),
+            tags: new_tags_builder(),
+            infos: InfoBuilder::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn append(&mut self, way: &Way) -> Result<(), ArrowError> {
+        self.ids.append_value(way.id as i64);
+        for node_ref in way.refs() {
+            self.refs.values().append_value(node_ref);
+        }
+        self.refs.append(true);
+        append_tags(&mut self.tags, way.tags())?;
+        self.infos.append(way.info.as_ref());
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.ids.finish()),
+            Arc::new(self.refs.finish()),
+            Arc::new(self.tags.finish()),
+        ];
+        self.infos.finish(&mut columns);
+        RecordBatch::try_new(way_schema(), columns)
+    }
+}
+
+/// Accumulates relations into a `RecordBatch` with `relation_schema()`
+pub struct RelationsBuilder {
+    ids: Int64Builder,
+    members: ListBuilder<StructBuilder>,
+    tags: MapBuilder<StringBuilder, StringBuilder>,
+    infos: InfoBuilder,
+}
+
+impl Default for RelationsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RelationsBuilder {
+    pub fn new() -> Self {
+        let fields = member_fields();
+        RelationsBuilder {
+            ids: Int64Builder::new(),
+            members: ListBuilder::new(StructBuilder::from_fields(fields.clone(), 0))
+                .with_field(Field::new("item", DataType::Struct(fields), false)),
+            tags: new_tags_builder(),
+            infos: InfoBuilder::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn append(&mut self, relation: &Relation) -> Result<(), ArrowError> {
+        self.ids.append_value(relation.id as i64);
+        let members = self.members.values();
+        for (role, id, member_type) in relation.members() {
+            let member_type = match member_type {
+                RelationMemberType::Node => "node",
+                RelationMemberType::Way => "way",
+                RelationMemberType::Relation => "relation",
+            };
+            members
+                .field_builder::<StringBuilder>(0)
+                .unwrap()
+                .append_value(member_type);
+            members
+                .field_builder::<Int64Builder>(1)
+                .unwrap()
+                .append_value(id as i64);
+            members
+                .field_builder::<StringBuilder>(2)
+                .unwrap()
+                .append_value(role);
+            members.append(true);
+        }
+        self.members.append(true);
+        append_tags(&mut self.tags, relation.tags())?;
+        self.infos.append(relation.info.as_ref());
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.ids.finish()),
+            Arc::new(self.members.finish()),
+            Arc::new(self.tags.finish()),
+        ];
+        self.infos.finish(&mut columns);
+        RecordBatch::try_new(relation_schema(), columns)
+    }
+}
+
+/// The elements of one `PrimitiveBlock`, by kind
+pub struct RecordBatches {
+    pub nodes: RecordBatch,
+    pub ways: RecordBatch,
+    pub relations: RecordBatch,
+}
+
+impl<'a> PrimitiveBlock<'a> {
+    pub fn to_record_batches(&'a self) -> Result<RecordBatches, ArrowError> {
+        let mut nodes = NodesBuilder::new();
+        let mut ways = WaysBuilder::new();
+        let mut relations = RelationsBuilder::new();
+        for primitive in self.primitives() {
+            match primitive {
+                Primitive::Node(node) => nodes.append(&node)?,
+                Primitive::Way(way) => ways.append(&way)?,
+                Primitive::Relation(relation) => relations.append(&relation)?,
+            }
+        }
+        Ok(RecordBatches {
+            nodes: nodes.finish()?,
+            ways: ways.finish()?,
+            relations: relations.finish()?,
+        })
+    }
+}
@@ -0,0 +1,34 @@
+extern crate osm_pbf_iter;
+
+use std::env::args;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::process::exit;
+
+use osm_pbf_iter::apply_changes::apply_changes;
+use osm_pbf_iter::xml::OscReader;
+use osm_pbf_iter::*;
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    if args.len() < 3 {
+        eprintln!("Usage: apply_changes <input.osm.pbf> <output.osm.pbf> <changes.osc[.gz]>...");
+        exit(1);
+    }
+
+    let mut changes = vec![];
+    for path in &args[2..] {
+        for change in OscReader::open(path).unwrap() {
+            changes.push(change.unwrap());
+        }
+    }
+
+    let f = File::open(&args[0]).unwrap();
+    let mut reader = BlobReader::new(BufReader::new(f));
+    let mut header = reader.header().cloned().unwrap_or_default();
+    header.writingprogram = Some("osm_pbf_iter apply_changes".to_string());
+    let out = BufWriter::new(File::create(&args[1]).unwrap());
+    let mut writer = PbfWriter::new(out, &header).unwrap();
+    apply_changes(&mut reader, changes, &mut writer).unwrap();
+    writer.finish().unwrap();
+}
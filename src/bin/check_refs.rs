@@ -0,0 +1,62 @@
+extern crate osm_pbf_iter;
+
+use std::env::args;
+use std::fs::File;
+use std::io::BufReader;
+use std::process::exit;
+
+use osm_pbf_iter::check_refs::CheckRefs;
+use osm_pbf_iter::*;
+
+fn type_prefix(member_type: &RelationMemberType) -> char {
+    match member_type {
+        RelationMemberType::Node => 'n',
+        RelationMemberType::Way => 'w',
+        RelationMemberType::Relation => 'r',
+    }
+}
+
+fn main() {
+    let (flags, files): (Vec<String>, Vec<String>) =
+        args().skip(1).partition(|arg| arg.starts_with("--"));
+    let show_ids = flags.iter().any(|flag| flag == "--show-ids");
+    let [file] = &files[..] else {
+        eprintln!("Usage: check_refs [--show-ids] <input.osm.pbf>");
+        exit(1);
+    };
+
+    let f = File::open(file).unwrap();
+    let mut check_refs = CheckRefs::new(show_ids);
+    apply(&mut BlobReader::new(BufReader::new(f)), &mut check_refs);
+    let report = check_refs.finish();
+
+    for missing in &report.missing {
+        println!(
+            "{}{} in {}{}",
+            type_prefix(&missing.member_type),
+            missing.id,
+            type_prefix(&missing.referrer_type),
+            missing.referrer_id
+        );
+    }
+    eprintln!(
+        "There are {} nodes, {} ways, and {} relations in this file.",
+        report.nodes, report.ways, report.relations
+    );
+    eprintln!("Nodes in ways missing: {}", report.missing_way_nodes);
+    eprintln!(
+        "Nodes in relations missing: {}",
+        report.missing_relation_nodes
+    );
+    eprintln!(
+        "Ways in relations missing: {}",
+        report.missing_relation_ways
+    );
+    eprintln!(
+        "Relations in relations missing: {}",
+        report.missing_relation_relations
+    );
+    if !report.is_complete() {
+        exit(1);
+    }
+}
@@ -1,65 +1,79 @@
 use std::env::args;
 use std::fs::File;
 use std::io::{BufReader, Seek};
-use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
 use std::thread;
 use std::time::Instant;
 
 use osm_pbf_iter::*;
 
-type Stats = [u64; 3];
+#[derive(Default)]
+struct Stats([u64; 3]);
 
-fn blobs_worker(req_rx: Receiver<Blob>, res_tx: SyncSender<Stats>) {
-    let mut stats = [0; 3];
+impl Handler for Stats {
+    fn node(&mut self, _node: &Node) {
+        self.0[0] += 1;
+    }
 
-    while let Ok(blob) = req_rx.recv() {
-        let data = blob.into_data();
-        let primitive_block = PrimitiveBlock::parse(&data);
-        for primitive in primitive_block.primitives() {
-            match primitive {
-                Primitive::Node(_) => stats[0] += 1,
-                Primitive::Way(_) => stats[1] += 1,
-                Primitive::Relation(_) => stats[2] += 1,
-            }
-        }
+    fn way(&mut self, _way: &Way) {
+        self.0[1] += 1;
+    }
+
+    fn relation(&mut self, _relation: &Relation) {
+        self.0[2] += 1;
+    }
+}
+
+fn format_kind(name: &str, summary: &KindSummary) -> String {
+    match &summary.ids {
+        Some(ids) => format!(
+            "{} {} ({}..={})",
+            summary.count,
+            name,
+            ids.start(),
+            ids.end()
+        ),
+        None => format!("0 {}", name),
     }
+}
 
-    res_tx.send(stats).unwrap();
+fn list_blocks(arg: &str) {
+    println!("Open {}", arg);
+    let f = File::open(arg).unwrap();
+    for (i, blob) in BlobReader::new(BufReader::new(f)).enumerate() {
+        let data = blob.into_data();
+        let summary = PrimitiveBlock::parse(&data).summary();
+        println!(
+            "block {}: {}, {}, {}",
+            i,
+            format_kind("nodes", &summary.nodes),
+            format_kind("ways", &summary.ways),
+            format_kind("relations", &summary.relations)
+        );
+    }
 }
 
 fn main() {
     let cpus: usize = thread::available_parallelism().unwrap().into();
 
-    for arg in args().skip(1) {
-        let mut workers = Vec::with_capacity(cpus);
-        for _ in 0..cpus {
-            let (req_tx, req_rx) = sync_channel(2);
-            let (res_tx, res_rx) = sync_channel(0);
-            workers.push((req_tx, res_rx));
-            thread::spawn(move || {
-                blobs_worker(req_rx, res_tx);
-            });
+    let (flags, files): (Vec<String>, Vec<String>) =
+        args().skip(1).partition(|arg| arg.starts_with("--"));
+    if flags.iter().any(|flag| flag == "--blocks") {
+        for arg in files {
+            list_blocks(&arg);
         }
+        return;
+    }
 
+    for arg in files {
         println!("Open {}", arg);
         let f = File::open(&arg).unwrap();
         let mut reader = BlobReader::new(BufReader::new(f));
         let start = Instant::now();
 
-        let mut w = 0;
-        for blob in &mut reader {
-            let req_tx = &workers[w].0;
-            w = (w + 1) % cpus;
-
-            req_tx.send(blob).unwrap();
-        }
-
         let mut stats = [0; 3];
-        for (req_tx, res_rx) in workers.into_iter() {
-            drop(req_tx);
-            let worker_stats = res_rx.recv().unwrap();
-            for i in 0..worker_stats.len() {
-                stats[i] += worker_stats[i];
+        for worker_stats in apply_parallel(&mut reader, cpus, Stats::default) {
+            for (total, count) in stats.iter_mut().zip(worker_stats.0) {
+                *total += count;
             }
         }
 
@@ -2,41 +2,95 @@ extern crate osm_pbf_iter;
 
 use std::env::args;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter, stdout};
+use std::process::exit;
 
+use osm_pbf_iter::opl::write_opl;
+use osm_pbf_iter::xml::write_xml;
 use osm_pbf_iter::*;
 
-fn main() {
-    for arg in args().skip(1) {
-        let mut stats = [0; 3];
-
-        println!("Open {}", arg);
-        let f = File::open(&arg).unwrap();
-        for blob in BlobReader::new(BufReader::new(f)) {
-            let data = blob.into_data();
-            println!("blob: {} KB", data.len() / 1024);
-            let primitive_block = PrimitiveBlock::parse(&data);
-            for primitive in primitive_block.primitives() {
-                match primitive {
-                    Primitive::Node(node) => {
-                        stats[0] += 1;
-                        println!("{:?}", node);
-                    }
-                    Primitive::Way(way) => {
-                        stats[1] += 1;
-                        println!("{:?}", way);
-                    }
-                    Primitive::Relation(relation) => {
-                        stats[2] += 1;
-                        println!("{:?}", relation);
-                    }
+enum Format {
+    Debug,
+    Opl,
+    Xml,
+}
+
+fn dump_debug(arg: &str) {
+    let mut stats = [0; 3];
+
+    println!("Open {}", arg);
+    let f = File::open(arg).unwrap();
+    for blob in BlobReader::new(BufReader::new(f)) {
+        let data = blob.into_data();
+        println!("blob: {} KB", data.len() / 1024);
+        let primitive_block = PrimitiveBlock::parse(&data);
+        for primitive in primitive_block.primitives() {
+            match primitive {
+                Primitive::Node(node) => {
+                    stats[0] += 1;
+                    println!("{:?}", node);
+                }
+                Primitive::Way(way) => {
+                    stats[1] += 1;
+                    println!("{:?}", way);
+                }
+                Primitive::Relation(relation) => {
+                    stats[2] += 1;
+                    println!("{:?}", relation);
                 }
             }
         }
+    }
 
-        println!(
-            "{} - {} nodes, {} ways, {} relations",
-            arg, stats[0], stats[1], stats[2]
-        );
+    println!(
+        "{} - {} nodes, {} ways, {} relations",
+        arg, stats[0], stats[1], stats[2]
+    );
+}
+
+fn dump_opl(arg: &str) {
+    let f = File::open(arg).unwrap();
+    let mut reader = BlobReader::new(BufReader::new(f));
+    let out = BufWriter::new(stdout().lock());
+    write_opl(&mut reader, out).unwrap();
+}
+
+fn dump_xml(arg: &str) {
+    let f = File::open(arg).unwrap();
+    let mut reader = BlobReader::new(BufReader::new(f));
+    let out = BufWriter::new(stdout().lock());
+    write_xml(&mut reader, out, "osm_pbf_iter").unwrap();
+}
+
+fn main() {
+    let mut format = Format::Debug;
+    let mut files = vec![];
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--format") {
+            Some("") => args.next(),
+            Some(value) => value.strip_prefix('=').map(str::to_string),
+            None => {
+                files.push(arg);
+                continue;
+            }
+        };
+        format = match value.as_deref() {
+            Some("debug") => Format::Debug,
+            Some("opl") => Format::Opl,
+            Some("xml") => Format::Xml,
+            _ => {
+                eprintln!("Usage: dump [--format debug|opl|xml] <file.osm.pbf>...");
+                exit(1);
+            }
+        };
+    }
+
+    for arg in files {
+        match format {
+            Format::Debug => dump_debug(&arg),
+            Format::Opl => dump_opl(&arg),
+            Format::Xml => dump_xml(&arg),
+        }
     }
 }
@@ -0,0 +1,86 @@
+extern crate osm_pbf_iter;
+
+use std::env::args;
+use std::fs::{File, read_to_string};
+use std::io::{BufReader, BufWriter};
+use std::process::exit;
+
+use osm_pbf_iter::extract::{Region, Strategy, extract};
+use osm_pbf_iter::geojson::read_multipolygon;
+use osm_pbf_iter::poly::read_poly;
+use osm_pbf_iter::*;
+
+const USAGE: &str = "Usage: extract <input.osm.pbf> <output.osm.pbf> \
+    (--bbox <left,bottom,right,top> | --polygon <file.geojson|file.poly>) \
+    [--strategy simple|complete_ways|smart]";
+
+fn parse_bbox(s: &str) -> Option<HeaderBBox> {
+    let coordinates = s
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    match coordinates[..] {
+        [left, bottom, right, top] if left <= right && bottom <= top => Some(HeaderBBox {
+            left,
+            right,
+            top,
+            bottom,
+        }),
+        _ => None,
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    let mut files = vec![];
+    let mut region = None;
+    let mut strategy = Strategy::CompleteWays;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bbox" => {
+                let bbox = args.next().and_then(|s| parse_bbox(s));
+                region = Some(Region::BBox(bbox.unwrap_or_else(|| usage())));
+            }
+            "--polygon" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                let contents = read_to_string(path).unwrap();
+                let multipolygon = if path.ends_with(".poly") {
+                    read_poly(&contents)
+                } else {
+                    read_multipolygon(&contents)
+                };
+                let Some(multipolygon) = multipolygon else {
+                    eprintln!("No polygon in {}", path);
+                    exit(1);
+                };
+                region = Some(Region::MultiPolygon(multipolygon));
+            }
+            "--strategy" => {
+                strategy = args
+                    .next()
+                    .and_then(|s| Strategy::parse(s))
+                    .unwrap_or_else(|| usage());
+            }
+            _ => files.push(arg),
+        }
+    }
+    let (Some(region), [input, output]) = (region, &files[..]) else {
+        usage();
+    };
+
+    let mut reader = BlobReader::new(BufReader::new(File::open(input).unwrap()));
+    let mut header = reader.header().cloned().unwrap_or_default();
+    header.bbox = Some(region.bbox());
+    header.writingprogram = Some("osm_pbf_iter extract".to_string());
+    let out = BufWriter::new(File::create(output).unwrap());
+    let mut writer = PbfWriter::new(out, &header).unwrap();
+    let open = || Ok(BlobReader::new(BufReader::new(File::open(input)?)));
+    extract(open, region, strategy, &mut writer).unwrap();
+    writer.finish().unwrap();
+}
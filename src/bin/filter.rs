@@ -0,0 +1,55 @@
+extern crate osm_pbf_iter;
+
+use std::env::args;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::process::exit;
+
+use osm_pbf_iter::owned::Element;
+use osm_pbf_iter::*;
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    if args.len() < 3 {
+        eprintln!("Usage: filter <input.osm.pbf> <output.osm.pbf> <expression>...");
+        eprintln!("Expressions: [nwr/][!]key[=value[,value...]], with * wildcards");
+        exit(1);
+    }
+    let filter = match TagsFilter::new(args[2..].iter().map(String::as_str)) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+
+    let f = File::open(&args[0]).unwrap();
+    let mut reader = BlobReader::new(BufReader::new(f));
+    let mut header = reader.header().cloned().unwrap_or_default();
+    header.writingprogram = Some("osm_pbf_iter filter".to_string());
+    let out = BufWriter::new(File::create(&args[1]).unwrap());
+    let mut writer = PbfWriter::new(out, &header).unwrap();
+    for blob in reader {
+        let data = blob.into_data();
+        let primitive_block = PrimitiveBlock::parse(&data);
+        let block_filter = filter.for_block(&primitive_block.stringtable);
+        let member_types = [
+            RelationMemberType::Node,
+            RelationMemberType::Way,
+            RelationMemberType::Relation,
+        ];
+        // Skip blocks that lack all wanted keys
+        if !member_types
+            .into_iter()
+            .any(|member_type| block_filter.may_match(member_type))
+        {
+            continue;
+        }
+        for primitive in primitive_block.primitives() {
+            if block_filter.matches_primitive(&primitive) {
+                writer.write_element(Element::from(&primitive)).unwrap();
+            }
+        }
+    }
+    writer.finish().unwrap();
+}
@@ -0,0 +1,56 @@
+extern crate osm_pbf_iter;
+
+use std::env::args;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::process::exit;
+
+use osm_pbf_iter::getid::{GetIdOptions, Ids, get_ids, parse_id};
+use osm_pbf_iter::*;
+
+fn usage() -> ! {
+    eprintln!("Usage: getid [-r] [--add-parents] <input.osm.pbf> <output.osm.pbf> <n|w|r><id>...");
+    exit(1);
+}
+
+fn main() {
+    let mut options = GetIdOptions::default();
+    let mut files = vec![];
+    let mut ids = Ids::default();
+    for arg in args().skip(1) {
+        match arg.as_str() {
+            "-r" => options.recursive = true,
+            "--add-parents" => options.parents = true,
+            _ if files.len() < 2 => files.push(arg),
+            _ => {
+                let (member_type, id) = parse_id(&arg).unwrap_or_else(|| usage());
+                ids.insert(member_type, id);
+            }
+        }
+    }
+    if ids.is_empty() {
+        usage();
+    }
+
+    let mut reader = BlobReader::new(BufReader::new(File::open(&files[0]).unwrap()));
+    let mut header = reader.header().cloned().unwrap_or_default();
+    header.writingprogram = Some("osm_pbf_iter getid".to_string());
+    let out = BufWriter::new(File::create(&files[1]).unwrap());
+    let mut writer = PbfWriter::new(out, &header).unwrap();
+    let open = || Ok(BlobReader::new(BufReader::new(File::open(&files[0])?)));
+    let missing = get_ids(open, ids, options, &mut writer).unwrap();
+    writer.finish().unwrap();
+
+    for (prefix, ids) in [
+        ("n", &missing.nodes),
+        ("w", &missing.ways),
+        ("r", &missing.relations),
+    ] {
+        for id in ids.iter() {
+            eprintln!("Not found: {}{}", prefix, id);
+        }
+    }
+    if !missing.is_empty() {
+        exit(2);
+    }
+}
@@ -0,0 +1,38 @@
+extern crate osm_pbf_iter;
+
+use std::env::args;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::process::exit;
+
+use osm_pbf_iter::history::{HISTORICAL_INFORMATION, elements, time_filter};
+use osm_pbf_iter::*;
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    let timestamp = match &args[..] {
+        [_, _, timestamp] => Timestamp::parse(timestamp),
+        _ => None,
+    };
+    let Some(timestamp) = timestamp else {
+        eprintln!("Usage: time_filter <input.osh.pbf> <output.osm.pbf> <YYYY-MM-DDTHH:MM:SSZ>");
+        exit(1);
+    };
+
+    let f = File::open(&args[0]).unwrap();
+    let mut reader = BlobReader::new(BufReader::new(f));
+    let mut header = reader.header().cloned().unwrap_or_default();
+    header
+        .required_features
+        .retain(|feature| feature != HISTORICAL_INFORMATION);
+    header.writingprogram = Some("osm_pbf_iter time_filter".to_string());
+    let out = BufWriter::new(File::create(&args[1]).unwrap());
+    let mut writer = PbfWriter::new(out, &header).unwrap();
+    for mut element in time_filter(elements(&mut reader), timestamp) {
+        if let Some(info) = element.info_mut() {
+            info.visible = None;
+        }
+        writer.write_element(element).unwrap();
+    }
+    writer.finish().unwrap();
+}
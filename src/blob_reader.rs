@@ -2,15 +2,47 @@ use std::io::Read;
 use std::str::from_utf8;
 
 use crate::blob::Blob;
+use crate::parse::HeaderBlock;
 use protobuf_iter::*;
 
+/// A blob together with the type given in its `BlobHeader`
+pub enum FileBlock {
+    /// `OSMHeader`, to be parsed with `HeaderBlock::parse()`
+    Header(Blob),
+    /// `OSMData`, to be parsed with `PrimitiveBlock::parse()`
+    Data(Blob),
+}
+
 pub struct BlobReader<R> {
     read: R,
+    header: Option<HeaderBlock>,
+    pending: Option<Blob>,
 }
 
 impl<R: Read> BlobReader<R> {
     pub fn new(r: R) -> Self {
-        BlobReader { read: r }
+        BlobReader {
+            read: r,
+            header: None,
+            pending: None,
+        }
+    }
+
+    /// Returns the file header, reading ahead if it has not been seen yet
+    ///
+    /// A data blob consumed while looking for the header is not lost but
+    /// returned by the next call to `next()`.
+    pub fn header(&mut self) -> Option<&HeaderBlock> {
+        if self.header.is_none() && self.pending.is_none() {
+            match Self::read_file_block(&mut self.read) {
+                Some(FileBlock::Header(blob)) => {
+                    self.header = Some(HeaderBlock::parse(&blob.into_data()))
+                }
+                Some(FileBlock::Data(blob)) => self.pending = Some(blob),
+                None => (),
+            }
+        }
+        self.header.as_ref()
     }
 
     pub fn into_inner(self) -> R {
@@ -22,6 +54,14 @@ impl<R: Read> BlobReader<R> {
     }
 
     pub fn read_blob(read: &mut R) -> Option<Blob> {
+        loop {
+            if let FileBlock::Data(blob) = Self::read_file_block(read)? {
+                return Some(blob);
+            }
+        }
+    }
+
+    pub fn read_file_block(read: &mut R) -> Option<FileBlock> {
         let mut len_buf = [0; 4];
         match read.read(&mut len_buf) {
             Ok(4) => {
@@ -30,17 +70,13 @@ impl<R: Read> BlobReader<R> {
                 let blob_header = parse_blob_header(&header_buf)?;
                 let datasize = blob_header.datasize as usize;
                 let blob_buf = Self::read_exact(read, datasize).ok()?;
-                if !blob_header.is_osm_data {
+                match parse_blob(&blob_buf) {
+                    Some(blob) if blob_header.is_osm_data => Some(FileBlock::Data(blob)),
+                    Some(blob) => Some(FileBlock::Header(blob)),
+                    None =>
                     // retry next
-                    Self::read_blob(read)
-                } else {
-                    match parse_blob(&blob_buf) {
-                        Some(blob) => Some(blob),
-                        None =>
-                        // retry next
-                        {
-                            Self::read_blob(read)
-                        }
+                    {
+                        Self::read_file_block(read)
                     }
                 }
             }
@@ -64,7 +100,17 @@ impl<R: Read> Iterator for BlobReader<R> {
     type Item = Blob;
 
     fn next(&mut self) -> Option<Self::Item> {
-        Self::read_blob(&mut self.read)
+        if let Some(blob) = self.pending.take() {
+            return Some(blob);
+        }
+        loop {
+            match Self::read_file_block(&mut self.read)? {
+                FileBlock::Header(blob) => {
+                    self.header = Some(HeaderBlock::parse(&blob.into_data()));
+                }
+                FileBlock::Data(blob) => return Some(blob),
+            }
+        }
     }
 }
 
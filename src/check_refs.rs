@@ -0,0 +1,189 @@
+//! Referential integrity of a file, like `osmium check-refs`
+//!
+//! Expects nodes before ways before relations, as in files sorted by type
+//! then ID. Relations may reference relations anywhere in the file.
+
+use crate::id_set::IdSet;
+use crate::{Handler, Node, Relation, RelationMemberType, Way};
+
+/// An element referenced by a way or relation, but absent from the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingRef {
+    pub member_type: RelationMemberType,
+    pub id: u64,
+    /// The way or relation referencing it
+    pub referrer_type: RelationMemberType,
+    pub referrer_id: u64,
+}
+
+/// Counts of the elements of a file, and of the references to missing ones
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RefsReport {
+    pub nodes: u64,
+    pub ways: u64,
+    pub relations: u64,
+    pub missing_way_nodes: u64,
+    pub missing_relation_nodes: u64,
+    pub missing_relation_ways: u64,
+    pub missing_relation_relations: u64,
+    /// Every missing reference, if listing was requested
+    pub missing: Vec<MissingRef>,
+}
+
+impl RefsReport {
+    pub fn is_complete(&self) -> bool {
+        self.missing_way_nodes == 0
+            && self.missing_relation_nodes == 0
+            && self.missing_relation_ways == 0
+            && self.missing_relation_relations == 0
+    }
+}
+
+/// Collects the IDs seen, and checks references against them
+#[derive(Default)]
+pub struct CheckRefs {
+    list: bool,
+    nodes: IdSet,
+    ways: IdSet,
+    relations: IdSet,
+    /// Members of relation type, checked once all relations are known
+    relation_members: Vec<(u64, u64)>,
+    report: RefsReport,
+}
+
+impl CheckRefs {
+    /// With `list`, the report includes every missing reference
+    pub fn new(list: bool) -> Self {
+        CheckRefs {
+            list,
+            nodes: IdSet::dense(),
+            ways: IdSet::dense(),
+            ..Self::default()
+        }
+    }
+
+    pub fn finish(mut self) -> RefsReport {
+        for (id, referrer_id) in std::mem::take(&mut self.relation_members) {
+            if !self.relations.contains(id) {
+                self.report.missing_relation_relations += 1;
+                self.add_missing(
+                    RelationMemberType::Relation,
+                    id,
+                    RelationMemberType::Relation,
+                    referrer_id,
+                );
+            }
+        }
+        self.report
+    }
+
+    fn add_missing(
+        &mut self,
+        member_type: RelationMemberType,
+        id: u64,
+        referrer_type: RelationMemberType,
+        referrer_id: u64,
+    ) {
+        if self.list {
+            self.report.missing.push(MissingRef {
+                member_type,
+                id,
+                referrer_type,
+                referrer_id,
+            });
+        }
+    }
+}
+
+impl Handler for CheckRefs {
+    fn node(&mut self, node: &Node) {
+        self.report.nodes += 1;
+        self.nodes.insert(node.id);
+    }
+
+    fn way(&mut self, way: &Way) {
+        self.report.ways += 1;
+        self.ways.insert(way.id);
+        for id in way.refs() {
+            let id = id as u64;
+            if !self.nodes.contains(id) {
+                self.report.missing_way_nodes += 1;
+                self.add_missing(
+                    RelationMemberType::Node,
+                    id,
+                    RelationMemberType::Way,
+                    way.id,
+                );
+            }
+        }
+    }
+
+    fn relation(&mut self, relation: &Relation) {
+        self.report.relations += 1;
+        self.relations.insert(relation.id);
+        for (_, id, member_type) in relation.members() {
+            let missing = match member_type {
+                RelationMemberType::Node if !self.nodes.contains(id) => {
+                    self.report.missing_relation_nodes += 1;
+                    true
+                }
+                RelationMemberType::Way if !self.ways.contains(id) => {
+                    self.report.missing_relation_ways += 1;
+                    true
+                }
+                RelationMemberType::Relation => {
+                    self.relation_members.push((id, relation.id));
+                    false
+                }
+                _ => false,
+            };
+            if missing {
+                self.add_missing(member_type, id, RelationMemberType::Relation, relation.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opl::opl_to_pbf;
+    use crate::{BlobReader, apply};
+
+    #[test]
+    fn test_check_refs() {
+        let opl = "n1 x0 y0
+n2 x1 y0
+w10 Nn1,n2
+w11 Nn2,n3,n4
+r20 Mw10@,n5@,r21@,r22@
+r21 Mw12@
+";
+        let out = opl_to_pbf(opl);
+
+        let mut check_refs = CheckRefs::new(true);
+        apply(&mut BlobReader::new(&out[..]), &mut check_refs);
+        let report = check_refs.finish();
+        assert!(!report.is_complete());
+        assert_eq!((report.nodes, report.ways, report.relations), (2, 2, 2));
+        assert_eq!(report.missing_way_nodes, 2);
+        assert_eq!(report.missing_relation_nodes, 1);
+        assert_eq!(report.missing_relation_ways, 1);
+        assert_eq!(report.missing_relation_relations, 1);
+        assert_eq!(report.missing.len(), 5);
+        assert_eq!(
+            report.missing[0],
+            MissingRef {
+                member_type: RelationMemberType::Node,
+                id: 3,
+                referrer_type: RelationMemberType::Way,
+                referrer_id: 11,
+            }
+        );
+        assert_eq!(report.missing[4].id, 22);
+
+        let mut check_refs = CheckRefs::new(false);
+        apply(&mut BlobReader::new(&out[..]), &mut check_refs);
+        assert!(check_refs.finish().missing.is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+//! Cutting the elements within a bounding box or polygon out of a file, like
+//! `osmium extract`
+//!
+//! Nodes are selected by location, ways by their nodes, and relations by
+//! their members. Strategies differ in how much is added to keep the result
+//! referentially complete:
+//!
+//! - `Simple`: nothing; ways may reference nodes outside of the extract
+//! - `CompleteWays`: all nodes of the selected ways
+//! - `Smart`: also all member ways of selected multipolygon relations, with
+//!   their nodes
+//!
+//! Relations that have selected relations as members are selected as well.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use crate::geometry::MultiPolygon;
+use crate::id_set::IdSet;
+use crate::owned::Element;
+use crate::{BlobReader, HeaderBBox, Location, PbfWriter, Primitive, PrimitiveBlock};
+use crate::{RelationMemberType, Way};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Simple,
+    CompleteWays,
+    Smart,
+}
+
+impl Strategy {
+    /// Parses the names used by osmium, `simple`, `complete_ways` and `smart`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "simple" => Some(Strategy::Simple),
+            "complete_ways" => Some(Strategy::CompleteWays),
+            "smart" => Some(Strategy::Smart),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    BBox(HeaderBBox),
+    MultiPolygon(MultiPolygon),
+}
+
+impl Region {
+    /// The bounding box, for the header of the extract
+    pub fn bbox(&self) -> HeaderBBox {
+        match self {
+            Region::BBox(bbox) => bbox.clone(),
+            Region::MultiPolygon(multipolygon) => {
+                let (min, max) = multipolygon
+                    .bbox()
+                    .unwrap_or((Location::new(0.0, 0.0), Location::new(0.0, 0.0)));
+                HeaderBBox {
+                    left: min.lon,
+                    right: max.lon,
+                    top: max.lat,
+                    bottom: min.lat,
+                }
+            }
+        }
+    }
+
+    pub fn contains(&self, location: Location) -> bool {
+        match self {
+            Region::BBox(bbox) => bbox_contains(bbox, location),
+            Region::MultiPolygon(multipolygon) => multipolygon.contains(location),
+        }
+    }
+}
+
+fn bbox_contains(bbox: &HeaderBBox, location: Location) -> bool {
+    (bbox.bottom..=bbox.top).contains(&location.lat)
+        && (bbox.left..=bbox.right).contains(&location.lon)
+}
+
+/// The IDs selected for an extract, collected over one or two passes
+pub struct Extract {
+    region: Region,
+    /// Cheap test before the polygon
+    bbox: HeaderBBox,
+    strategy: Strategy,
+    /// Nodes inside the region
+    nodes: IdSet,
+    /// Nodes outside the region, added to complete ways
+    way_nodes: IdSet,
+    ways: IdSet,
+    relations: IdSet,
+    /// Ways to add completely in the second pass of `Smart`
+    multipolygon_ways: IdSet,
+}
+
+impl Extract {
+    pub fn new(region: Region, strategy: Strategy) -> Self {
+        Extract {
+            bbox: region.bbox(),
+            region,
+            strategy,
+            nodes: IdSet::new(),
+            way_nodes: IdSet::dense(),
+            ways: IdSet::new(),
+            relations: IdSet::new(),
+            multipolygon_ways: IdSet::new(),
+        }
+    }
+
+    /// Whether `complete()` needs another pass before `write()`
+    pub fn needs_completion(&self) -> bool {
+        self.strategy == Strategy::Smart
+    }
+
+    /// First pass: selects nodes, ways and relations
+    pub fn select<R: Read>(&mut self, reader: &mut BlobReader<R>) {
+        // Relation members of relations, to select parents of selected ones
+        let mut parents: HashMap<u64, Vec<u64>> = HashMap::new();
+
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            for primitive in primitive_block.primitives() {
+                match primitive {
+                    Primitive::Node(node) => {
+                        let location = Location::from(&node);
+                        if bbox_contains(&self.bbox, location) && self.region.contains(location) {
+                            self.nodes.insert(node.id);
+                        }
+                    }
+                    Primitive::Way(way) => {
+                        if way.refs().any(|id| self.nodes.contains(id as u64)) {
+                            self.ways.insert(way.id);
+                            if self.strategy != Strategy::Simple {
+                                self.add_way_nodes(&way);
+                            }
+                        }
+                    }
+                    Primitive::Relation(relation) => {
+                        let mut selected = false;
+                        for (_, id, member_type) in relation.members() {
+                            selected |= match member_type {
+                                RelationMemberType::Node => self.nodes.contains(id),
+                                RelationMemberType::Way => self.ways.contains(id),
+                                RelationMemberType::Relation => {
+                                    parents.entry(id).or_default().push(relation.id);
+                                    false
+                                }
+                            };
+                        }
+                        if !selected {
+                            continue;
+                        }
+                        self.relations.insert(relation.id);
+                        if self.strategy == Strategy::Smart
+                            && relation.tags().any(|tag| tag == ("type", "multipolygon"))
+                        {
+                            for (_, id, member_type) in relation.members() {
+                                if member_type == RelationMemberType::Way {
+                                    self.multipolygon_ways.insert(id);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut pending: Vec<u64> = self.relations.iter().collect();
+        while let Some(id) = pending.pop() {
+            for parent in parents.get(&id).into_iter().flatten() {
+                if self.relations.insert(*parent) {
+                    pending.push(*parent);
+                }
+            }
+        }
+    }
+
+    /// Second pass for `Smart`: adds the member ways of selected
+    /// multipolygons, with all their nodes
+    pub fn complete<R: Read>(&mut self, reader: &mut BlobReader<R>) {
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            for primitive in primitive_block.primitives() {
+                if let Primitive::Way(way) = primitive
+                    && self.multipolygon_ways.contains(way.id)
+                {
+                    self.ways.insert(way.id);
+                    self.add_way_nodes(&way);
+                }
+            }
+        }
+    }
+
+    /// Last pass: copies the selected elements
+    pub fn write<R: Read, W: Write>(
+        &self,
+        reader: &mut BlobReader<R>,
+        writer: &mut PbfWriter<W>,
+    ) -> io::Result<()> {
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            for primitive in primitive_block.primitives() {
+                let selected = match &primitive {
+                    Primitive::Node(node) => {
+                        self.nodes.contains(node.id) || self.way_nodes.contains(node.id)
+                    }
+                    Primitive::Way(way) => self.ways.contains(way.id),
+                    Primitive::Relation(relation) => self.relations.contains(relation.id),
+                };
+                if selected {
+                    writer.write_element(Element::from(&primitive))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_way_nodes(&mut self, way: &Way) {
+        for id in way.refs() {
+            let id = id as u64;
+            if !self.nodes.contains(id) {
+                self.way_nodes.insert(id);
+            }
+        }
+    }
+}
+
+/// Runs all passes of an extract, opening the input anew for each
+pub fn extract<R, W, F>(
+    mut open: F,
+    region: Region,
+    strategy: Strategy,
+    writer: &mut PbfWriter<W>,
+) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut() -> io::Result<BlobReader<R>>,
+{
+    let mut extract = Extract::new(region, strategy);
+    extract.select(&mut open()?);
+    if extract.needs_completion() {
+        extract.complete(&mut open()?);
+    }
+    extract.write(&mut open()?, writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HeaderBlock;
+    use crate::opl::{opl_to_pbf, sort_keys};
+
+    const DATA: &str = "n1 x0.5 y0.5
+n2 x1.5 y0.5
+n3 x0.5 y1.5
+n4 x5 y5
+n5 x6 y5
+n6 x7 y5
+w10 Nn1,n2
+w11 Nn4,n5
+w12 Nn5,n3,n4
+w13 Nn5,n6
+r20 Ttype=multipolygon Mw12@outer,w13@outer
+r21 Mn1@,r22@
+r22 Mn5@
+r23 Mr21@
+r24 Mn4@
+";
+
+    fn run(strategy: Strategy) -> Vec<(u8, u64)> {
+        let mut writer = PbfWriter::new(vec![], &HeaderBlock::default()).unwrap();
+        let input = opl_to_pbf(DATA);
+        let open = || Ok(BlobReader::new(&input[..]));
+        let region = Region::BBox(HeaderBBox {
+            left: 0.0,
+            right: 1.0,
+            top: 2.0,
+            bottom: 0.0,
+        });
+        extract(open, region, strategy, &mut writer).unwrap();
+        sort_keys(&writer.finish().unwrap())
+    }
+
+    #[test]
+    fn test_strategies() {
+        assert_eq!(
+            run(Strategy::Simple),
+            vec![(0, 1), (0, 3), (1, 10), (1, 12), (2, 20), (2, 21), (2, 23)]
+        );
+        assert_eq!(
+            run(Strategy::CompleteWays),
+            vec![
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (0, 4),
+                (0, 5),
+                (1, 10),
+                (1, 12),
+                (2, 20),
+                (2, 21),
+                (2, 23)
+            ]
+        );
+        assert_eq!(
+            run(Strategy::Smart),
+            vec![
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (0, 4),
+                (0, 5),
+                (0, 6),
+                (1, 10),
+                (1, 12),
+                (1, 13),
+                (2, 20),
+                (2, 21),
+                (2, 23)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_region() {
+        let bbox = HeaderBBox {
+            left: -1.0,
+            right: 1.0,
+            top: 2.0,
+            bottom: -2.0,
+        };
+        let region = Region::BBox(bbox.clone());
+        assert!(region.contains(Location::new(2.0, 1.0)));
+        assert!(!region.contains(Location::new(1.0, 2.0)));
+        assert_eq!(region.bbox(), bbox);
+        assert_eq!(
+            Strategy::parse("complete_ways"),
+            Some(Strategy::CompleteWays)
+        );
+        assert_eq!(Strategy::parse("complete"), None);
+    }
+}
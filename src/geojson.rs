@@ -0,0 +1,359 @@
+//! GeoJSON export of elements with their geometries, and import of the
+//! (multi)polygons that delimit extracts
+//!
+//! Writes either one `FeatureCollection` or, in sequence mode, one feature
+//! per line. Way geometries need node locations, see `crate::location`.
+
+use std::io::{self, Write};
+
+use crate::area::{Area, AreaSource, is_area_way};
+use crate::geometry::{MultiPolygon, Polygon, Ring, signed_area};
+use crate::json::Json;
+use crate::{Location, Node, NodeLocationStore, Way};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GeoJsonOptions {
+    /// GeoJSONSeq: newline-delimited features instead of a `FeatureCollection`
+    pub seq: bool,
+    /// Add `@type`, `@id` and, where known, `@version` to the properties
+    pub metadata: bool,
+}
+
+pub struct GeoJsonWriter<W: Write> {
+    out: W,
+    options: GeoJsonOptions,
+    features: u64,
+}
+
+impl<W: Write> GeoJsonWriter<W> {
+    pub fn new(mut out: W, options: GeoJsonOptions) -> io::Result<Self> {
+        if !options.seq {
+            write!(out, "{{\"type\":\"FeatureCollection\",\"features\":[")?;
+        }
+        Ok(GeoJsonWriter {
+            out,
+            options,
+            features: 0,
+        })
+    }
+
+    /// Number of features written so far
+    pub fn features(&self) -> u64 {
+        self.features
+    }
+
+    pub fn write_node(&mut self, node: &Node) -> io::Result<()> {
+        let mut geometry = String::from("{\"type\":\"Point\",\"coordinates\":");
+        push_position(&mut geometry, Location::from(node));
+        geometry.push('}');
+        let version = node.info.as_ref().and_then(|info| info.version);
+        self.write_feature(
+            "node",
+            node.id,
+            version,
+            node.tags.iter().copied(),
+            &geometry,
+        )
+    }
+
+    /// Writes a LineString, or a Polygon for closed ways with area tags
+    ///
+    /// Returns `false` without writing anything if node locations are missing.
+    pub fn write_way<S: NodeLocationStore>(&mut self, way: &Way, store: &S) -> io::Result<bool> {
+        let Ok(mut locations) = store.way_locations(way) else {
+            return Ok(false);
+        };
+        let mut geometry = String::new();
+        let closed = locations.len() >= 4 && locations.first() == locations.last();
+        if closed && is_area_way(way.tags()) {
+            if signed_area(&locations) < 0.0 {
+                locations.reverse();
+            }
+            geometry.push_str("{\"type\":\"Polygon\",\"coordinates\":[");
+            push_positions(&mut geometry, &locations);
+            geometry.push_str("]}");
+        } else {
+            geometry.push_str("{\"type\":\"LineString\",\"coordinates\":");
+            push_positions(&mut geometry, &locations);
+            geometry.push('}');
+        }
+        let version = way.info.as_ref().and_then(|info| info.version);
+        self.write_feature("way", way.id, version, way.tags(), &geometry)?;
+        Ok(true)
+    }
+
+    /// Writes an assembled area as a MultiPolygon
+    pub fn write_area(&mut self, area: &Area) -> io::Result<()> {
+        let mut geometry = String::from("{\"type\":\"MultiPolygon\",\"coordinates\":");
+        push_multipolygon(&mut geometry, &area.geometry);
+        geometry.push('}');
+        let (element_type, id) = match area.source {
+            AreaSource::Way(id) => ("way", id),
+            AreaSource::Relation(id) => ("relation", id),
+        };
+        let tags = area.tags.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        self.write_feature(element_type, id, None, tags, &geometry)
+    }
+
+    /// Closes the `FeatureCollection`, and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.options.seq {
+            writeln!(self.out, "]}}")?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_feature<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(
+        &mut self,
+        element_type: &str,
+        id: u64,
+        version: Option<u32>,
+        tags: I,
+        geometry: &str,
+    ) -> io::Result<()> {
+        let mut properties = vec![];
+        if self.options.metadata {
+            properties.push(format!("\"@type\":\"{}\"", element_type));
+            properties.push(format!("\"@id\":{}", id));
+            if let Some(version) = version {
+                properties.push(format!("\"@version\":{}", version));
+            }
+        }
+        for (key, value) in tags {
+            properties.push(format!("{}:{}", json_string(key), json_string(value)));
+        }
+
+        if !self.options.seq {
+            if self.features > 0 {
+                write!(self.out, ",")?;
+            }
+            writeln!(self.out)?;
+        }
+        write!(
+            self.out,
+            "{{\"type\":\"Feature\",\"id\":\"{}/{}\",\"properties\":{{{}}},\"geometry\":{}}}",
+            element_type,
+            id,
+            properties.join(","),
+            geometry
+        )?;
+        if self.options.seq {
+            writeln!(self.out)?;
+        }
+        self.features += 1;
+        Ok(())
+    }
+}
+
+fn push_position(out: &mut String, location: Location) {
+    out.push_str(&format!("[{},{}]", location.lon, location.lat));
+}
+
+fn push_positions(out: &mut String, locations: &[Location]) {
+    out.push('[');
+    for (i, location) in locations.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        push_position(out, *location);
+    }
+    out.push(']');
+}
+
+fn push_multipolygon(out: &mut String, multipolygon: &MultiPolygon) {
+    out.push('[');
+    for (i, polygon) in multipolygon.polygons.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push('[');
+        push_positions(out, &polygon.outer);
+        for inner in &polygon.inners {
+            out.push(',');
+            push_positions(out, inner);
+        }
+        out.push(']');
+    }
+    out.push(']');
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Reads the polygons of a `Polygon` or `MultiPolygon` geometry, or of all
+/// such features of a `Feature` or `FeatureCollection`
+///
+/// Unclosed rings are closed. Returns `None` for invalid JSON, or if no
+/// polygon is found.
+pub fn read_multipolygon(json: &str) -> Option<MultiPolygon> {
+    let value = Json::parse(json)?;
+    let mut multipolygon = MultiPolygon::default();
+    collect_polygons(&value, &mut multipolygon)?;
+    if multipolygon.polygons.is_empty() {
+        return None;
+    }
+    Some(multipolygon)
+}
+
+fn collect_polygons(value: &Json, multipolygon: &mut MultiPolygon) -> Option<()> {
+    match value.get("type")?.as_str()? {
+        "FeatureCollection" => {
+            for feature in value.get("features")?.as_array()? {
+                collect_polygons(feature, multipolygon)?;
+            }
+        }
+        "Feature" => match value.get("geometry")? {
+            Json::Null => (),
+            geometry => collect_polygons(geometry, multipolygon)?,
+        },
+        "Polygon" => {
+            let polygon = read_polygon(value.get("coordinates")?)?;
+            multipolygon.polygons.push(polygon);
+        }
+        "MultiPolygon" => {
+            for polygon in value.get("coordinates")?.as_array()? {
+                multipolygon.polygons.push(read_polygon(polygon)?);
+            }
+        }
+        // Other geometries delimit no area
+        _ => (),
+    }
+    Some(())
+}
+
+fn read_polygon(value: &Json) -> Option<Polygon> {
+    let mut rings = value.as_array()?.iter().map(read_ring);
+    Some(Polygon {
+        outer: rings.next()??,
+        inners: rings.collect::<Option<_>>()?,
+    })
+}
+
+fn read_ring(value: &Json) -> Option<Ring> {
+    let mut ring = value
+        .as_array()?
+        .iter()
+        .map(|position| match position.as_array()?.as_slice() {
+            [Json::Number(lon), Json::Number(lat), ..] => Some(Location::new(*lat, *lon)),
+            _ => None,
+        })
+        .collect::<Option<Ring>>()?;
+    if ring.len() < 3 {
+        return None;
+    }
+    if ring.first() != ring.last() {
+        ring.push(ring[0]);
+    }
+    Some(ring)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node() -> Node<'static> {
+        Node {
+            id: 1,
+            lat: 52.5,
+            lon: 13.25,
+            info: None,
+            tags: vec![("amenity", "hospital"), ("name", "St. \"Q\"\n")],
+        }
+    }
+
+    #[test]
+    fn test_feature_collection() {
+        let options = GeoJsonOptions {
+            seq: false,
+            metadata: true,
+        };
+        let mut writer = GeoJsonWriter::new(vec![], options).unwrap();
+        writer.write_node(&node()).unwrap();
+        writer.write_node(&node()).unwrap();
+        assert_eq!(writer.features(), 2);
+        let out = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let feature = r#"{"type":"Feature","id":"node/1","properties":{"@type":"node","@id":1,"amenity":"hospital","name":"St. \"Q\"\n"},"geometry":{"type":"Point","coordinates":[13.25,52.5]}}"#;
+        assert_eq!(
+            out,
+            format!(
+                "{{\"type\":\"FeatureCollection\",\"features\":[\n{},\n{}]}}\n",
+                feature, feature
+            )
+        );
+    }
+
+    #[test]
+    fn test_seq_area() {
+        let options = GeoJsonOptions {
+            seq: true,
+            metadata: false,
+        };
+        let ring = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)]
+            .iter()
+            .map(|&(lon, lat)| Location::new(lat, lon))
+            .collect();
+        let area = Area {
+            source: AreaSource::Relation(7),
+            tags: vec![("landuse".to_string(), "grass".to_string())],
+            geometry: MultiPolygon {
+                polygons: vec![Polygon {
+                    outer: ring,
+                    inners: vec![],
+                }],
+            },
+        };
+        let mut writer = GeoJsonWriter::new(vec![], options).unwrap();
+        writer.write_area(&area).unwrap();
+        writer.write_node(&node()).unwrap();
+        let out = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            r#"{"type":"Feature","id":"relation/7","properties":{"landuse":"grass"},"geometry":{"type":"MultiPolygon","coordinates":[[[[0,0],[1,0],[1,1],[0,0]]]]}}"#
+        );
+    }
+
+    #[test]
+    fn test_read_multipolygon() {
+        let json = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"name": "A \"q\" \u00e9"},
+             "geometry": {"type": "Polygon", "coordinates": [
+                [[0, 0], [4, 0], [4, 4], [0, 4], [0, 0]],
+                [[1, 1], [1, 2], [2, 2]]]}},
+            {"type": "Feature", "properties": null, "geometry": null},
+            {"type": "Feature", "properties": {}, "geometry":
+                {"type": "MultiPolygon", "coordinates": [[[[10, 10], [11, 10], [11, 11.5e0]]]]}},
+            {"type": "Feature", "properties": {}, "geometry":
+                {"type": "Point", "coordinates": [1, 2]}}
+        ]}"#;
+        let multipolygon = read_multipolygon(json).unwrap();
+        assert_eq!(multipolygon.polygons.len(), 2);
+        assert_eq!(multipolygon.polygons[0].inners[0].len(), 4);
+        assert_eq!(multipolygon.polygons[1].outer[2], Location::new(11.5, 11.0));
+        assert!(multipolygon.contains(Location::new(3.0, 3.0)));
+        assert!(!multipolygon.contains(Location::new(1.8, 1.2)));
+        assert!(multipolygon.contains(Location::new(10.2, 10.5)));
+
+        assert!(read_multipolygon(r#"{"type":"Point","coordinates":[1,2]}"#).is_none());
+        assert!(read_multipolygon(r#"{"type":"Polygon","coordinates":[[[0,0],[1,0]]]}"#).is_none());
+        assert!(read_multipolygon(r#"{"type":"Polygon""#).is_none());
+        assert!(read_multipolygon(&"[".repeat(1_000_000)).is_none());
+    }
+}
@@ -0,0 +1,153 @@
+//! Polygons of node locations, and the few planar operations needed on them
+//!
+//! Coordinates are treated as planar with longitude as x and latitude as y,
+//! which is good enough for containment tests at OSM scales.
+
+use crate::Location;
+
+/// Closed ring; the first and last location are equal
+pub type Ring = Vec<Location>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub outer: Ring,
+    pub inners: Vec<Ring>,
+}
+
+impl Polygon {
+    pub fn contains(&self, location: Location) -> bool {
+        ring_contains(&self.outer, location)
+            && !self
+                .inners
+                .iter()
+                .any(|inner| ring_contains(inner, location))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MultiPolygon {
+    pub polygons: Vec<Polygon>,
+}
+
+impl MultiPolygon {
+    pub fn contains(&self, location: Location) -> bool {
+        self.polygons
+            .iter()
+            .any(|polygon| polygon.contains(location))
+    }
+
+    /// `(min, max)` corners of the outer rings, or `None` if empty
+    pub fn bbox(&self) -> Option<(Location, Location)> {
+        let mut locations = self.polygons.iter().flat_map(|polygon| &polygon.outer);
+        let first = *locations.next()?;
+        Some(locations.fold((first, first), |(min, max), location| {
+            (
+                Location::new(min.lat.min(location.lat), min.lon.min(location.lon)),
+                Location::new(max.lat.max(location.lat), max.lon.max(location.lon)),
+            )
+        }))
+    }
+}
+
+/// Even-odd test; locations exactly on the boundary may go either way
+pub fn ring_contains(ring: &[Location], location: Location) -> bool {
+    let mut inside = false;
+    for segment in ring.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        if (a.lat > location.lat) != (b.lat > location.lat) {
+            let lon = a.lon + (location.lat - a.lat) / (b.lat - a.lat) * (b.lon - a.lon);
+            if location.lon < lon {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Shoelace formula; positive for counterclockwise rings
+pub fn signed_area(ring: &[Location]) -> f64 {
+    ring.windows(2)
+        .map(|segment| {
+            let (a, b) = (segment[0], segment[1]);
+            a.lon * b.lat - b.lon * a.lat
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+fn orientation(a: Location, b: Location, c: Location) -> f64 {
+    (b.lon - a.lon) * (c.lat - a.lat) - (b.lat - a.lat) * (c.lon - a.lon)
+}
+
+/// Whether segments `a`-`b` and `c`-`d` cross at a point interior to both
+///
+/// Touching at endpoints and collinear overlaps do not count.
+pub fn segments_cross(a: Location, b: Location, c: Location, d: Location) -> bool {
+    let o1 = orientation(a, b, c);
+    let o2 = orientation(a, b, d);
+    let o3 = orientation(c, d, a);
+    let o4 = orientation(c, d, b);
+    o1 * o2 < 0.0 && o3 * o4 < 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(coords: &[(f64, f64)]) -> Ring {
+        coords
+            .iter()
+            .map(|&(lon, lat)| Location::new(lat, lon))
+            .collect()
+    }
+
+    #[test]
+    fn test_polygon_contains() {
+        let polygon = Polygon {
+            outer: ring(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0), (0.0, 0.0)]),
+            inners: vec![ring(&[
+                (1.0, 1.0),
+                (1.0, 2.0),
+                (2.0, 2.0),
+                (2.0, 1.0),
+                (1.0, 1.0),
+            ])],
+        };
+        assert!(polygon.contains(Location::new(3.0, 3.0)));
+        assert!(!polygon.contains(Location::new(1.5, 1.5)));
+        assert!(!polygon.contains(Location::new(5.0, 1.0)));
+        assert!(signed_area(&polygon.outer) > 0.0);
+        assert!(signed_area(&polygon.inners[0]) < 0.0);
+
+        let multipolygon = MultiPolygon {
+            polygons: vec![polygon],
+        };
+        assert_eq!(
+            multipolygon.bbox(),
+            Some((Location::new(0.0, 0.0), Location::new(4.0, 4.0)))
+        );
+    }
+
+    #[test]
+    fn test_segments_cross() {
+        let p = |lon, lat| Location::new(lat, lon);
+        assert!(segments_cross(
+            p(0.0, 0.0),
+            p(2.0, 2.0),
+            p(0.0, 2.0),
+            p(2.0, 0.0)
+        ));
+        assert!(!segments_cross(
+            p(0.0, 0.0),
+            p(2.0, 2.0),
+            p(2.0, 2.0),
+            p(4.0, 0.0)
+        ));
+        assert!(!segments_cross(
+            p(0.0, 0.0),
+            p(1.0, 1.0),
+            p(2.0, 2.0),
+            p(3.0, 3.0)
+        ));
+    }
+}
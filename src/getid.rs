@@ -0,0 +1,312 @@
+//! Picking elements by ID out of a file, like `osmium getid`
+//!
+//! Every pass skips the blocks whose ID ranges, from
+//! `PrimitiveBlock::summary()`, contain none of the wanted IDs.
+
+use std::io::{self, Read, Write};
+
+use crate::id_set::IdSet;
+use crate::owned::Element;
+use crate::{BlobReader, KindSummary, PbfWriter, Primitive, PrimitiveBlock, RelationMemberType};
+
+/// Parses `n123`, `w123` or `r123`
+pub fn parse_id(s: &str) -> Option<(RelationMemberType, u64)> {
+    let member_type = match s.get(..1)? {
+        "n" => RelationMemberType::Node,
+        "w" => RelationMemberType::Way,
+        "r" => RelationMemberType::Relation,
+        _ => return None,
+    };
+    Some((member_type, s[1..].parse().ok()?))
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Ids {
+    pub nodes: IdSet,
+    pub ways: IdSet,
+    pub relations: IdSet,
+}
+
+impl Ids {
+    /// Returns whether the ID was not yet present
+    pub fn insert(&mut self, member_type: RelationMemberType, id: u64) -> bool {
+        match member_type {
+            RelationMemberType::Node => self.nodes.insert(id),
+            RelationMemberType::Way => self.ways.insert(id),
+            RelationMemberType::Relation => self.relations.insert(id),
+        }
+    }
+
+    pub fn contains(&self, member_type: &RelationMemberType, id: u64) -> bool {
+        match member_type {
+            RelationMemberType::Node => self.nodes.contains(id),
+            RelationMemberType::Way => self.ways.contains(id),
+            RelationMemberType::Relation => self.relations.contains(id),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.nodes.len() + self.ways.len() + self.relations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether the ID was present
+    pub fn remove(&mut self, member_type: &RelationMemberType, id: u64) -> bool {
+        match member_type {
+            RelationMemberType::Node => self.nodes.remove(id),
+            RelationMemberType::Way => self.ways.remove(id),
+            RelationMemberType::Relation => self.relations.remove(id),
+        }
+    }
+
+    /// The IDs not in `other`
+    pub fn difference(&self, other: &Ids) -> Ids {
+        Ids {
+            nodes: self.nodes.difference(&other.nodes),
+            ways: self.ways.difference(&other.ways),
+            relations: self.relations.difference(&other.relations),
+        }
+    }
+}
+
+fn primitive_id(primitive: &Primitive) -> (RelationMemberType, u64) {
+    match primitive {
+        Primitive::Node(node) => (RelationMemberType::Node, node.id),
+        Primitive::Way(way) => (RelationMemberType::Way, way.id),
+        Primitive::Relation(relation) => (RelationMemberType::Relation, relation.id),
+    }
+}
+
+/// Whether any ID of `set` lies in the range of the block
+fn overlaps(set: &IdSet, summary: &KindSummary) -> bool {
+    summary
+        .ids
+        .as_ref()
+        .is_some_and(|ids| set.contains_any(ids.clone()))
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GetIdOptions {
+    /// Add the members of wanted relations, recursively, and the nodes of
+    /// wanted ways
+    pub recursive: bool,
+    /// Add the relations that have any of the wanted elements as members,
+    /// after adding the recursive ones
+    pub parents: bool,
+}
+
+/// Resolves the options into the final set of IDs, with as many passes as
+/// needed
+pub struct GetId {
+    pub ids: Ids,
+    options: GetIdOptions,
+}
+
+impl GetId {
+    pub fn new(ids: Ids, options: GetIdOptions) -> Self {
+        GetId { ids, options }
+    }
+
+    /// Whether there are relations whose members `add_members()` must add
+    pub fn needs_members(&self) -> bool {
+        self.options.recursive && !self.ids.relations.is_empty()
+    }
+
+    /// Adds the members of wanted relations; returns whether any new
+    /// relation was added, in which case another pass is needed
+    pub fn add_members<R: Read>(&mut self, reader: &mut BlobReader<R>) -> bool {
+        let mut added = false;
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            if !overlaps(&self.ids.relations, &primitive_block.summary().relations) {
+                continue;
+            }
+            for primitive in primitive_block.primitives() {
+                if let Primitive::Relation(relation) = primitive
+                    && self.ids.relations.contains(relation.id)
+                {
+                    for (_, id, member_type) in relation.members() {
+                        let is_relation = member_type == RelationMemberType::Relation;
+                        added |= self.ids.insert(member_type, id) && is_relation;
+                    }
+                }
+            }
+        }
+        added
+    }
+
+    /// Adds the nodes of wanted ways
+    pub fn add_way_nodes<R: Read>(&mut self, reader: &mut BlobReader<R>) {
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            if !overlaps(&self.ids.ways, &primitive_block.summary().ways) {
+                continue;
+            }
+            for primitive in primitive_block.primitives() {
+                if let Primitive::Way(way) = primitive
+                    && self.ids.ways.contains(way.id)
+                {
+                    self.ids.nodes.extend(way.refs().map(|id| id as u64));
+                }
+            }
+        }
+    }
+
+    /// Adds the relations referencing any wanted element
+    pub fn add_parents<R: Read>(&mut self, reader: &mut BlobReader<R>) {
+        let mut parents = vec![];
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            if primitive_block.summary().relations.is_empty() {
+                continue;
+            }
+            for primitive in primitive_block.primitives() {
+                if let Primitive::Relation(relation) = primitive
+                    && relation
+                        .members()
+                        .any(|(_, id, member_type)| self.ids.contains(&member_type, id))
+                {
+                    parents.push(relation.id);
+                }
+            }
+        }
+        self.ids.relations.extend(parents);
+    }
+
+    /// Copies the wanted elements, and returns the IDs not found
+    pub fn write<R: Read, W: Write>(
+        &self,
+        reader: &mut BlobReader<R>,
+        writer: &mut PbfWriter<W>,
+    ) -> io::Result<Ids> {
+        let mut found = Ids::default();
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            let summary = primitive_block.summary();
+            if !overlaps(&self.ids.nodes, &summary.nodes)
+                && !overlaps(&self.ids.ways, &summary.ways)
+                && !overlaps(&self.ids.relations, &summary.relations)
+            {
+                continue;
+            }
+            for primitive in primitive_block.primitives() {
+                let (member_type, id) = primitive_id(&primitive);
+                if self.ids.contains(&member_type, id) {
+                    found.insert(member_type, id);
+                    writer.write_element(Element::from(&primitive))?;
+                }
+            }
+        }
+        Ok(self.ids.difference(&found))
+    }
+}
+
+/// Runs all passes, opening the input anew for each, and returns the IDs not
+/// found
+pub fn get_ids<R, W, F>(
+    mut open: F,
+    ids: Ids,
+    options: GetIdOptions,
+    writer: &mut PbfWriter<W>,
+) -> io::Result<Ids>
+where
+    R: Read,
+    W: Write,
+    F: FnMut() -> io::Result<BlobReader<R>>,
+{
+    let mut get_id = GetId::new(ids, options);
+    if get_id.needs_members() {
+        while get_id.add_members(&mut open()?) {}
+    }
+    if options.recursive && !get_id.ids.ways.is_empty() {
+        get_id.add_way_nodes(&mut open()?);
+    }
+    if options.parents {
+        get_id.add_parents(&mut open()?);
+    }
+    get_id.write(&mut open()?, writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HeaderBlock;
+    use crate::opl::{opl_to_pbf, sort_keys};
+
+    const DATA: &str = "n1 x0 y0
+n2 x1 y0
+n3 x2 y0
+n4 x3 y0
+w10 Nn1,n2
+w11 Nn3,n4
+r20 Mw10@,r21@
+r21 Mn4@,r22@
+r22 Mw11@
+r23 Mn2@
+";
+
+    fn run(ids: &[&str], options: GetIdOptions) -> (Vec<(u8, u64)>, Ids) {
+        let mut wanted = Ids::default();
+        for id in ids {
+            let (member_type, id) = parse_id(id).unwrap();
+            wanted.insert(member_type, id);
+        }
+        let input = opl_to_pbf(DATA);
+        let open = || Ok(BlobReader::new(&input[..]));
+        let mut writer = PbfWriter::new(vec![], &HeaderBlock::default()).unwrap();
+        let missing = get_ids(open, wanted, options, &mut writer).unwrap();
+        (sort_keys(&writer.finish().unwrap()), missing)
+    }
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(parse_id("n17"), Some((RelationMemberType::Node, 17)));
+        assert_eq!(parse_id("r1"), Some((RelationMemberType::Relation, 1)));
+        assert_eq!(parse_id("17"), None);
+        assert_eq!(parse_id("w"), None);
+        assert_eq!(parse_id("é1"), None);
+    }
+
+    #[test]
+    fn test_get_ids() {
+        let (elements, missing) = run(&["n2", "w11", "r99"], GetIdOptions::default());
+        assert_eq!(elements, vec![(0, 2), (1, 11)]);
+        assert_eq!(missing.relations.iter().collect::<Vec<_>>(), vec![99]);
+        assert_eq!(missing.len(), 1);
+
+        let recursive = GetIdOptions {
+            recursive: true,
+            parents: false,
+        };
+        let (elements, missing) = run(&["r20"], recursive);
+        assert!(missing.is_empty());
+        assert_eq!(
+            elements,
+            vec![
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (0, 4),
+                (1, 10),
+                (1, 11),
+                (2, 20),
+                (2, 21),
+                (2, 22)
+            ]
+        );
+
+        let parents = GetIdOptions {
+            recursive: false,
+            parents: true,
+        };
+        let (elements, _) = run(&["n2", "w11"], parents);
+        assert_eq!(elements, vec![(0, 2), (1, 11), (2, 22), (2, 23)]);
+    }
+}
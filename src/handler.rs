@@ -0,0 +1,120 @@
+//! Push-style processing of a file through callbacks
+//!
+//! Instead of nesting loops over blobs, blocks and primitives, implement
+//! `Handler` for the methods of interest and let `apply()` drive it.
+
+use std::io::Read;
+use std::sync::mpsc::{Receiver, sync_channel};
+use std::thread;
+
+use crate::{Blob, BlobReader, HeaderBlock, Node, Primitive, PrimitiveBlock, Relation, Way};
+
+/// Callbacks for the contents of a file; all of them default to doing nothing
+pub trait Handler {
+    fn header(&mut self, _header: &HeaderBlock) {}
+    fn block_start(&mut self, _primitive_block: &PrimitiveBlock) {}
+    fn node(&mut self, _node: &Node) {}
+    fn way(&mut self, _way: &Way) {}
+    fn relation(&mut self, _relation: &Relation) {}
+    fn block_end(&mut self, _primitive_block: &PrimitiveBlock) {}
+}
+
+impl<H: Handler + ?Sized> Handler for &mut H {
+    fn header(&mut self, header: &HeaderBlock) {
+        (**self).header(header)
+    }
+
+    fn block_start(&mut self, primitive_block: &PrimitiveBlock) {
+        (**self).block_start(primitive_block)
+    }
+
+    fn node(&mut self, node: &Node) {
+        (**self).node(node)
+    }
+
+    fn way(&mut self, way: &Way) {
+        (**self).way(way)
+    }
+
+    fn relation(&mut self, relation: &Relation) {
+        (**self).relation(relation)
+    }
+
+    fn block_end(&mut self, primitive_block: &PrimitiveBlock) {
+        (**self).block_end(primitive_block)
+    }
+}
+
+/// Feeds the primitives of one block to a handler
+pub fn apply_block<H: Handler>(primitive_block: &PrimitiveBlock, handler: &mut H) {
+    handler.block_start(primitive_block);
+    for primitive in primitive_block.primitives() {
+        match primitive {
+            Primitive::Node(node) => handler.node(&node),
+            Primitive::Way(way) => handler.way(&way),
+            Primitive::Relation(relation) => handler.relation(&relation),
+        }
+    }
+    handler.block_end(primitive_block);
+}
+
+/// Feeds a whole file to a handler, in file order
+pub fn apply<R: Read, H: Handler>(reader: &mut BlobReader<R>, handler: &mut H) {
+    if let Some(header) = reader.header() {
+        handler.header(header);
+    }
+    for blob in reader {
+        let data = blob.into_data();
+        apply_block(&PrimitiveBlock::parse(&data), handler);
+    }
+}
+
+fn blobs_worker<H: Handler>(req_rx: Receiver<Blob>, mut handler: H) -> H {
+    while let Ok(blob) = req_rx.recv() {
+        let data = blob.into_data();
+        apply_block(&PrimitiveBlock::parse(&data), &mut handler);
+    }
+    handler
+}
+
+/// Feeds a file to `threads` handlers created by `new_handler`, decompressing
+/// and parsing blocks on as many threads
+///
+/// Every handler sees the header and whole blocks, but blocks are
+/// distributed round-robin and processed in no particular order. The
+/// handlers are returned for merging their results.
+pub fn apply_parallel<R, H, F>(reader: &mut BlobReader<R>, threads: usize, new_handler: F) -> Vec<H>
+where
+    R: Read,
+    H: Handler + Send,
+    F: Fn() -> H,
+{
+    let threads = threads.max(1);
+    let header = reader.header().cloned();
+    thread::scope(|scope| {
+        let mut workers = Vec::with_capacity(threads);
+        for _ in 0..threads {
+            let mut handler = new_handler();
+            if let Some(header) = &header {
+                handler.header(header);
+            }
+            let (req_tx, req_rx) = sync_channel(2);
+            let worker = scope.spawn(move || blobs_worker(req_rx, handler));
+            workers.push((req_tx, worker));
+        }
+
+        let mut w = 0;
+        for blob in reader {
+            workers[w].0.send(blob).unwrap();
+            w = (w + 1) % threads;
+        }
+
+        workers
+            .into_iter()
+            .map(|(req_tx, worker)| {
+                drop(req_tx);
+                worker.join().unwrap()
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,148 @@
+//! Full-history files, which hold every version of every element
+//!
+//! Versions of an element follow each other, oldest first, and deleted
+//! versions have `visible` set to false.
+
+use std::io::Read;
+use std::iter::Peekable;
+
+use crate::owned::Element;
+use crate::{BlobReader, HeaderBlock, PrimitiveBlock, Timestamp};
+
+pub const HISTORICAL_INFORMATION: &str = "HistoricalInformation";
+
+pub fn is_history_file(header: &HeaderBlock) -> bool {
+    header.has_feature(HISTORICAL_INFORMATION)
+}
+
+/// All versions of one element, oldest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    pub versions: Vec<Element>,
+}
+
+impl History {
+    pub fn sort_key(&self) -> (u8, u64) {
+        self.versions[0].sort_key()
+    }
+
+    pub fn id(&self) -> u64 {
+        self.versions[0].id()
+    }
+
+    pub fn latest(&self) -> &Element {
+        self.versions.last().unwrap()
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        !self.latest().is_visible()
+    }
+
+    /// The version current at `timestamp`, or `None` if the element did not
+    /// exist then
+    pub fn at(&self, timestamp: Timestamp) -> Option<&Element> {
+        self.versions
+            .iter()
+            .take_while(|version| version.timestamp().unwrap_or_default() <= timestamp)
+            .last()
+            .filter(|version| version.is_visible())
+    }
+}
+
+/// Groups consecutive versions of the same element
+pub struct Histories<I: Iterator<Item = Element>> {
+    elements: Peekable<I>,
+}
+
+impl<I: Iterator<Item = Element>> Histories<I> {
+    pub fn new<T: IntoIterator<IntoIter = I>>(elements: T) -> Self {
+        Histories {
+            elements: elements.into_iter().peekable(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Element>> Iterator for Histories<I> {
+    type Item = History;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.elements.next()?;
+        let key = first.sort_key();
+        let mut versions = vec![first];
+        while let Some(version) = self.elements.next_if(|element| element.sort_key() == key) {
+            versions.push(version);
+        }
+        Some(History { versions })
+    }
+}
+
+/// The elements of a history file as they were at `timestamp`, like
+/// `osmium time-filter`
+pub fn time_filter<I: IntoIterator<Item = Element>>(
+    elements: I,
+    timestamp: Timestamp,
+) -> impl Iterator<Item = Element> {
+    Histories::new(elements).filter_map(move |history| history.at(timestamp).cloned())
+}
+
+/// Owned copies of all elements of a file, one block at a time
+pub fn elements<R: Read>(reader: &mut BlobReader<R>) -> impl Iterator<Item = Element> + '_ {
+    reader.flat_map(|blob| {
+        let data = blob.into_data();
+        let primitive_block = PrimitiveBlock::parse(&data);
+        primitive_block
+            .primitives()
+            .map(|primitive| Element::from(&primitive))
+            .collect::<Vec<_>>()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opl::OplReader;
+    use std::io;
+
+    const HISTORY: &str = "n1 v1 dV t2020-01-01T00:00:00Z x1 y1
+n1 v2 dV t2020-02-01T00:00:00Z x2 y2
+n1 v3 dD t2020-03-01T00:00:00Z x y
+n2 v1 dV t2020-02-15T00:00:00Z x3 y3
+w1 v1 dV t2020-01-15T00:00:00Z Nn1
+w1 v2 dV t2020-02-15T00:00:00Z Nn1,n2
+";
+
+    fn history() -> Vec<Element> {
+        OplReader::new(HISTORY.as_bytes())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_histories() {
+        let histories: Vec<History> = Histories::new(history()).collect();
+        assert_eq!(histories.len(), 3);
+        assert_eq!(histories[0].versions.len(), 3);
+        assert!(histories[0].is_deleted());
+        assert_eq!(histories[1].id(), 2);
+        assert_eq!(histories[2].sort_key(), (1, 1));
+        assert_eq!(histories[2].latest().version(), Some(2));
+    }
+
+    #[test]
+    fn test_time_filter() {
+        let at = |timestamp: &str| {
+            let timestamp = Timestamp::parse(timestamp).unwrap();
+            time_filter(history(), timestamp)
+                .map(|element| (element.sort_key(), element.version().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(at("2019-12-31T23:59:59Z"), vec![]);
+        assert_eq!(at("2020-01-01T00:00:00Z"), vec![((0, 1), 1)]);
+        assert_eq!(at("2020-02-01T00:00:00Z"), vec![((0, 1), 2), ((1, 1), 1)]);
+        assert_eq!(
+            at("2020-02-20T00:00:00Z"),
+            vec![((0, 1), 2), ((0, 2), 1), ((1, 1), 2)]
+        );
+        assert_eq!(at("2020-03-01T00:00:00Z"), vec![((0, 2), 1), ((1, 1), 2)]);
+    }
+}
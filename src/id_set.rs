@@ -0,0 +1,686 @@
+//! Compact sets of element IDs
+//!
+//! OSM IDs are dense enough that one bit per possible ID, allocated in
+//! pages on first use, takes far less memory than a `HashSet<u64>` for the
+//! many IDs of large files. Few or scattered IDs fit better into a sorted
+//! vector, and `IdSet` starts as one until the bitmap becomes smaller.
+
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::ops::RangeInclusive;
+
+/// IDs per page
+const PAGE_BITS: u64 = 1 << 16;
+const PAGE_WORDS: usize = (PAGE_BITS / 64) as usize;
+/// Pages beyond this, for IDs from 2^42 on, are never allocated; such IDs,
+/// including negative ones cast to `u64`, go into a sparse set instead
+const MAX_PAGES: u64 = 1 << 26;
+/// Size below which a sparse set is not worth converting
+const MIN_DENSE_LEN: usize = 1 << 10;
+
+type Page = Box<[u64; PAGE_WORDS]>;
+
+/// The positions of the set bits of a word, lowest first
+struct Bits(u64);
+
+impl Iterator for Bits {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+        let bit = self.0.trailing_zeros() as u64;
+        self.0 &= self.0 - 1;
+        Some(bit)
+    }
+}
+
+/// Sorted vector of IDs; appending in ascending order is cheapest
+///
+/// IDs inserted out of order wait in a hash set, and are merged into the
+/// vector once there are as many of them, which keeps random inserts
+/// amortized O(log n).
+#[derive(Debug, Default, Clone)]
+pub struct SparseIdSet {
+    ids: Vec<u64>,
+    pending: HashSet<u64>,
+}
+
+impl SparseIdSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether the ID was not yet present
+    pub fn insert(&mut self, id: u64) -> bool {
+        if self.ids.last().is_none_or(|last| *last < id) && !self.pending.contains(&id) {
+            self.ids.push(id);
+            return true;
+        }
+        if self.ids.binary_search(&id).is_ok() || !self.pending.insert(id) {
+            return false;
+        }
+        if self.pending.len() >= self.ids.len() {
+            self.flush();
+        }
+        true
+    }
+
+    /// Merges the pending IDs into the sorted vector
+    fn flush(&mut self) {
+        let mut pending: Vec<u64> = self.pending.drain().collect();
+        pending.sort_unstable();
+        self.ids = Merge::new(self.ids.iter().copied(), pending.into_iter()).collect();
+    }
+
+    /// Returns whether the ID was present
+    pub fn remove(&mut self, id: u64) -> bool {
+        match self.ids.binary_search(&id) {
+            Ok(index) => {
+                self.ids.remove(index);
+                true
+            }
+            Err(_) => self.pending.remove(&id),
+        }
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.ids.binary_search(&id).is_ok() || self.pending.contains(&id)
+    }
+
+    pub fn len(&self) -> u64 {
+        (self.ids.len() + self.pending.len()) as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty() && self.pending.is_empty()
+    }
+
+    /// All IDs, ascending
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.range(0..=u64::MAX)
+    }
+
+    /// The IDs within `ids`, ascending
+    pub fn range(&self, ids: RangeInclusive<u64>) -> impl Iterator<Item = u64> + '_ {
+        let start = self.ids.partition_point(|id| id < ids.start());
+        let end = self.ids.partition_point(|id| id <= ids.end());
+        let mut pending: Vec<u64> = self
+            .pending
+            .iter()
+            .copied()
+            .filter(|id| ids.contains(id))
+            .collect();
+        pending.sort_unstable();
+        Merge::new(
+            self.ids[start..end.max(start)].iter().copied(),
+            pending.into_iter(),
+        )
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        self.merge(other, true, true, true)
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.merge(other, false, false, true)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        self.merge(other, true, false, false)
+    }
+
+    /// Merges the sorted IDs, keeping those only in `self`, only in
+    /// `other`, or in both as requested
+    fn merge(&self, other: &Self, only_self: bool, only_other: bool, both: bool) -> Self {
+        let mut ids = vec![];
+        let (mut a, mut b) = (self.iter().peekable(), other.iter().peekable());
+        loop {
+            match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) if x == y => {
+                    if both {
+                        ids.push(*x);
+                    }
+                    a.next();
+                    b.next();
+                }
+                (Some(x), y) if y.is_none_or(|y| x < y) => {
+                    if only_self {
+                        ids.push(*x);
+                    }
+                    a.next();
+                }
+                (_, Some(y)) => {
+                    if only_other {
+                        ids.push(*y);
+                    }
+                    b.next();
+                }
+                _ => break,
+            }
+        }
+        SparseIdSet {
+            ids,
+            pending: HashSet::new(),
+        }
+    }
+
+    /// How many bitmap pages the IDs would occupy
+    fn pages(&self) -> usize {
+        let mut pages = 0;
+        let mut last = None;
+        for id in self.iter() {
+            let page = id / PAGE_BITS;
+            if page < MAX_PAGES && last != Some(page) {
+                pages += 1;
+                last = Some(page);
+            }
+        }
+        pages
+    }
+}
+
+impl PartialEq for SparseIdSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for SparseIdSet {}
+
+/// Interleaves two ascending iterators of distinct IDs
+struct Merge<A: Iterator<Item = u64>, B: Iterator<Item = u64>> {
+    a: Peekable<A>,
+    b: Peekable<B>,
+}
+
+impl<A: Iterator<Item = u64>, B: Iterator<Item = u64>> Merge<A, B> {
+    fn new(a: A, b: B) -> Self {
+        Merge {
+            a: a.peekable(),
+            b: b.peekable(),
+        }
+    }
+}
+
+impl<A: Iterator<Item = u64>, B: Iterator<Item = u64>> Iterator for Merge<A, B> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.a.peek(), self.b.peek()) {
+            (Some(a), Some(b)) if b < a => self.b.next(),
+            (Some(_), _) => self.a.next(),
+            (None, _) => self.b.next(),
+        }
+    }
+}
+
+impl FromIterator<u64> for SparseIdSet {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        let mut ids: Vec<u64> = iter.into_iter().collect();
+        ids.sort_unstable();
+        ids.dedup();
+        SparseIdSet {
+            ids,
+            pending: HashSet::new(),
+        }
+    }
+}
+
+/// A bitmap of IDs, in pages of 8 KiB
+#[derive(Debug, Default, Clone)]
+pub struct DenseIdSet {
+    pages: Vec<Option<Page>>,
+    outliers: SparseIdSet,
+    len: u64,
+}
+
+impl DenseIdSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether the ID was not yet present
+    pub fn insert(&mut self, id: u64) -> bool {
+        let page = id / PAGE_BITS;
+        if page >= MAX_PAGES {
+            let inserted = self.outliers.insert(id);
+            self.len += inserted as u64;
+            return inserted;
+        }
+        let page = page as usize;
+        if page >= self.pages.len() {
+            self.pages.resize(page + 1, None);
+        }
+        let page = self.pages[page].get_or_insert_with(|| Box::new([0; PAGE_WORDS]));
+        let (word, bit) = word_and_bit(id);
+        let inserted = page[word] & bit == 0;
+        page[word] |= bit;
+        self.len += inserted as u64;
+        inserted
+    }
+
+    /// Returns whether the ID was present; emptied pages stay allocated
+    pub fn remove(&mut self, id: u64) -> bool {
+        let page = id / PAGE_BITS;
+        let removed = if page >= MAX_PAGES {
+            self.outliers.remove(id)
+        } else if let Some(Some(page)) = self.pages.get_mut(page as usize) {
+            let (word, bit) = word_and_bit(id);
+            let removed = page[word] & bit != 0;
+            page[word] &= !bit;
+            removed
+        } else {
+            false
+        };
+        self.len -= removed as u64;
+        removed
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        let page = id / PAGE_BITS;
+        if page >= MAX_PAGES {
+            return self.outliers.contains(id);
+        }
+        let (word, bit) = word_and_bit(id);
+        self.pages
+            .get(page as usize)
+            .and_then(Option::as_ref)
+            .is_some_and(|page| page[word] & bit != 0)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// All IDs, ascending
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.range(0..=u64::MAX)
+    }
+
+    /// The IDs within `ids`, ascending
+    pub fn range(&self, ids: RangeInclusive<u64>) -> impl Iterator<Item = u64> + '_ {
+        let (start, end) = (*ids.start(), *ids.end());
+        let first_page = (start / PAGE_BITS).min(MAX_PAGES) as usize;
+        self.pages
+            .iter()
+            .enumerate()
+            .skip(first_page)
+            .filter_map(|(index, page)| Some((index as u64 * PAGE_BITS, page.as_ref()?)))
+            .flat_map(|(base, page)| {
+                page.iter().enumerate().flat_map(move |(word, bits)| {
+                    Bits(*bits).map(move |bit| base + word as u64 * 64 + bit)
+                })
+            })
+            .skip_while(move |id| *id < start)
+            .take_while(move |id| *id <= end)
+            .chain(self.outliers.range(ids))
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a | b, SparseIdSet::union)
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a & b, SparseIdSet::intersection)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a & !b, SparseIdSet::difference)
+    }
+
+    /// Combines the bitmaps word by word; `op(0, 0)` must be 0
+    fn combine(
+        &self,
+        other: &Self,
+        op: fn(u64, u64) -> u64,
+        outliers: fn(&SparseIdSet, &SparseIdSet) -> SparseIdSet,
+    ) -> Self {
+        let outliers = outliers(&self.outliers, &other.outliers);
+        let mut len = outliers.len();
+        let mut pages = vec![];
+        for index in 0..self.pages.len().max(other.pages.len()) {
+            let a = self.pages.get(index).and_then(Option::as_ref);
+            let b = other.pages.get(index).and_then(Option::as_ref);
+            if a.is_none() && b.is_none() {
+                pages.push(None);
+                continue;
+            }
+            let mut page = Box::new([0; PAGE_WORDS]);
+            let mut count = 0;
+            for (word, result) in page.iter_mut().enumerate() {
+                *result = op(a.map_or(0, |a| a[word]), b.map_or(0, |b| b[word]));
+                count += result.count_ones() as u64;
+            }
+            len += count;
+            pages.push((count > 0).then_some(page));
+        }
+        while pages.last().is_some_and(Option::is_none) {
+            pages.pop();
+        }
+        DenseIdSet {
+            pages,
+            outliers,
+            len,
+        }
+    }
+}
+
+impl FromIterator<u64> for DenseIdSet {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        let mut set = DenseIdSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<u64> for DenseIdSet {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, iter: I) {
+        for id in iter {
+            self.insert(id);
+        }
+    }
+}
+
+impl PartialEq for DenseIdSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for DenseIdSet {}
+
+fn word_and_bit(id: u64) -> (usize, u64) {
+    let offset = id % PAGE_BITS;
+    ((offset / 64) as usize, 1 << (offset % 64))
+}
+
+/// A set of IDs that starts sparse, and turns into a bitmap once that takes
+/// less memory
+#[derive(Debug, Clone)]
+pub enum IdSet {
+    Sparse(SparseIdSet),
+    Dense(DenseIdSet),
+}
+
+impl Default for IdSet {
+    fn default() -> Self {
+        IdSet::Sparse(SparseIdSet::new())
+    }
+}
+
+impl IdSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A bitmap from the start, for sets known to become large
+    pub fn dense() -> Self {
+        IdSet::Dense(DenseIdSet::new())
+    }
+
+    /// Returns whether the ID was not yet present
+    pub fn insert(&mut self, id: u64) -> bool {
+        match self {
+            IdSet::Sparse(sparse) => {
+                let inserted = sparse.insert(id);
+                let len = sparse.len() as usize;
+                if inserted && len >= MIN_DENSE_LEN && len.is_power_of_two() {
+                    self.optimize();
+                }
+                inserted
+            }
+            IdSet::Dense(dense) => dense.insert(id),
+        }
+    }
+
+    /// Returns whether the ID was present
+    pub fn remove(&mut self, id: u64) -> bool {
+        match self {
+            IdSet::Sparse(sparse) => sparse.remove(id),
+            IdSet::Dense(dense) => dense.remove(id),
+        }
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        match self {
+            IdSet::Sparse(sparse) => sparse.contains(id),
+            IdSet::Dense(dense) => dense.contains(id),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        match self {
+            IdSet::Sparse(sparse) => sparse.len(),
+            IdSet::Dense(dense) => dense.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// All IDs, ascending
+    pub fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        match self {
+            IdSet::Sparse(sparse) => Box::new(sparse.iter()),
+            IdSet::Dense(dense) => Box::new(dense.iter()),
+        }
+    }
+
+    /// The IDs within `ids`, ascending
+    pub fn range(&self, ids: RangeInclusive<u64>) -> Box<dyn Iterator<Item = u64> + '_> {
+        match self {
+            IdSet::Sparse(sparse) => Box::new(sparse.range(ids)),
+            IdSet::Dense(dense) => Box::new(dense.range(ids)),
+        }
+    }
+
+    /// Whether any ID lies within `ids`, such as the range of a
+    /// `KindSummary`
+    pub fn contains_any(&self, ids: RangeInclusive<u64>) -> bool {
+        self.range(ids).next().is_some()
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        self.combine(other, SparseIdSet::union, DenseIdSet::union)
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.combine(other, SparseIdSet::intersection, DenseIdSet::intersection)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        self.combine(other, SparseIdSet::difference, DenseIdSet::difference)
+    }
+
+    fn combine(
+        &self,
+        other: &Self,
+        sparse_op: fn(&SparseIdSet, &SparseIdSet) -> SparseIdSet,
+        dense_op: fn(&DenseIdSet, &DenseIdSet) -> DenseIdSet,
+    ) -> Self {
+        let mut result = match (self, other) {
+            (IdSet::Sparse(a), IdSet::Sparse(b)) => IdSet::Sparse(sparse_op(a, b)),
+            (IdSet::Dense(a), IdSet::Dense(b)) => IdSet::Dense(dense_op(a, b)),
+            (IdSet::Sparse(a), IdSet::Dense(b)) => IdSet::Dense(dense_op(&a.iter().collect(), b)),
+            (IdSet::Dense(a), IdSet::Sparse(b)) => IdSet::Dense(dense_op(a, &b.iter().collect())),
+        };
+        result.optimize();
+        result
+    }
+
+    /// Converts a sparse set into a bitmap if that is smaller, that is if
+    /// there are more than 1024 IDs per page on average
+    pub fn optimize(&mut self) {
+        if let IdSet::Sparse(sparse) = self {
+            let len = sparse.len() as usize;
+            let bitmap_size = sparse.pages() * PAGE_WORDS * 8;
+            if len >= MIN_DENSE_LEN && bitmap_size < len * 8 {
+                *self = IdSet::Dense(sparse.iter().collect());
+            }
+        }
+    }
+}
+
+impl FromIterator<u64> for IdSet {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        let mut set = IdSet::Sparse(iter.into_iter().collect());
+        set.optimize();
+        set
+    }
+}
+
+impl Extend<u64> for IdSet {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, iter: I) {
+        for id in iter {
+            self.insert(id);
+        }
+    }
+}
+
+/// Equal if the IDs are, whatever the representation
+impl PartialEq for IdSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for IdSet {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDS: [u64; 7] = [0, 63, 64, 65_535, 65_536, 1 << 33, u64::MAX - 4];
+
+    #[test]
+    fn test_dense() {
+        let mut set = DenseIdSet::new();
+        assert!(set.is_empty());
+        for id in IDS {
+            assert!(!set.contains(id));
+            assert!(set.insert(id));
+        }
+        assert!(!set.insert(65_536));
+        assert_eq!(set.len(), 7);
+        assert!(IDS.iter().all(|id| set.contains(*id)));
+        assert!(!set.contains(1));
+        assert!(!set.contains((1 << 33) + 1));
+        assert_eq!(set.iter().collect::<Vec<_>>(), IDS);
+        assert_eq!(set.range(64..=1 << 33).collect::<Vec<_>>(), IDS[2..6]);
+        assert_eq!(set.range(1..=62).next(), None);
+
+        assert!(set.remove(63));
+        assert!(!set.remove(63));
+        assert!(set.remove(u64::MAX - 4));
+        assert_eq!(set.len(), 5);
+        assert!(!set.contains(63));
+    }
+
+    #[test]
+    fn test_sparse() {
+        let mut set = SparseIdSet::new();
+        for id in IDS.iter().rev() {
+            assert!(set.insert(*id));
+        }
+        assert!(!set.insert(0));
+        assert_eq!(set.len(), 7);
+        assert_eq!(set.iter().collect::<Vec<_>>(), IDS);
+        assert_eq!(set.range(64..=1 << 33).collect::<Vec<_>>(), IDS[2..6]);
+        assert!(set.range(65..=65_534).next().is_none());
+        assert!(set.remove(64));
+        assert!(!set.contains(64));
+        assert_eq!(set.pages(), 3);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a: SparseIdSet = [1, 2, 3, 70_000].into_iter().collect();
+        let b: SparseIdSet = [2, 3, 4, u64::MAX].into_iter().collect();
+        let union = [1, 2, 3, 4, 70_000, u64::MAX];
+        let intersection = [2, 3];
+        let difference = [1, 70_000];
+        assert_eq!(a.union(&b).iter().collect::<Vec<_>>(), union);
+        assert_eq!(a.intersection(&b).iter().collect::<Vec<_>>(), intersection);
+        assert_eq!(a.difference(&b).iter().collect::<Vec<_>>(), difference);
+
+        let (a, b): (DenseIdSet, DenseIdSet) = (a.iter().collect(), b.iter().collect());
+        assert_eq!(a.union(&b).iter().collect::<Vec<_>>(), union);
+        assert_eq!(a.intersection(&b).iter().collect::<Vec<_>>(), intersection);
+        assert_eq!(a.difference(&b).iter().collect::<Vec<_>>(), difference);
+        assert_eq!(a.intersection(&b).len(), 2);
+        assert_eq!(a.difference(&a), DenseIdSet::new());
+        assert_eq!(a.difference(&a).pages.len(), 0);
+
+        let (a, b) = (IdSet::Dense(a), IdSet::Sparse(b.iter().collect()));
+        assert_eq!(a.union(&b).iter().collect::<Vec<_>>(), union);
+        assert_eq!(b.difference(&a).iter().collect::<Vec<_>>(), [4, u64::MAX]);
+    }
+
+    #[test]
+    fn test_id_set() {
+        let mut set = IdSet::new();
+        for id in 0..MIN_DENSE_LEN as u64 - 1 {
+            set.insert(id * 1_000_000);
+        }
+        set.insert(1);
+        assert!(matches!(set, IdSet::Sparse(_)), "scattered IDs stay sparse");
+        assert!(set.contains_any(999_999..=1_000_000));
+        assert!(!set.contains_any(2..=999_999));
+
+        let ids = 0..2 * MIN_DENSE_LEN as u64;
+        let mut set = IdSet::new();
+        set.extend(ids.clone());
+        assert!(
+            matches!(set, IdSet::Dense(_)),
+            "consecutive IDs become dense"
+        );
+        assert_eq!(set.len(), ids.end);
+        assert_eq!(set, IdSet::Sparse(ids.collect()));
+        assert!(set.remove(5));
+        assert!(!set.contains(5));
+        assert_eq!(set.iter().nth(5), Some(6));
+    }
+
+    #[test]
+    fn test_unordered_inserts() {
+        // Scattered like real node IDs, and in no particular order
+        let mut state = 1u64;
+        let ids: Vec<u64> = (0..200_000)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1);
+                (state >> 20) % 13_000_000_000
+            })
+            .collect();
+        let mut set = IdSet::new();
+        let mut expected = std::collections::BTreeSet::new();
+        for id in &ids {
+            assert_eq!(set.insert(*id), expected.insert(*id));
+        }
+        assert!(matches!(set, IdSet::Sparse(_)), "scattered IDs stay sparse");
+        assert_eq!(set.len(), expected.len() as u64);
+        assert!(ids.iter().all(|id| set.contains(*id)));
+        assert!(set.iter().eq(expected.iter().copied()));
+        assert!(
+            set.range(1_000_000_000..=2_000_000_000)
+                .eq(expected.range(1_000_000_000..=2_000_000_000).copied())
+        );
+
+        let mut sparse = SparseIdSet::new();
+        for id in [5, 1, 9, 3, 7] {
+            sparse.insert(id);
+        }
+        assert!(sparse.remove(3));
+        assert!(!sparse.insert(9));
+        assert_eq!(sparse.iter().collect::<Vec<_>>(), [1, 5, 7, 9]);
+        assert_eq!(sparse, [9, 7, 5, 1].into_iter().collect());
+    }
+}
@@ -0,0 +1,257 @@
+//! A small JSON parser, for reading polygons from GeoJSON files
+//!
+//! Values nest at most `MAX_DEPTH` deep, so that malicious input cannot
+//! exhaust the stack.
+
+/// Deep enough for any GeoJSON geometry inside a `FeatureCollection`
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Returns `None` for invalid JSON
+    pub fn parse(s: &str) -> Option<Json> {
+        let mut parser = Parser {
+            s: s.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos != parser.s.len() {
+            return None;
+        }
+        Some(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.s.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips whitespace, then consumes `byte` if it is next
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.s.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: Json) -> Option<Json> {
+        let end = self.pos + literal.len();
+        if self.s.get(self.pos..end) != Some(literal.as_bytes()) {
+            return None;
+        }
+        self.pos = end;
+        Some(value)
+    }
+
+    /// A value nested within `depth` arrays and objects
+    fn value(&mut self, depth: usize) -> Option<Json> {
+        self.skip_whitespace();
+        match *self.s.get(self.pos)? {
+            b'{' | b'[' if depth >= MAX_DEPTH => None,
+            b'{' => {
+                self.pos += 1;
+                let mut members = vec![];
+                if self.eat(b'}') {
+                    return Some(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    if !self.eat(b':') {
+                        return None;
+                    }
+                    members.push((key, self.value(depth + 1)?));
+                    if self.eat(b'}') {
+                        return Some(Json::Object(members));
+                    }
+                    if !self.eat(b',') {
+                        return None;
+                    }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut values = vec![];
+                if self.eat(b']') {
+                    return Some(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    if self.eat(b']') {
+                        return Some(Json::Array(values));
+                    }
+                    if !self.eat(b',') {
+                        return None;
+                    }
+                }
+            }
+            b'"' => self.string().map(Json::String),
+            b't' => self.literal("true", Json::Bool(true)),
+            b'f' => self.literal("false", Json::Bool(false)),
+            b'n' => self.literal("null", Json::Null),
+            _ => {
+                let start = self.pos;
+                while self
+                    .s
+                    .get(self.pos)
+                    .is_some_and(|b| b"+-.eE".contains(b) || b.is_ascii_digit())
+                {
+                    self.pos += 1;
+                }
+                let number = std::str::from_utf8(&self.s[start..self.pos]).ok()?;
+                number.parse().ok().map(Json::Number)
+            }
+        }
+    }
+
+    /// Four hex digits after `\u`
+    fn code_unit(&mut self) -> Option<u32> {
+        let hex = self.s.get(self.pos + 1..self.pos + 5)?;
+        self.pos += 4;
+        u32::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.s.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            // Runs of unescaped bytes are copied whole, keeping their UTF-8
+            let start = self.pos;
+            while self
+                .s
+                .get(self.pos)
+                .is_some_and(|b| *b != b'"' && *b != b'\\')
+            {
+                self.pos += 1;
+            }
+            s.push_str(std::str::from_utf8(&self.s[start..self.pos]).ok()?);
+            if *self.s.get(self.pos)? == b'"' {
+                break;
+            }
+            self.pos += 1;
+            let unescaped = match *self.s.get(self.pos)? {
+                b'"' => '"',
+                b'\\' => '\\',
+                b'/' => '/',
+                b'b' => '\u{8}',
+                b'f' => '\u{c}',
+                b'n' => '\n',
+                b'r' => '\r',
+                b't' => '\t',
+                b'u' => {
+                    let mut code = self.code_unit()?;
+                    // A high surrogate, and the low one that must follow
+                    if (0xd800..0xdc00).contains(&code) {
+                        if self.s.get(self.pos + 1..self.pos + 3) != Some(b"\\u") {
+                            return None;
+                        }
+                        self.pos += 2;
+                        let low = self.code_unit()?;
+                        if !(0xdc00..0xe000).contains(&low) {
+                            return None;
+                        }
+                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                    }
+                    char::from_u32(code)?
+                }
+                _ => return None,
+            };
+            s.push(unescaped);
+            self.pos += 1;
+        }
+        self.pos += 1;
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let json = Json::parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "d"}} "#).unwrap();
+        assert_eq!(
+            json.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(-25.0),
+                Json::Bool(true),
+                Json::Null
+            ]))
+        );
+        assert_eq!(json.get("b").unwrap().get("c").unwrap().as_str(), Some("d"));
+        assert!(Json::parse("[1,]").is_none());
+        assert!(Json::parse("[1] 2").is_none());
+    }
+
+    #[test]
+    fn test_strings() {
+        let parse = |s: &str| Json::parse(s).map(|json| json.as_str().unwrap().to_string());
+        assert_eq!(
+            parse(r#""é \"q\" \u00e9\/""#).as_deref(),
+            Some("é \"q\" é/")
+        );
+        assert_eq!(parse(r#""\ud83d\ude00""#).as_deref(), Some("😀"));
+        // Escapes of non-ASCII characters, or unpaired surrogates
+        assert!(parse("\"\\é\"").is_none());
+        assert!(parse(r#""\ud83d""#).is_none());
+        assert!(parse(r#""\ude00""#).is_none());
+        assert!(parse(r#""\x""#).is_none());
+        assert!(parse(r#""open"#).is_none());
+    }
+
+    #[test]
+    fn test_depth() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_some());
+        assert!(Json::parse(&nested(MAX_DEPTH + 1)).is_none());
+        assert!(Json::parse(&"[".repeat(1_000_000)).is_none());
+        assert!(Json::parse(&r#"{"a":"#.repeat(1_000_000)).is_none());
+    }
+}
@@ -9,3 +9,37 @@ pub mod parse;
 pub use parse::*;
 pub mod delimited;
 pub mod delta;
+pub mod handler;
+pub mod history;
+pub mod id_set;
+pub mod varint;
+pub mod xml;
+pub use handler::{Handler, apply, apply_parallel};
+pub mod apply_changes;
+pub mod area;
+pub mod check_refs;
+pub mod extract;
+pub mod geojson;
+pub mod geometry;
+pub mod getid;
+mod json;
+pub mod location;
+pub mod o5m;
+pub mod opl;
+pub mod owned;
+pub mod pbf_writer;
+pub mod poly;
+pub mod relation_collector;
+pub mod sort_order;
+pub mod tags_filter;
+pub mod timestamp;
+pub use id_set::IdSet;
+pub use location::{Location, NodeLocationStore};
+pub use pbf_writer::PbfWriter;
+pub use relation_collector::RelationCollector;
+pub use tags_filter::TagsFilter;
+pub use timestamp::Timestamp;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "parquet")]
+pub mod parquet;
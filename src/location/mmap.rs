@@ -0,0 +1,415 @@
+//! Node location stores kept in memory-mapped files
+//!
+//! Both file formats start with an 8 byte magic and a little endian `u64`
+//! entry count, followed by fixed-size little endian entries:
+//!
+//! * `FlatFileLocationStore`: `(lat: i32, lon: i32)` at the index of the node
+//!   ID, with the sign bits flipped so that the zeroes of unwritten (sparse)
+//!   file regions read as unset.
+//! * `SparseFileLocationStore`: `(id: u64, lat: i32, lon: i32)` sorted by ID.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use memmap2::{Mmap, MmapMut};
+
+use super::{Location, NodeLocationStore, collect_locations};
+use crate::BlobReader;
+
+const FLAT_MAGIC: &[u8; 8] = b"OSMLOCF1";
+const SPARSE_MAGIC: &[u8; 8] = b"OSMLOCS1";
+const HEADER_LEN: usize = 16;
+const FLAT_ENTRY_LEN: usize = 8;
+const SPARSE_ENTRY_LEN: usize = 16;
+/// Flat files grow in steps of this many entries
+const FLAT_GROWTH: u64 = 1 << 20;
+
+enum Map {
+    ReadOnly(Mmap),
+    Writable(File, MmapMut),
+}
+
+impl Map {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Map::ReadOnly(map) => map,
+            Map::Writable(_, map) => map,
+        }
+    }
+}
+
+fn read_header(bytes: &[u8], magic: &[u8; 8]) -> io::Result<u64> {
+    if bytes.len() < HEADER_LEN || &bytes[..8] != magic {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a node location file",
+        ));
+    }
+    Ok(u64::from_le_bytes(bytes[8..16].try_into().unwrap()))
+}
+
+fn write_header(bytes: &mut [u8], magic: &[u8; 8], len: u64) {
+    bytes[..8].copy_from_slice(magic);
+    bytes[8..16].copy_from_slice(&len.to_le_bytes());
+}
+
+fn encode_flat(location: Location) -> [u8; FLAT_ENTRY_LEN] {
+    let (lat, lon) = location.to_fixed();
+    let mut entry = [0; FLAT_ENTRY_LEN];
+    entry[..4].copy_from_slice(&(lat ^ i32::MIN).to_le_bytes());
+    entry[4..].copy_from_slice(&(lon ^ i32::MIN).to_le_bytes());
+    entry
+}
+
+fn decode_flat(entry: &[u8]) -> Option<Location> {
+    if entry.iter().all(|&byte| byte == 0) {
+        return None;
+    }
+    let lat = i32::from_le_bytes(entry[..4].try_into().unwrap()) ^ i32::MIN;
+    let lon = i32::from_le_bytes(entry[4..].try_into().unwrap()) ^ i32::MIN;
+    Some(Location::from_fixed(lat, lon))
+}
+
+/// Flat array of locations indexed by node ID, in a file
+///
+/// Takes 8 bytes of address space for every ID up to the highest one set,
+/// but only as much disk and memory as the file system and page cache
+/// need for the regions actually written.
+pub struct FlatFileLocationStore {
+    map: Map,
+    /// Number of entries, i.e. highest ID set + 1
+    len: u64,
+}
+
+impl FlatFileLocationStore {
+    /// Creates an empty store, truncating `path`
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(HEADER_LEN as u64)?;
+        // SAFETY: The file has just been created by us. Concurrent
+        // modification by other processes is not supported.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        write_header(&mut map, FLAT_MAGIC, 0);
+        Ok(FlatFileLocationStore {
+            map: Map::Writable(file, map),
+            len: 0,
+        })
+    }
+
+    /// Opens a store previously written with `create()` for lookups
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: Concurrent modification of the file is not supported.
+        let map = unsafe { Mmap::map(&file)? };
+        let len = read_header(&map, FLAT_MAGIC)?;
+        if map.len() < HEADER_LEN + len as usize * FLAT_ENTRY_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "node location file is truncated",
+            ));
+        }
+        Ok(FlatFileLocationStore {
+            map: Map::ReadOnly(map),
+            len,
+        })
+    }
+
+    /// Creates a store at `path` from all nodes in a file
+    pub fn build<R: Read, P: AsRef<Path>>(reader: &mut BlobReader<R>, path: P) -> io::Result<Self> {
+        let mut store = Self::create(path)?;
+        collect_locations(reader, &mut store);
+        store.flush()?;
+        Ok(store)
+    }
+
+    /// Number of entries, i.e. highest ID set + 1
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Writes the entry count and syncs the mapping to disk
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.map {
+            Map::ReadOnly(_) => Ok(()),
+            Map::Writable(file, map) => {
+                write_header(map, FLAT_MAGIC, self.len);
+                map.flush()?;
+                file.sync_all()
+            }
+        }
+    }
+
+    fn ensure_capacity(&mut self, entries: u64) -> io::Result<()> {
+        let Map::Writable(file, map) = &mut self.map else {
+            panic!("FlatFileLocationStore opened read-only");
+        };
+        let needed = HEADER_LEN + entries as usize * FLAT_ENTRY_LEN;
+        if needed > map.len() {
+            let entries = entries.div_ceil(FLAT_GROWTH) * FLAT_GROWTH;
+            file.set_len(HEADER_LEN as u64 + entries * FLAT_ENTRY_LEN as u64)?;
+            // SAFETY: See create()
+            *map = unsafe { MmapMut::map_mut(&*file)? };
+        }
+        Ok(())
+    }
+}
+
+impl NodeLocationStore for FlatFileLocationStore {
+    /// Panics if the store was opened read-only or the file cannot grow
+    fn set(&mut self, id: u64, location: Location) {
+        self.ensure_capacity(id + 1)
+            .expect("cannot grow node location file");
+        self.len = self.len.max(id + 1);
+        if let Map::Writable(_, map) = &mut self.map {
+            let offset = HEADER_LEN + id as usize * FLAT_ENTRY_LEN;
+            map[offset..offset + FLAT_ENTRY_LEN].copy_from_slice(&encode_flat(location));
+        }
+    }
+
+    fn get(&self, id: u64) -> Option<Location> {
+        if id >= self.len {
+            return None;
+        }
+        let offset = HEADER_LEN + id as usize * FLAT_ENTRY_LEN;
+        decode_flat(&self.map.bytes()[offset..offset + FLAT_ENTRY_LEN])
+    }
+
+    fn prepare(&mut self) {
+        self.flush().expect("cannot flush node location file");
+    }
+}
+
+fn sparse_entry_id(entry: &[u8]) -> u64 {
+    u64::from_le_bytes(entry[..8].try_into().unwrap())
+}
+
+/// Locations sorted by node ID, in a file
+///
+/// Takes 16 bytes per node, so it is preferable to `FlatFileLocationStore`
+/// for extracts with few, widely spread IDs. Locations are appended while
+/// building and sorted by `prepare()`, which needs memory for all entries
+/// unless they were added in ascending order.
+pub struct SparseFileLocationStore {
+    writer: Option<BufWriter<File>>,
+    map: Option<Map>,
+    len: u64,
+    last_id: Option<u64>,
+    sorted: bool,
+}
+
+impl SparseFileLocationStore {
+    /// Creates an empty store, truncating `path`
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut writer = BufWriter::new(file);
+        let mut header = [0; HEADER_LEN];
+        write_header(&mut header, SPARSE_MAGIC, 0);
+        writer.write_all(&header)?;
+        Ok(SparseFileLocationStore {
+            writer: Some(writer),
+            map: None,
+            len: 0,
+            last_id: None,
+            sorted: true,
+        })
+    }
+
+    /// Opens a store previously written with `create()` for lookups
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: Concurrent modification of the file is not supported.
+        let map = unsafe { Mmap::map(&file)? };
+        let len = read_header(&map, SPARSE_MAGIC)?;
+        if map.len() < HEADER_LEN + len as usize * SPARSE_ENTRY_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "node location file is truncated",
+            ));
+        }
+        Ok(SparseFileLocationStore {
+            writer: None,
+            map: Some(Map::ReadOnly(map)),
+            len,
+            last_id: None,
+            sorted: true,
+        })
+    }
+
+    /// Creates a store at `path` from all nodes in a file
+    pub fn build<R: Read, P: AsRef<Path>>(reader: &mut BlobReader<R>, path: P) -> io::Result<Self> {
+        let mut store = Self::create(path)?;
+        collect_locations(reader, &mut store);
+        store.finish()?;
+        Ok(store)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Completes writing: sorts the entries, writes the entry count and
+    /// maps the file for lookups
+    pub fn finish(&mut self) -> io::Result<()> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        // SAFETY: The file has been created by us. Concurrent modification
+        // by other processes is not supported.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        write_header(&mut map, SPARSE_MAGIC, self.len);
+        if !self.sorted {
+            let entries = &mut map[HEADER_LEN..];
+            let mut sorted: Vec<[u8; SPARSE_ENTRY_LEN]> = entries
+                .chunks_exact(SPARSE_ENTRY_LEN)
+                .map(|entry| entry.try_into().unwrap())
+                .collect();
+            sorted.sort_by_key(|entry| sparse_entry_id(entry));
+            for (dst, src) in entries.chunks_exact_mut(SPARSE_ENTRY_LEN).zip(sorted) {
+                dst.copy_from_slice(&src);
+            }
+            self.sorted = true;
+        }
+        map.flush()?;
+        self.map = Some(Map::Writable(file, map));
+        Ok(())
+    }
+
+    fn entry(&self, index: usize) -> &[u8] {
+        let bytes = self.map.as_ref().unwrap().bytes();
+        let offset = HEADER_LEN + index * SPARSE_ENTRY_LEN;
+        &bytes[offset..offset + SPARSE_ENTRY_LEN]
+    }
+}
+
+impl NodeLocationStore for SparseFileLocationStore {
+    /// Panics if the store was opened read-only, or on write errors
+    fn set(&mut self, id: u64, location: Location) {
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("SparseFileLocationStore is not writable");
+        let (lat, lon) = location.to_fixed();
+        let mut entry = [0; SPARSE_ENTRY_LEN];
+        entry[..8].copy_from_slice(&id.to_le_bytes());
+        entry[8..12].copy_from_slice(&lat.to_le_bytes());
+        entry[12..].copy_from_slice(&lon.to_le_bytes());
+        writer
+            .write_all(&entry)
+            .expect("cannot write node location file");
+        if self.last_id.is_some_and(|last_id| last_id >= id) {
+            self.sorted = false;
+        }
+        self.last_id = Some(id);
+        self.len += 1;
+    }
+
+    fn get(&self, id: u64) -> Option<Location> {
+        assert!(
+            self.map.is_some(),
+            "SparseFileLocationStore::get() before prepare()"
+        );
+        // Binary search for the last entry with this ID
+        let (mut low, mut high) = (0, self.len as usize);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if sparse_entry_id(self.entry(mid)) <= id {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let entry = self.entry(low.checked_sub(1)?);
+        if sparse_entry_id(entry) != id {
+            return None;
+        }
+        let lat = i32::from_le_bytes(entry[8..12].try_into().unwrap());
+        let lon = i32::from_le_bytes(entry[12..].try_into().unwrap());
+        Some(Location::from_fixed(lat, lon))
+    }
+
+    fn prepare(&mut self) {
+        self.finish().expect("cannot finish node location file");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("osm_pbf_iter-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_flat_file_store() {
+        let path = temp_path("flat");
+        let mut store = FlatFileLocationStore::create(&path).unwrap();
+        store.set(3, Location::new(0.0, 0.0));
+        store.set(3_000_000, Location::new(-12.5, 100.25));
+        store.set(1, Location::new(1.0, -1.0));
+        store.prepare();
+        assert_eq!(store.len(), 3_000_001);
+        assert_eq!(store.get(3), Some(Location::new(0.0, 0.0)));
+        assert_eq!(store.get(2), None);
+        drop(store);
+
+        let store = FlatFileLocationStore::open(&path).unwrap();
+        assert_eq!(store.get(1), Some(Location::new(1.0, -1.0)));
+        assert_eq!(store.get(3), Some(Location::new(0.0, 0.0)));
+        assert_eq!(store.get(3_000_000), Some(Location::new(-12.5, 100.25)));
+        assert_eq!(store.get(3_000_001), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sparse_file_store() {
+        let path = temp_path("sparse");
+        let mut store = SparseFileLocationStore::create(&path).unwrap();
+        store.set(1 << 40, Location::new(-12.5, 100.25));
+        store.set(3, Location::new(0.0, 0.0));
+        store.set(7, Location::new(1.0, -1.0));
+        store.set(3, Location::new(2.0, -2.0));
+        store.prepare();
+        assert_eq!(store.get(3), Some(Location::new(2.0, -2.0)));
+        assert_eq!(store.get(2), None);
+        drop(store);
+
+        let store = SparseFileLocationStore::open(&path).unwrap();
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.get(0), None);
+        assert_eq!(store.get(3), Some(Location::new(2.0, -2.0)));
+        assert_eq!(store.get(7), Some(Location::new(1.0, -1.0)));
+        assert_eq!(store.get(1 << 40), Some(Location::new(-12.5, 100.25)));
+        assert_eq!(store.get(u64::MAX), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_invalid() {
+        let path = temp_path("invalid");
+        std::fs::write(&path, b"not a location file").unwrap();
+        assert!(FlatFileLocationStore::open(&path).is_err());
+        assert!(SparseFileLocationStore::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
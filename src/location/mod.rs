@@ -0,0 +1,288 @@
+//! Node locations, and stores for looking them up by node ID
+//!
+//! Ways only reference their nodes, so assembling their geometries requires
+//! remembering the location of every node in a first pass.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::{BlobReader, Handler, Node, Way, apply};
+
+pub mod mmap;
+
+/// Scale of the fixed-point representation, 1e-7 degrees per unit
+const PRECISION: f64 = 1.0e7;
+
+/// Coordinates in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Location {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Location { lat, lon }
+    }
+
+    /// From 1e-7 degree units, as used by the stores
+    pub fn from_fixed(lat: i32, lon: i32) -> Self {
+        Location {
+            lat: lat as f64 / PRECISION,
+            lon: lon as f64 / PRECISION,
+        }
+    }
+
+    /// To 1e-7 degree units, as used by the stores
+    pub fn to_fixed(&self) -> (i32, i32) {
+        (
+            (self.lat * PRECISION).round() as i32,
+            (self.lon * PRECISION).round() as i32,
+        )
+    }
+}
+
+impl<'a> From<&Node<'a>> for Location {
+    fn from(node: &Node<'a>) -> Self {
+        Location::new(node.lat, node.lon)
+    }
+}
+
+/// Maps node IDs to locations
+pub trait NodeLocationStore {
+    fn set(&mut self, id: u64, location: Location);
+
+    fn get(&self, id: u64) -> Option<Location>;
+
+    /// Called once all locations have been `set()`, before the first `get()`
+    fn prepare(&mut self) {}
+
+    fn add_node(&mut self, node: &Node) {
+        self.set(node.id, Location::from(node));
+    }
+
+    /// Looks up all `refs`, or returns the IDs of those not found
+    fn locations<I: IntoIterator<Item = u64>>(&self, refs: I) -> Result<Vec<Location>, Vec<u64>>
+    where
+        Self: Sized,
+    {
+        let mut locations = vec![];
+        let mut missing = vec![];
+        for id in refs {
+            match self.get(id) {
+                Some(location) => locations.push(location),
+                None => missing.push(id),
+            }
+        }
+        if missing.is_empty() {
+            Ok(locations)
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// The geometry of a way, or the IDs of its nodes not found
+    fn way_locations(&self, way: &Way) -> Result<Vec<Location>, Vec<u64>>
+    where
+        Self: Sized,
+    {
+        self.locations(way.refs().map(|id| id as u64))
+    }
+}
+
+/// Handler that adds every node to a store
+pub struct StoreLocations<'s, S: NodeLocationStore>(pub &'s mut S);
+
+impl<'s, S: NodeLocationStore> Handler for StoreLocations<'s, S> {
+    fn node(&mut self, node: &Node) {
+        self.0.add_node(node);
+    }
+}
+
+/// Adds all nodes of a file to a store, and prepares it for lookups
+pub fn collect_locations<R: Read, S: NodeLocationStore>(reader: &mut BlobReader<R>, store: &mut S) {
+    apply(reader, &mut StoreLocations(&mut *store));
+    store.prepare();
+}
+
+/// Store backed by a `HashMap`, for small extracts in arbitrary order
+#[derive(Debug, Default, Clone)]
+pub struct HashLocationStore {
+    locations: HashMap<u64, (i32, i32)>,
+}
+
+impl HashLocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+}
+
+impl NodeLocationStore for HashLocationStore {
+    fn set(&mut self, id: u64, location: Location) {
+        self.locations.insert(id, location.to_fixed());
+    }
+
+    fn get(&self, id: u64) -> Option<Location> {
+        self.locations
+            .get(&id)
+            .map(|&(lat, lon)| Location::from_fixed(lat, lon))
+    }
+}
+
+/// Store backed by a vector sorted by ID, for extracts
+///
+/// Cheaper than `HashLocationStore` in memory. Sorting is skipped if nodes
+/// were added in ascending order, as they appear in sorted files.
+#[derive(Debug, Clone)]
+pub struct SortedLocationStore {
+    entries: Vec<(u64, i32, i32)>,
+    sorted: bool,
+}
+
+impl SortedLocationStore {
+    pub fn new() -> Self {
+        SortedLocationStore {
+            entries: vec![],
+            sorted: true,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Default for SortedLocationStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeLocationStore for SortedLocationStore {
+    fn set(&mut self, id: u64, location: Location) {
+        if self.entries.last().is_some_and(|&(last, _, _)| last >= id) {
+            self.sorted = false;
+        }
+        let (lat, lon) = location.to_fixed();
+        self.entries.push((id, lat, lon));
+    }
+
+    fn get(&self, id: u64) -> Option<Location> {
+        assert!(self.sorted, "SortedLocationStore::get() before prepare()");
+        self.entries
+            .binary_search_by_key(&id, |&(id, _, _)| id)
+            .ok()
+            .map(|i| Location::from_fixed(self.entries[i].1, self.entries[i].2))
+    }
+
+    fn prepare(&mut self) {
+        if !self.sorted {
+            // Stable, so that the last location set for an ID wins
+            self.entries.sort_by_key(|&(id, _, _)| id);
+            self.entries.reverse();
+            self.entries.dedup_by_key(|&mut (id, _, _)| id);
+            self.entries.reverse();
+            self.sorted = true;
+        }
+    }
+}
+
+/// Store backed by a flat array indexed by node ID, for planets
+///
+/// Takes 8 bytes for every ID up to the highest one set.
+#[derive(Debug, Default, Clone)]
+pub struct DenseLocationStore {
+    locations: Vec<(i32, i32)>,
+}
+
+/// Marks unset entries, as no valid latitude is this large
+const UNSET: (i32, i32) = (i32::MAX, i32::MAX);
+
+impl DenseLocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(max_id: u64) -> Self {
+        DenseLocationStore {
+            locations: Vec::with_capacity(max_id as usize + 1),
+        }
+    }
+}
+
+impl NodeLocationStore for DenseLocationStore {
+    fn set(&mut self, id: u64, location: Location) {
+        let index = id as usize;
+        if index >= self.locations.len() {
+            self.locations.resize(index + 1, UNSET);
+        }
+        self.locations[index] = location.to_fixed();
+    }
+
+    fn get(&self, id: u64) -> Option<Location> {
+        match self.locations.get(id as usize) {
+            Some(&UNSET) | None => None,
+            Some(&(lat, lon)) => Some(Location::from_fixed(lat, lon)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_store<S: NodeLocationStore>(mut store: S) {
+        store.set(7, Location::new(1.5, -2.25));
+        store.set(3, Location::new(-89.9999999, 179.9999999));
+        store.set(5, Location::new(0.0, 0.0));
+        store.set(7, Location::new(1.0, 2.0));
+        store.prepare();
+        assert_eq!(store.get(3), Some(Location::new(-89.9999999, 179.9999999)));
+        assert_eq!(store.get(5), Some(Location::new(0.0, 0.0)));
+        assert_eq!(store.get(7), Some(Location::new(1.0, 2.0)));
+        assert_eq!(store.get(4), None);
+        assert_eq!(store.get(1 << 40), None);
+        assert_eq!(
+            store.locations([3, 5]),
+            Ok(vec![
+                Location::new(-89.9999999, 179.9999999),
+                Location::new(0.0, 0.0)
+            ])
+        );
+        assert_eq!(store.locations([3, 4, 5, 6]), Err(vec![4, 6]));
+    }
+
+    #[test]
+    fn test_hash_store() {
+        check_store(HashLocationStore::new());
+    }
+
+    #[test]
+    fn test_sorted_store() {
+        check_store(SortedLocationStore::new());
+        check_store(SortedLocationStore::default());
+
+        // Nodes in ascending order need no prepare()
+        let mut store = SortedLocationStore::default();
+        store.set(1, Location::new(1.0, 2.0));
+        store.set(2, Location::new(3.0, 4.0));
+        assert_eq!(store.get(2), Some(Location::new(3.0, 4.0)));
+    }
+
+    #[test]
+    fn test_dense_store() {
+        check_store(DenseLocationStore::new());
+    }
+}
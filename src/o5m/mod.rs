@@ -0,0 +1,263 @@
+//! o5m (`.o5m`) support, the format of osmconvert and osmfilter
+//!
+//! Datasets are delta coded against the previous dataset, and strings
+//! refer back to a table of the 15000 most recently written ones. Both are
+//! reset at every reset byte, which the writer emits whenever the element
+//! type changes.
+
+use std::io;
+
+use crate::xml::invalid_data;
+
+pub mod reader;
+pub use self::reader::O5mReader;
+pub mod writer;
+pub use self::writer::{O5mWriter, write_o5m};
+
+pub(crate) const NODE: u8 = 0x10;
+pub(crate) const WAY: u8 = 0x11;
+pub(crate) const RELATION: u8 = 0x12;
+pub(crate) const BOUNDING_BOX: u8 = 0xdb;
+pub(crate) const FILE_TIMESTAMP: u8 = 0xdc;
+pub(crate) const HEADER: u8 = 0xe0;
+pub(crate) const END_OF_FILE: u8 = 0xfe;
+pub(crate) const RESET: u8 = 0xff;
+
+/// Entries of the string table
+pub(crate) const STRING_TABLE_SIZE: usize = 15000;
+/// Longer strings are never put into the string table
+pub(crate) const MAX_TABLE_STRING: usize = 250;
+
+/// Locations are stored in units of 1e-7 degrees
+pub(crate) const LOCATION_SCALE: f64 = 1e7;
+
+/// The running value of a delta coded field
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Delta(i64);
+
+impl Delta {
+    pub fn decode(&mut self, delta: i64) -> i64 {
+        self.0 = self.0.wrapping_add(delta);
+        self.0
+    }
+
+    pub fn encode(&mut self, value: i64) -> i64 {
+        let delta = value.wrapping_sub(self.0);
+        self.0 = value;
+        delta
+    }
+}
+
+/// Every delta coded field of a file
+#[derive(Debug, Default)]
+pub(crate) struct Deltas {
+    pub id: Delta,
+    pub timestamp: Delta,
+    pub changeset: Delta,
+    pub lon: Delta,
+    pub lat: Delta,
+    pub node_ref: Delta,
+    /// Per member type
+    pub member_ids: [Delta; 3],
+}
+
+/// What a string table entry holds, each part terminated by a 0 byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Entry {
+    /// Key and value of a tag
+    Pair,
+    /// Varint uid and user name
+    User,
+    /// Member type digit followed by the role
+    Role,
+}
+
+impl Entry {
+    fn terminators(self) -> usize {
+        match self {
+            Entry::Pair | Entry::User => 2,
+            Entry::Role => 1,
+        }
+    }
+
+    /// Whether an entry of this kind is put into the string table
+    pub fn fits_table(self, raw: &[u8]) -> bool {
+        raw.len() - self.terminators() <= MAX_TABLE_STRING
+    }
+
+    /// Length of the entry at the start of `data`, terminators included
+    fn len(self, data: &[u8]) -> Option<usize> {
+        let mut pos = 0;
+        if self == Entry::User {
+            let (_, len) = read_varint(&data[pos..])?;
+            if data.get(len) != Some(&0) {
+                return None;
+            }
+            pos = len + 1;
+        }
+        let parts = if self == Entry::Pair { 2 } else { 1 };
+        for _ in 0..parts {
+            pos += data[pos..].iter().position(|b| *b == 0)? + 1;
+        }
+        Some(pos)
+    }
+}
+
+/// The strings that can be referred back to, most recent last
+pub(crate) struct StringTable {
+    entries: Vec<Vec<u8>>,
+    next: usize,
+}
+
+impl StringTable {
+    pub fn new() -> Self {
+        StringTable {
+            entries: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.next = 0;
+    }
+
+    pub fn add(&mut self, raw: &[u8]) {
+        if self.entries.len() < STRING_TABLE_SIZE {
+            self.entries.push(raw.to_vec());
+        } else {
+            self.entries[self.next] = raw.to_vec();
+        }
+        self.next = (self.next + 1) % STRING_TABLE_SIZE;
+    }
+
+    /// Looks up a back reference, where 1 is the most recent entry
+    pub fn get(&self, reference: u64) -> Option<&[u8]> {
+        let reference = reference as usize;
+        if reference == 0 || reference > self.entries.len() {
+            return None;
+        }
+        let index = (self.next + STRING_TABLE_SIZE - reference) % STRING_TABLE_SIZE;
+        Some(&self.entries[index])
+    }
+
+    /// Reads an inline entry or a reference, returning the raw entry
+    ///
+    /// The table is shared by all kinds of entries, so a reference must
+    /// point at one of the expected kind.
+    pub fn read(&mut self, data: &mut &[u8], entry: Entry) -> io::Result<Vec<u8>> {
+        let reference = read_uint(data)?;
+        if reference != 0 {
+            return self
+                .get(reference)
+                .filter(|raw| entry.len(raw) == Some(raw.len()))
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| invalid_data("invalid string reference"));
+        }
+        let len = entry
+            .len(data)
+            .ok_or_else(|| invalid_data("unterminated string"))?;
+        let (raw, rest) = data.split_at(len);
+        *data = rest;
+        if entry.fits_table(raw) {
+            self.add(raw);
+        }
+        Ok(raw.to_vec())
+    }
+}
+
+/// Decodes one varint, returning it together with the number of bytes read
+pub(crate) fn read_varint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (i, byte) in data.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+pub(crate) fn read_uint(data: &mut &[u8]) -> io::Result<u64> {
+    let (value, len) = read_varint(data).ok_or_else(|| invalid_data("truncated number"))?;
+    *data = &data[len..];
+    Ok(value)
+}
+
+pub(crate) fn read_sint(data: &mut &[u8]) -> io::Result<i64> {
+    let value = read_uint(data)?;
+    Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+}
+
+pub(crate) fn write_uint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub(crate) fn write_sint(out: &mut Vec<u8>, value: i64) {
+    write_uint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers() {
+        let mut out = vec![];
+        for value in [0, -1, 1, -64, 64, i64::MIN, i64::MAX] {
+            write_sint(&mut out, value);
+        }
+        write_uint(&mut out, 300);
+        assert_eq!(&out[..5], &[0x00, 0x01, 0x02, 0x7f, 0x80]);
+
+        let mut data = &out[..];
+        for value in [0, -1, 1, -64, 64, i64::MIN, i64::MAX] {
+            assert_eq!(read_sint(&mut data).unwrap(), value);
+        }
+        assert_eq!(read_uint(&mut data).unwrap(), 300);
+        assert!(data.is_empty());
+        assert!(read_uint(&mut data).is_err());
+    }
+
+    #[test]
+    fn test_string_table() {
+        let mut strings = StringTable::new();
+        let data = b"\0oneway\0yes\0\x01\0\0\0anonymous\0\x02";
+        let mut data = &data[..];
+        assert_eq!(
+            strings.read(&mut data, Entry::Pair).unwrap(),
+            b"oneway\0yes\0"
+        );
+        assert_eq!(
+            strings.read(&mut data, Entry::Pair).unwrap(),
+            b"oneway\0yes\0"
+        );
+        assert_eq!(
+            strings.read(&mut data, Entry::User).unwrap(),
+            b"\0\0anonymous\0"
+        );
+        assert_eq!(
+            strings.read(&mut data, Entry::Pair).unwrap(),
+            b"oneway\0yes\0"
+        );
+        assert!(data.is_empty());
+
+        // A role referenced as a tag, or a tag as a user
+        let mut data = &b"\x001r\0\x01"[..];
+        strings.read(&mut data, Entry::Role).unwrap();
+        assert!(strings.read(&mut data, Entry::Pair).is_err());
+        let mut data = &b"\x03"[..];
+        assert!(strings.read(&mut data, Entry::User).is_err());
+
+        for i in 0..STRING_TABLE_SIZE {
+            strings.add(format!("{}\0", i).as_bytes());
+        }
+        assert_eq!(strings.get(1), Some(&b"14999\0"[..]));
+        assert_eq!(strings.get(STRING_TABLE_SIZE as u64), Some(&b"0\0"[..]));
+        assert_eq!(strings.get(STRING_TABLE_SIZE as u64 + 1), None);
+    }
+}
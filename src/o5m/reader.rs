@@ -0,0 +1,358 @@
+use std::io::{self, ErrorKind, Read};
+
+use super::{
+    BOUNDING_BOX, Deltas, END_OF_FILE, Entry, FILE_TIMESTAMP, HEADER, LOCATION_SCALE, NODE,
+    RELATION, RESET, StringTable, WAY, read_sint, read_uint, read_varint,
+};
+use crate::HeaderBBox;
+use crate::RelationMemberType;
+use crate::Timestamp;
+use crate::owned::{Element, Info, Member, Node, Relation, Way};
+use crate::xml::invalid_data;
+
+/// Reads the elements of an o5m file, or of an o5c change file
+///
+/// Elements of a change file that carry no data besides their metadata are
+/// deletions, and are returned with `visible` set to false.
+pub struct O5mReader<R> {
+    read: R,
+    buf: Vec<u8>,
+    strings: StringTable,
+    deltas: Deltas,
+    bounds: Option<HeaderBBox>,
+    timestamp: Option<Timestamp>,
+    done: bool,
+}
+
+impl<R: Read> O5mReader<R> {
+    pub fn new(read: R) -> Self {
+        O5mReader {
+            read,
+            buf: vec![],
+            strings: StringTable::new(),
+            deltas: Deltas::default(),
+            bounds: None,
+            timestamp: None,
+            done: false,
+        }
+    }
+
+    /// The bounding box dataset, once read past it
+    pub fn bounds(&self) -> Option<&HeaderBBox> {
+        self.bounds.as_ref()
+    }
+
+    /// The file timestamp dataset, once read past it
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.read.read_exact(&mut byte) {
+            Ok(()) => Ok(Some(byte[0])),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads the varint length of a dataset
+    fn read_len(&mut self) -> io::Result<usize> {
+        let mut bytes = vec![];
+        loop {
+            let byte = self
+                .read_byte()?
+                .ok_or_else(|| invalid_data("truncated dataset length"))?;
+            bytes.push(byte);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        read_varint(&bytes)
+            .map(|(len, _)| len as usize)
+            .ok_or_else(|| invalid_data("invalid dataset length"))
+    }
+
+    fn read_element(&mut self) -> io::Result<Option<Element>> {
+        loop {
+            let dataset = match self.read_byte()? {
+                None | Some(END_OF_FILE) => return Ok(None),
+                Some(dataset) => dataset,
+            };
+            if dataset == RESET {
+                self.strings.clear();
+                self.deltas = Deltas::default();
+                continue;
+            }
+            // The remaining single byte datasets are of no interest
+            if dataset >= 0xf0 {
+                continue;
+            }
+
+            let len = self.read_len()?;
+            let mut buf = std::mem::take(&mut self.buf);
+            buf.resize(len, 0);
+            self.read.read_exact(&mut buf)?;
+            let mut data = &buf[..];
+            let result = match dataset {
+                NODE => self.parse_node(&mut data).map(Some),
+                WAY => self.parse_way(&mut data).map(Some),
+                RELATION => self.parse_relation(&mut data).map(Some),
+                BOUNDING_BOX => self.parse_bounds(&mut data).map(|_| None),
+                FILE_TIMESTAMP => read_timestamp(&mut data).map(|timestamp| {
+                    self.timestamp = Some(timestamp);
+                    None
+                }),
+                HEADER if buf == b"o5m2" || buf == b"o5c2" => Ok(None),
+                HEADER => Err(invalid_data("not an o5m file")),
+                _ => Ok(None),
+            };
+            self.buf = buf;
+            if let Some(element) = result? {
+                return Ok(Some(element));
+            }
+        }
+    }
+
+    fn parse_bounds(&mut self, data: &mut &[u8]) -> io::Result<()> {
+        let mut coordinate = || Ok::<_, io::Error>(read_sint(data)? as f64 / LOCATION_SCALE);
+        let left = coordinate()?;
+        let bottom = coordinate()?;
+        let right = coordinate()?;
+        let top = coordinate()?;
+        self.bounds = Some(HeaderBBox {
+            left,
+            right,
+            top,
+            bottom,
+        });
+        Ok(())
+    }
+
+    /// Reads the id and metadata that start every element dataset
+    fn parse_start(&mut self, data: &mut &[u8]) -> io::Result<(u64, Option<Info>)> {
+        let id = self.deltas.id.decode(read_sint(data)?) as u64;
+        let version = read_uint(data)?;
+        if version == 0 {
+            return Ok((id, None));
+        }
+        let mut info = Info {
+            version: Some(version as u32),
+            ..Info::default()
+        };
+        let timestamp = self.deltas.timestamp.decode(read_sint(data)?);
+        if timestamp < 0 {
+            return Err(invalid_data("invalid timestamp"));
+        }
+        if timestamp != 0 {
+            info.timestamp = Some(Timestamp::from_secs(timestamp as u64));
+            info.changeset = Some(self.deltas.changeset.decode(read_sint(data)?) as u64);
+            let raw = self.strings.read(data, Entry::User)?;
+            let (uid, len) = read_varint(&raw).ok_or_else(|| invalid_data("invalid uid"))?;
+            info.uid = Some(uid as u32);
+            info.user = Some(to_string(&raw[len + 1..raw.len() - 1])?);
+        }
+        Ok((id, Some(info)))
+    }
+
+    fn parse_tags(&mut self, data: &mut &[u8]) -> io::Result<Vec<(String, String)>> {
+        let mut tags = vec![];
+        while !data.is_empty() {
+            let raw = self.strings.read(data, Entry::Pair)?;
+            let key_len = raw.iter().position(|b| *b == 0).unwrap();
+            tags.push((
+                to_string(&raw[..key_len])?,
+                to_string(&raw[key_len + 1..raw.len() - 1])?,
+            ));
+        }
+        Ok(tags)
+    }
+
+    fn parse_node(&mut self, data: &mut &[u8]) -> io::Result<Element> {
+        let (id, info) = self.parse_start(data)?;
+        if data.is_empty() {
+            return Ok(Element::Node(Node {
+                id,
+                lat: 0.0,
+                lon: 0.0,
+                info: Some(deleted(info)),
+                tags: vec![],
+            }));
+        }
+        let lon = self.deltas.lon.decode(read_sint(data)?) as f64 / LOCATION_SCALE;
+        let lat = self.deltas.lat.decode(read_sint(data)?) as f64 / LOCATION_SCALE;
+        Ok(Element::Node(Node {
+            id,
+            lat,
+            lon,
+            info,
+            tags: self.parse_tags(data)?,
+        }))
+    }
+
+    fn parse_way(&mut self, data: &mut &[u8]) -> io::Result<Element> {
+        let (id, info) = self.parse_start(data)?;
+        if data.is_empty() {
+            return Ok(Element::Way(Way {
+                id,
+                info: Some(deleted(info)),
+                tags: vec![],
+                refs: vec![],
+            }));
+        }
+        let mut refs_data = split_section(data)?;
+        let mut refs = vec![];
+        while !refs_data.is_empty() {
+            refs.push(self.deltas.node_ref.decode(read_sint(&mut refs_data)?) as u64);
+        }
+        Ok(Element::Way(Way {
+            id,
+            info,
+            tags: self.parse_tags(data)?,
+            refs,
+        }))
+    }
+
+    fn parse_relation(&mut self, data: &mut &[u8]) -> io::Result<Element> {
+        let (id, info) = self.parse_start(data)?;
+        if data.is_empty() {
+            return Ok(Element::Relation(Relation {
+                id,
+                info: Some(deleted(info)),
+                tags: vec![],
+                members: vec![],
+            }));
+        }
+        let mut members_data = split_section(data)?;
+        let mut members = vec![];
+        while !members_data.is_empty() {
+            let delta = read_sint(&mut members_data)?;
+            let raw = self.strings.read(&mut members_data, Entry::Role)?;
+            let (member_type, index) = match raw[0] {
+                b'0' => (RelationMemberType::Node, 0),
+                b'1' => (RelationMemberType::Way, 1),
+                b'2' => (RelationMemberType::Relation, 2),
+                _ => return Err(invalid_data("invalid member type")),
+            };
+            members.push(Member {
+                member_type,
+                id: self.deltas.member_ids[index].decode(delta) as u64,
+                role: to_string(&raw[1..raw.len() - 1])?,
+            });
+        }
+        Ok(Element::Relation(Relation {
+            id,
+            info,
+            tags: self.parse_tags(data)?,
+            members,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for O5mReader<R> {
+    type Item = io::Result<Element>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_element().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+fn deleted(info: Option<Info>) -> Info {
+    Info {
+        visible: Some(false),
+        ..info.unwrap_or_default()
+    }
+}
+
+/// Splits off the length prefixed section of way refs or relation members
+fn split_section<'a>(data: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let len = read_uint(data)? as usize;
+    if len > data.len() {
+        return Err(invalid_data("truncated dataset"));
+    }
+    let (section, rest) = data.split_at(len);
+    *data = rest;
+    Ok(section)
+}
+
+fn read_timestamp(data: &mut &[u8]) -> io::Result<Timestamp> {
+    match read_sint(data)? {
+        timestamp if timestamp < 0 => Err(invalid_data("invalid timestamp")),
+        timestamp => Ok(Timestamp::from_secs(timestamp as u64)),
+    }
+}
+
+fn to_string(bytes: &[u8]) -> io::Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(invalid_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let data = b"\xff\xe0\x04o5m2\
+            \x10\x19\x8e\x10\x01\xb6\x01\x9a\x01\x00\x18\x00jacob\x00\
+            \x80\x8f\xfcR\x80\xf5\xd6\xf4\x03\
+            \x11\x0d\x02\x00\x03\x06\x0e\x06\x00ref\x001\x00\
+            \xfe";
+        let elements = O5mReader::new(&data[..])
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                Element::Node(Node {
+                    id: 1031,
+                    lat: 52.5,
+                    lon: 8.7,
+                    info: Some(Info {
+                        version: Some(1),
+                        timestamp: Some(Timestamp::from_secs(91)),
+                        changeset: Some(77),
+                        uid: Some(24),
+                        user: Some("jacob".to_string()),
+                        visible: None,
+                    }),
+                    tags: vec![],
+                }),
+                Element::Way(Way {
+                    id: 1032,
+                    info: None,
+                    tags: vec![("ref".to_string(), "1".to_string())],
+                    refs: vec![3, 10, 13],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut reader = O5mReader::new(&b"\xff\xe0\x04o5x2"[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let corrupt: [&[u8]; 3] = [
+            // A relation whose tag refers back to its member role
+            b"\xff\xe0\x04o5m2\x12\x09\x02\x00\x05\x02\x001r\x00\x01",
+            // Negative timestamps, of a node and of the file
+            b"\xff\xe0\x04o5m2\x10\x03\x02\x01\x01",
+            b"\xff\xe0\x04o5m2\xdc\x01\x01",
+        ];
+        for data in corrupt {
+            let mut reader = O5mReader::new(data);
+            assert!(reader.next().unwrap().is_err());
+        }
+    }
+}
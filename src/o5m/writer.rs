@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use super::{
+    BOUNDING_BOX, Deltas, END_OF_FILE, Entry, HEADER, LOCATION_SCALE, NODE, RELATION, RESET,
+    STRING_TABLE_SIZE, WAY, write_sint, write_uint,
+};
+use crate::owned::{Element, Info};
+use crate::{BlobReader, HeaderBBox, Primitive, PrimitiveBlock, RelationMemberType, Timestamp};
+
+/// Strings written so far, by the count of table entries at the time
+struct StringIndex {
+    written: HashMap<Vec<u8>, u64>,
+    count: u64,
+}
+
+impl StringIndex {
+    fn new() -> Self {
+        StringIndex {
+            written: HashMap::new(),
+            count: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.written.clear();
+        self.count = 0;
+    }
+
+    /// Writes a reference if the entry is still in the reader's table,
+    /// otherwise the entry itself
+    fn write(&mut self, out: &mut Vec<u8>, raw: Vec<u8>, entry: Entry) {
+        if let Some(added) = self.written.get(&raw) {
+            let reference = self.count - added;
+            if reference <= STRING_TABLE_SIZE as u64 {
+                write_uint(out, reference);
+                return;
+            }
+        }
+        out.push(0);
+        out.extend_from_slice(&raw);
+        if entry.fits_table(&raw) {
+            self.written.insert(raw, self.count);
+            self.count += 1;
+        }
+    }
+}
+
+/// Writes an o5m file, resetting the delta coding whenever the element
+/// type changes as osmconvert does
+pub struct O5mWriter<W: Write> {
+    out: W,
+    buf: Vec<u8>,
+    strings: StringIndex,
+    deltas: Deltas,
+    dataset: Option<u8>,
+}
+
+impl<W: Write> O5mWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(&[RESET, HEADER, 4])?;
+        out.write_all(b"o5m2")?;
+        Ok(O5mWriter {
+            out,
+            buf: vec![],
+            strings: StringIndex::new(),
+            deltas: Deltas::default(),
+            dataset: None,
+        })
+    }
+
+    pub fn write_bounds(&mut self, bbox: &HeaderBBox) -> io::Result<()> {
+        for coordinate in [bbox.left, bbox.bottom, bbox.right, bbox.top] {
+            write_sint(&mut self.buf, (coordinate * LOCATION_SCALE).round() as i64);
+        }
+        self.write_dataset(BOUNDING_BOX)
+    }
+
+    pub fn write_primitive(&mut self, primitive: &Primitive) -> io::Result<()> {
+        self.write_element(&Element::from(primitive))
+    }
+
+    /// Writes an element; one whose `visible` is false is written as a
+    /// deletion, without any data but its metadata
+    pub fn write_element(&mut self, element: &Element) -> io::Result<()> {
+        let dataset = match element {
+            Element::Node(_) => NODE,
+            Element::Way(_) => WAY,
+            Element::Relation(_) => RELATION,
+        };
+        if self.dataset != Some(dataset) {
+            self.out.write_all(&[RESET])?;
+            self.strings.clear();
+            self.deltas = Deltas::default();
+            self.dataset = Some(dataset);
+        }
+
+        let id = self.deltas.id.encode(element.id() as i64);
+        write_sint(&mut self.buf, id);
+        self.write_info(element.info());
+        if element.info().and_then(|info| info.visible) == Some(false) {
+            return self.write_dataset(dataset);
+        }
+
+        match element {
+            Element::Node(node) => {
+                let lon = self
+                    .deltas
+                    .lon
+                    .encode((node.lon * LOCATION_SCALE).round() as i64);
+                write_sint(&mut self.buf, lon);
+                let lat = self
+                    .deltas
+                    .lat
+                    .encode((node.lat * LOCATION_SCALE).round() as i64);
+                write_sint(&mut self.buf, lat);
+            }
+            Element::Way(way) => {
+                let mut refs = vec![];
+                for id in &way.refs {
+                    write_sint(&mut refs, self.deltas.node_ref.encode(*id as i64));
+                }
+                write_uint(&mut self.buf, refs.len() as u64);
+                self.buf.extend_from_slice(&refs);
+            }
+            Element::Relation(relation) => {
+                let mut members = vec![];
+                for member in &relation.members {
+                    let (digit, index) = match member.member_type {
+                        RelationMemberType::Node => (b'0', 0),
+                        RelationMemberType::Way => (b'1', 1),
+                        RelationMemberType::Relation => (b'2', 2),
+                    };
+                    let id = self.deltas.member_ids[index].encode(member.id as i64);
+                    write_sint(&mut members, id);
+                    let mut raw = vec![digit];
+                    raw.extend_from_slice(member.role.as_bytes());
+                    raw.push(0);
+                    self.strings.write(&mut members, raw, Entry::Role);
+                }
+                write_uint(&mut self.buf, members.len() as u64);
+                self.buf.extend_from_slice(&members);
+            }
+        }
+
+        for (key, value) in element.tags() {
+            let mut raw = Vec::with_capacity(key.len() + value.len() + 2);
+            raw.extend_from_slice(key.as_bytes());
+            raw.push(0);
+            raw.extend_from_slice(value.as_bytes());
+            raw.push(0);
+            self.strings.write(&mut self.buf, raw, Entry::Pair);
+        }
+        self.write_dataset(dataset)
+    }
+
+    /// Ends the file, and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[END_OF_FILE])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_info(&mut self, info: Option<&Info>) {
+        let Some(info) = info.filter(|info| info.version.unwrap_or(0) != 0) else {
+            write_uint(&mut self.buf, 0);
+            return;
+        };
+        write_uint(&mut self.buf, info.version.unwrap_or(0) as u64);
+        let timestamp = info.timestamp.map_or(0, Timestamp::secs) as i64;
+        write_sint(&mut self.buf, self.deltas.timestamp.encode(timestamp));
+        if timestamp == 0 {
+            return;
+        }
+        let changeset = info.changeset.unwrap_or(0) as i64;
+        write_sint(&mut self.buf, self.deltas.changeset.encode(changeset));
+        let mut raw = vec![];
+        write_uint(&mut raw, info.uid.unwrap_or(0) as u64);
+        raw.push(0);
+        raw.extend_from_slice(info.user.as_deref().unwrap_or("").as_bytes());
+        raw.push(0);
+        self.strings.write(&mut self.buf, raw, Entry::User);
+    }
+
+    fn write_dataset(&mut self, dataset: u8) -> io::Result<()> {
+        let mut start = vec![dataset];
+        write_uint(&mut start, self.buf.len() as u64);
+        self.out.write_all(&start)?;
+        self.out.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+/// Converts a whole PBF file, with a bounding box if its header has one
+pub fn write_o5m<R: Read, W: Write>(reader: &mut BlobReader<R>, out: W) -> io::Result<W> {
+    let mut writer = O5mWriter::new(out)?;
+    if let Some(bbox) = reader.header().and_then(|header| header.bbox.as_ref()) {
+        writer.write_bounds(bbox)?;
+    }
+    for blob in reader {
+        let data = blob.into_data();
+        let primitive_block = PrimitiveBlock::parse(&data);
+        for primitive in primitive_block.primitives() {
+            writer.write_primitive(&primitive)?;
+        }
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::o5m::O5mReader;
+    use crate::owned::{Member, Node, Relation, Way};
+
+    #[test]
+    fn test_roundtrip() {
+        let info = Some(Info {
+            version: Some(3),
+            timestamp: Some(Timestamp::from_secs(1_259_186_353)),
+            changeset: Some(42),
+            uid: Some(0),
+            user: Some("".to_string()),
+            visible: None,
+        });
+        let elements = vec![
+            Element::Node(Node {
+                id: 5,
+                lat: -33.8688197,
+                lon: 151.2092955,
+                info: info.clone(),
+                tags: vec![("name".to_string(), "Sydney".to_string())],
+            }),
+            Element::Node(Node {
+                id: 3,
+                lat: 0.0,
+                lon: 0.0,
+                info: Some(Info {
+                    version: Some(2),
+                    visible: Some(false),
+                    ..Info::default()
+                }),
+                tags: vec![],
+            }),
+            Element::Way(Way {
+                id: 1 << 40,
+                info: None,
+                tags: vec![
+                    ("name".to_string(), "Sydney".to_string()),
+                    ("long".to_string(), "x".repeat(300)),
+                    ("long".to_string(), "x".repeat(300)),
+                ],
+                refs: vec![5, 3, 5],
+            }),
+            Element::Relation(Relation {
+                id: 7,
+                info,
+                tags: vec![("name".to_string(), "Sydney".to_string())],
+                members: vec![
+                    Member {
+                        member_type: RelationMemberType::Way,
+                        id: 1 << 40,
+                        role: "outer".to_string(),
+                    },
+                    Member {
+                        member_type: RelationMemberType::Node,
+                        id: 5,
+                        role: "".to_string(),
+                    },
+                    Member {
+                        member_type: RelationMemberType::Way,
+                        id: 1,
+                        role: "outer".to_string(),
+                    },
+                ],
+            }),
+        ];
+
+        let bbox = HeaderBBox {
+            left: -1.5,
+            right: 1.5,
+            top: 2.5,
+            bottom: -2.5,
+        };
+        let mut writer = O5mWriter::new(vec![]).unwrap();
+        writer.write_bounds(&bbox).unwrap();
+        for element in &elements {
+            writer.write_element(element).unwrap();
+        }
+        let out = writer.finish().unwrap();
+
+        let mut reader = O5mReader::new(&out[..]);
+        let read = reader.by_ref().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(reader.bounds(), Some(&bbox));
+        assert_eq!(read, elements);
+    }
+}
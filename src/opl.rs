@@ -0,0 +1,472 @@
+//! OPL (Object Per Line) text format, as written by osmium
+//!
+//! Each element is one line of space separated fields, such as
+//! `n1 v2 dV c3 t2009-11-25T21:59:13Z i4 ufred Tamenity=bench x13.4 y52.5`.
+//! Characters with a meaning in OPL are escaped as `%` followed by their
+//! code point in hex and another `%`.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Read, Write};
+
+use crate::owned::{Element, Info, Member, Node, Relation, Way};
+use crate::xml::invalid_data;
+use crate::{BlobReader, Primitive, PrimitiveBlock, RelationMemberType, Timestamp};
+
+/// Whether `c` can be written as it is, after osmium
+fn is_plain(c: char) -> bool {
+    matches!(c,
+        '\u{21}'..='\u{24}'
+        | '\u{26}'..='\u{2b}'
+        | '\u{2d}'..='\u{3c}'
+        | '\u{3e}'..='\u{3f}'
+        | '\u{41}'..='\u{7e}'
+        | '\u{a1}'..='\u{ac}'
+        | '\u{ae}'..='\u{5ff}')
+}
+
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if is_plain(c) {
+            escaped.push(c);
+        } else {
+            write!(escaped, "%{:x}%", c as u32).unwrap();
+        }
+    }
+    escaped
+}
+
+pub fn unescape(s: &str) -> io::Result<String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('%') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let end = rest
+            .find('%')
+            .ok_or_else(|| invalid_data("unterminated escape"))?;
+        let c = u32::from_str_radix(&rest[..end], 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| invalid_data(format!("invalid escape %{}%", &rest[..end])))?;
+        unescaped.push(c);
+        rest = &rest[end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+/// Formats a coordinate with up to 7 decimals, without trailing zeros
+fn format_coordinate(value: f64) -> String {
+    let formatted = format!("{:.7}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" => "0".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+fn member_type_char(member_type: &RelationMemberType) -> char {
+    match member_type {
+        RelationMemberType::Node => 'n',
+        RelationMemberType::Way => 'w',
+        RelationMemberType::Relation => 'r',
+    }
+}
+
+/// Formats an element as a line of OPL, without the line break
+pub fn format_element(element: &Element) -> String {
+    let mut line = String::new();
+    let kind = match element {
+        Element::Node(_) => 'n',
+        Element::Way(_) => 'w',
+        Element::Relation(_) => 'r',
+    };
+    // Negative IDs, as used by editors, are stored cast to `u64`
+    write!(line, "{}{}", kind, element.id() as i64).unwrap();
+    if let Some(info) = element.info() {
+        if let Some(version) = info.version {
+            write!(line, " v{}", version).unwrap();
+        }
+        if let Some(visible) = info.visible {
+            line.push_str(if visible { " dV" } else { " dD" });
+        }
+        if let Some(changeset) = info.changeset {
+            write!(line, " c{}", changeset).unwrap();
+        }
+        if let Some(timestamp) = info.timestamp {
+            write!(line, " t{}", timestamp).unwrap();
+        }
+        if let Some(uid) = info.uid {
+            write!(line, " i{}", uid).unwrap();
+        }
+        if let Some(user) = &info.user {
+            write!(line, " u{}", escape(user)).unwrap();
+        }
+    }
+
+    line.push_str(" T");
+    for (i, (key, value)) in element.tags().iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        write!(line, "{}={}", escape(key), escape(value)).unwrap();
+    }
+
+    match element {
+        Element::Node(node) => {
+            // Deleted nodes have no location
+            if node.info.as_ref().and_then(|info| info.visible) == Some(false) {
+                line.push_str(" x y");
+            } else {
+                write!(
+                    line,
+                    " x{} y{}",
+                    format_coordinate(node.lon),
+                    format_coordinate(node.lat)
+                )
+                .unwrap();
+            }
+        }
+        Element::Way(way) => {
+            line.push_str(" N");
+            for (i, id) in way.refs.iter().enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                write!(line, "n{}", *id as i64).unwrap();
+            }
+        }
+        Element::Relation(relation) => {
+            line.push_str(" M");
+            for (i, member) in relation.members.iter().enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                write!(
+                    line,
+                    "{}{}@{}",
+                    member_type_char(&member.member_type),
+                    member.id as i64,
+                    escape(&member.role)
+                )
+                .unwrap();
+            }
+        }
+    }
+    line
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, field: char) -> io::Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid_data(format!("invalid {} field: {}", field, value)))
+}
+
+fn parse_id(value: &str, field: char) -> io::Result<u64> {
+    Ok(parse_number::<i64>(value, field)? as u64)
+}
+
+fn parse_tags(value: &str) -> io::Result<Vec<(String, String)>> {
+    if value.is_empty() {
+        return Ok(vec![]);
+    }
+    value
+        .split(',')
+        .map(|tag| {
+            let (key, value) = tag
+                .split_once('=')
+                .ok_or_else(|| invalid_data(format!("invalid tag: {}", tag)))?;
+            Ok((unescape(key)?, unescape(value)?))
+        })
+        .collect()
+}
+
+fn parse_refs(value: &str) -> io::Result<Vec<u64>> {
+    if value.is_empty() {
+        return Ok(vec![]);
+    }
+    value
+        .split(',')
+        .map(|node_ref| {
+            let id = node_ref
+                .strip_prefix('n')
+                .ok_or_else(|| invalid_data(format!("invalid node ref: {}", node_ref)))?;
+            parse_id(id, 'N')
+        })
+        .collect()
+}
+
+fn parse_members(value: &str) -> io::Result<Vec<Member>> {
+    if value.is_empty() {
+        return Ok(vec![]);
+    }
+    value
+        .split(',')
+        .map(|member| {
+            let invalid = || invalid_data(format!("invalid member: {}", member));
+            let member_type = match member.chars().next() {
+                Some('n') => RelationMemberType::Node,
+                Some('w') => RelationMemberType::Way,
+                Some('r') => RelationMemberType::Relation,
+                _ => return Err(invalid()),
+            };
+            let (id, role) = member[1..].split_once('@').ok_or_else(invalid)?;
+            Ok(Member {
+                member_type,
+                id: parse_id(id, 'M')?,
+                role: unescape(role)?,
+            })
+        })
+        .collect()
+}
+
+/// Parses one line of OPL
+pub fn parse_element(line: &str) -> io::Result<Element> {
+    let mut fields = line.split_whitespace();
+    let first = fields.next().ok_or_else(|| invalid_data("empty line"))?;
+    let (kind, id) = first.split_at(first.chars().next().map_or(0, char::len_utf8));
+    let id = parse_id(id, kind.chars().next().unwrap_or(' '))?;
+
+    let mut info = Info::default();
+    let mut tags = vec![];
+    let mut location = (None, None);
+    let mut refs = vec![];
+    let mut members = vec![];
+    for field in fields {
+        let (key, value) = field.split_at(field.chars().next().map_or(0, char::len_utf8));
+        match key {
+            "v" => info.version = Some(parse_number(value, 'v')?),
+            "d" => {
+                info.visible = match value {
+                    "V" => Some(true),
+                    "D" => Some(false),
+                    _ => return Err(invalid_data(format!("invalid d field: {}", value))),
+                }
+            }
+            "c" => info.changeset = Some(parse_number(value, 'c')?),
+            "t" if value.is_empty() => (),
+            "t" => {
+                info.timestamp = Some(
+                    Timestamp::parse(value)
+                        .ok_or_else(|| invalid_data(format!("invalid timestamp: {}", value)))?,
+                )
+            }
+            "i" => info.uid = Some(parse_number(value, 'i')?),
+            "u" => info.user = Some(unescape(value)?),
+            "T" => tags = parse_tags(value)?,
+            "x" if value.is_empty() => (),
+            "x" => location.0 = Some(parse_number(value, 'x')?),
+            "y" if value.is_empty() => (),
+            "y" => location.1 = Some(parse_number(value, 'y')?),
+            "N" => refs = parse_refs(value)?,
+            "M" => members = parse_members(value)?,
+            _ => return Err(invalid_data(format!("unknown field: {}", field))),
+        }
+    }
+    let info = if info == Info::default() {
+        None
+    } else {
+        Some(info)
+    };
+
+    Ok(match kind {
+        "n" => Element::Node(Node {
+            id,
+            lat: location.1.unwrap_or_default(),
+            lon: location.0.unwrap_or_default(),
+            info,
+            tags,
+        }),
+        "w" => Element::Way(Way {
+            id,
+            info,
+            tags,
+            refs,
+        }),
+        "r" => Element::Relation(Relation {
+            id,
+            info,
+            tags,
+            members,
+        }),
+        _ => return Err(invalid_data(format!("unknown element type: {}", kind))),
+    })
+}
+
+/// Reads the elements of an OPL file, skipping empty lines and `#` comments
+pub struct OplReader<R> {
+    read: R,
+    line: String,
+    done: bool,
+}
+
+impl<R: BufRead> OplReader<R> {
+    pub fn new(read: R) -> Self {
+        OplReader {
+            read,
+            line: String::new(),
+            done: false,
+        }
+    }
+
+    fn read_element(&mut self) -> io::Result<Option<Element>> {
+        loop {
+            self.line.clear();
+            if self.read.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            let line = self.line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                return parse_element(line).map(Some);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for OplReader<R> {
+    type Item = io::Result<Element>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_element().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+pub struct OplWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> OplWriter<W> {
+    pub fn new(out: W) -> Self {
+        OplWriter { out }
+    }
+
+    pub fn write_primitive(&mut self, primitive: &Primitive) -> io::Result<()> {
+        self.write_element(&Element::from(primitive))
+    }
+
+    pub fn write_element(&mut self, element: &Element) -> io::Result<()> {
+        writeln!(self.out, "{}", format_element(element))
+    }
+
+    /// Flushes and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Converts a whole PBF file
+pub fn write_opl<R: Read, W: Write>(reader: &mut BlobReader<R>, out: W) -> io::Result<W> {
+    let mut writer = OplWriter::new(out);
+    for blob in reader {
+        let data = blob.into_data();
+        let primitive_block = PrimitiveBlock::parse(&data);
+        for primitive in primitive_block.primitives() {
+            writer.write_primitive(&primitive)?;
+        }
+    }
+    writer.finish()
+}
+
+/// Writes OPL fixtures as a PBF file in memory, for tests
+#[cfg(test)]
+pub(crate) fn opl_to_pbf(opl: &str) -> Vec<u8> {
+    let mut writer = crate::PbfWriter::new(vec![], &crate::HeaderBlock::default()).unwrap();
+    for element in OplReader::new(opl.as_bytes()) {
+        writer.write_element(element.unwrap()).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+/// The type and ID of every element in a PBF file, for tests
+#[cfg(test)]
+pub(crate) fn sort_keys(pbf: &[u8]) -> Vec<(u8, u64)> {
+    let mut keys = vec![];
+    for blob in BlobReader::new(pbf) {
+        let data = blob.into_data();
+        let primitive_block = PrimitiveBlock::parse(&data);
+        for primitive in primitive_block.primitives() {
+            keys.push(Element::from(&primitive).sort_key());
+        }
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPL: &str = "n1 v2 dV c3 t2009-11-25T21:59:13Z i4 ufred%20%&%20%barney Tamenity=bench,note=a%2c%b%3d%c x13.4 y-52.5
+n2 v3 dD c4 t2009-11-25T21:59:14Z i4 ufred T x y
+w3 v1 T N
+w4 Thighway=path Nn1,n2
+r5 Ttype=multipolygon Mw4@outer,n1@,r6@%40%
+n-1 T x1 y2
+w-2 T Nn-1,n1
+r-3 T Mw-2@,n-1@
+";
+
+    #[test]
+    fn test_roundtrip() {
+        let elements = OplReader::new(OPL.as_bytes())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(elements.len(), 8);
+        let Element::Node(node) = &elements[0] else {
+            panic!("not a node");
+        };
+        assert_eq!((node.lat, node.lon), (-52.5, 13.4));
+        assert_eq!(
+            node.info.as_ref().unwrap().user.as_deref(),
+            Some("fred & barney")
+        );
+        assert_eq!(node.tags[1], ("note".to_string(), "a,b=c".to_string()));
+        let Element::Relation(relation) = &elements[4] else {
+            panic!("not a relation");
+        };
+        assert_eq!(relation.members[1].role, "");
+        assert_eq!(relation.members[2].role, "@");
+        assert_eq!(elements[5].id(), -1i64 as u64);
+        let Element::Way(way) = &elements[6] else {
+            panic!("not a way");
+        };
+        assert_eq!(way.refs, vec![-1i64 as u64, 1]);
+        let Element::Relation(relation) = &elements[7] else {
+            panic!("not a relation");
+        };
+        assert_eq!(relation.members[0].id, -2i64 as u64);
+
+        let mut writer = OplWriter::new(vec![]);
+        for element in &elements {
+            writer.write_element(element).unwrap();
+        }
+        let out = writer.finish().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), OPL);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a b\n€%ü"), "a%20%b%a%%20ac%%25%ü");
+        assert_eq!(unescape("a%20%b%a%%20ac%%25%ü").unwrap(), "a b\n€%ü");
+        assert!(unescape("%20").is_err());
+        assert!(unescape("%d800%").is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse_element("x1").is_err());
+        assert!(parse_element("n1 q").is_err());
+        assert!(parse_element("w1 N1,2").is_err());
+        let mut reader = OplReader::new(&b"# comment\n\nn1 vx\nn2\n"[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,181 @@
+//! Owned copies of elements, for keeping them beyond the lifetime of their
+//! `PrimitiveBlock`
+
+use crate::info;
+use crate::{Primitive, RelationMemberType, Timestamp};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Info {
+    pub version: Option<u32>,
+    pub timestamp: Option<Timestamp>,
+    pub changeset: Option<u64>,
+    pub uid: Option<u32>,
+    pub user: Option<String>,
+    pub visible: Option<bool>,
+}
+
+impl<'a> From<&info::Info<'a>> for Info {
+    fn from(info: &info::Info<'a>) -> Self {
+        Info {
+            version: info.version,
+            timestamp: info.timestamp,
+            changeset: info.changeset,
+            uid: info.uid,
+            user: info.user.map(str::to_string),
+            visible: info.visible,
+        }
+    }
+}
+
+fn owned_tags<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(tags: I) -> Vec<(String, String)> {
+    tags.into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub id: u64,
+    pub lat: f64,
+    pub lon: f64,
+    pub info: Option<Info>,
+    pub tags: Vec<(String, String)>,
+}
+
+impl<'a> From<&crate::Node<'a>> for Node {
+    fn from(node: &crate::Node<'a>) -> Self {
+        Node {
+            id: node.id,
+            lat: node.lat,
+            lon: node.lon,
+            info: node.info.as_ref().map(Info::from),
+            tags: owned_tags(node.tags.iter().copied()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Way {
+    pub id: u64,
+    pub info: Option<Info>,
+    pub tags: Vec<(String, String)>,
+    pub refs: Vec<u64>,
+}
+
+impl<'a> From<&crate::Way<'a>> for Way {
+    fn from(way: &crate::Way<'a>) -> Self {
+        Way {
+            id: way.id,
+            info: way.info.as_ref().map(Info::from),
+            tags: owned_tags(way.tags()),
+            refs: way.refs().map(|id| id as u64).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub member_type: RelationMemberType,
+    pub id: u64,
+    pub role: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relation {
+    pub id: u64,
+    pub info: Option<Info>,
+    pub tags: Vec<(String, String)>,
+    pub members: Vec<Member>,
+}
+
+impl<'a> From<&crate::Relation<'a>> for Relation {
+    fn from(relation: &crate::Relation<'a>) -> Self {
+        Relation {
+            id: relation.id,
+            info: relation.info.as_ref().map(Info::from),
+            tags: owned_tags(relation.tags()),
+            members: relation
+                .members()
+                .map(|(role, id, member_type)| Member {
+                    member_type,
+                    id,
+                    role: role.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Node(Node),
+    Way(Way),
+    Relation(Relation),
+}
+
+impl Element {
+    pub fn id(&self) -> u64 {
+        match self {
+            Element::Node(node) => node.id,
+            Element::Way(way) => way.id,
+            Element::Relation(relation) => relation.id,
+        }
+    }
+
+    pub fn tags(&self) -> &[(String, String)] {
+        match self {
+            Element::Node(node) => &node.tags,
+            Element::Way(way) => &way.tags,
+            Element::Relation(relation) => &relation.tags,
+        }
+    }
+
+    pub fn info(&self) -> Option<&Info> {
+        match self {
+            Element::Node(node) => node.info.as_ref(),
+            Element::Way(way) => way.info.as_ref(),
+            Element::Relation(relation) => relation.info.as_ref(),
+        }
+    }
+
+    pub fn info_mut(&mut self) -> Option<&mut Info> {
+        match self {
+            Element::Node(node) => node.info.as_mut(),
+            Element::Way(way) => way.info.as_mut(),
+            Element::Relation(relation) => relation.info.as_mut(),
+        }
+    }
+
+    pub fn version(&self) -> Option<u32> {
+        self.info().and_then(|info| info.version)
+    }
+
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        self.info().and_then(|info| info.timestamp)
+    }
+
+    /// False only for the deleted versions of history and change files
+    pub fn is_visible(&self) -> bool {
+        self.info().and_then(|info| info.visible) != Some(false)
+    }
+
+    /// Position in a file sorted by type, then id
+    pub fn sort_key(&self) -> (u8, u64) {
+        let rank = match self {
+            Element::Node(_) => 0,
+            Element::Way(_) => 1,
+            Element::Relation(_) => 2,
+        };
+        (rank, self.id())
+    }
+}
+
+impl<'a> From<&Primitive<'a>> for Element {
+    fn from(primitive: &Primitive<'a>) -> Self {
+        match primitive {
+            Primitive::Node(node) => Element::Node(node.into()),
+            Primitive::Way(way) => Element::Way(way.into()),
+            Primitive::Relation(relation) => Element::Relation(relation.into()),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Writing elements to Parquet files, one per element kind
+//!
+//! The files use the fixed schemas of the `arrow` module. With
+//! `ParquetOptions::geoparquet` the nodes file additionally gets a WKB
+//! `geometry` column and GeoParquet metadata describing it.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::Compression;
+use ::parquet::errors::ParquetError;
+use ::parquet::file::properties::WriterProperties;
+use ::parquet::format::KeyValue;
+use arrow_array::builder::BinaryBuilder;
+use arrow_array::{Array, ArrayRef, Float64Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+pub use ::parquet;
+
+use crate::arrow::{
+    NodesBuilder, RelationsBuilder, WaysBuilder, node_schema, relation_schema, way_schema,
+};
+use crate::{BlobReader, Primitive, PrimitiveBlock};
+
+pub const NODES_FILE: &str = "nodes.parquet";
+pub const WAYS_FILE: &str = "ways.parquet";
+pub const RELATIONS_FILE: &str = "relations.parquet";
+
+#[derive(Debug, Clone)]
+pub struct ParquetOptions {
+    /// Maximum number of rows per row group, and per written batch
+    pub row_group_size: usize,
+    pub compression: Compression,
+    /// Add a WKB point `geometry` column and GeoParquet metadata to nodes
+    pub geoparquet: bool,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions {
+            row_group_size: 1024 * 1024,
+            compression: Compression::SNAPPY,
+            geoparquet: false,
+        }
+    }
+}
+
+/// Number of rows written per file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParquetCounts {
+    pub nodes: u64,
+    pub ways: u64,
+    pub relations: u64,
+}
+
+fn geoparquet_node_schema() -> SchemaRef {
+    let mut fields: Vec<Field> = node_schema()
+        .fields()
+        .iter()
+        .map(|field| field.as_ref().clone())
+        .collect();
+    fields.push(Field::new("geometry", DataType::Binary, false));
+    Arc::new(Schema::new(fields))
+}
+
+/// Appends a WKB-encoded point for every row of a node batch
+fn with_geometry(batch: RecordBatch, schema: SchemaRef) -> Result<RecordBatch, ParquetError> {
+    let downcast = |name| {
+        batch
+            .column_by_name(name)
+            .and_then(|column| column.as_any().downcast_ref::<Float64Array>())
+            .ok_or_else(|| ParquetError::General(format!("node batch lacks {}", name)))
+    };
+    let lats = downcast("lat")?;
+    let lons = downcast("lon")?;
+    let mut geometries = BinaryBuilder::with_capacity(lats.len(), 21 * lats.len());
+    let mut wkb = [0; 21];
+    // little endian, wkbPoint
+    wkb[0] = 1;
+    wkb[1..5].copy_from_slice(&1u32.to_le_bytes());
+    for (lat, lon) in lats.values().iter().zip(lons.values()) {
+        wkb[5..13].copy_from_slice(&lon.to_le_bytes());
+        wkb[13..21].copy_from_slice(&lat.to_le_bytes());
+        geometries.append_value(wkb);
+    }
+    let mut columns: Vec<ArrayRef> = batch.columns().to_vec();
+    columns.push(Arc::new(geometries.finish()));
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+/// Bounding box of all written nodes as `[min_lon, min_lat, max_lon, max_lat]`
+struct Bbox(Option<[f64; 4]>);
+
+impl Bbox {
+    fn extend(&mut self, lat: f64, lon: f64) {
+        let bbox = self.0.get_or_insert([lon, lat, lon, lat]);
+        bbox[0] = bbox[0].min(lon);
+        bbox[1] = bbox[1].min(lat);
+        bbox[2] = bbox[2].max(lon);
+        bbox[3] = bbox[3].max(lat);
+    }
+
+    fn geo_metadata(&self) -> String {
+        let bbox = match self.0 {
+            Some([min_lon, min_lat, max_lon, max_lat]) => format!(
+                ",\"bbox\":[{:?},{:?},{:?},{:?}]",
+                min_lon, min_lat, max_lon, max_lat
+            ),
+            None => String::new(),
+        };
+        format!(
+            "{{\"version\":\"1.1.0\",\"primary_column\":\"geometry\",\"columns\":{{\"geometry\":{{\"encoding\":\"WKB\",\"geometry_types\":[\"Point\"]{}}}}}}}",
+            bbox
+        )
+    }
+}
+
+/// Writes `nodes.parquet`, `ways.parquet` and `relations.parquet` into a
+/// directory
+pub struct ParquetWriter {
+    options: ParquetOptions,
+    geometry_schema: Option<SchemaRef>,
+    bbox: Bbox,
+    counts: ParquetCounts,
+    nodes: NodesBuilder,
+    ways: WaysBuilder,
+    relations: RelationsBuilder,
+    nodes_writer: ArrowWriter<File>,
+    ways_writer: ArrowWriter<File>,
+    relations_writer: ArrowWriter<File>,
+}
+
+impl ParquetWriter {
+    pub fn create<P: AsRef<Path>>(dir: P, options: ParquetOptions) -> Result<Self, ParquetError> {
+        let dir = dir.as_ref();
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(options.row_group_size)
+            .set_compression(options.compression)
+            .build();
+        let geometry_schema = options.geoparquet.then(geoparquet_node_schema);
+        let open = |name, schema| {
+            let file = File::create(dir.join(name))?;
+            ArrowWriter::try_new(file, schema, Some(properties.clone()))
+        };
+
+        Ok(ParquetWriter {
+            nodes_writer: open(
+                NODES_FILE,
+                geometry_schema.clone().unwrap_or_else(node_schema),
+            )?,
+            ways_writer: open(WAYS_FILE, way_schema())?,
+            relations_writer: open(RELATIONS_FILE, relation_schema())?,
+            options,
+            geometry_schema,
+            bbox: Bbox(None),
+            counts: ParquetCounts::default(),
+            nodes: NodesBuilder::new(),
+            ways: WaysBuilder::new(),
+            relations: RelationsBuilder::new(),
+        })
+    }
+
+    pub fn write_block(&mut self, primitive_block: &PrimitiveBlock) -> Result<(), ParquetError> {
+        for primitive in primitive_block.primitives() {
+            match primitive {
+                Primitive::Node(node) => {
+                    if self.geometry_schema.is_some() {
+                        self.bbox.extend(node.lat, node.lon);
+                    }
+                    self.nodes.append(&node)?;
+                    self.counts.nodes += 1;
+                    if self.nodes.len() >= self.options.row_group_size {
+                        self.flush_nodes()?;
+                    }
+                }
+                Primitive::Way(way) => {
+                    self.ways.append(&way)?;
+                    self.counts.ways += 1;
+                    if self.ways.len() >= self.options.row_group_size {
+                        self.ways_writer.write(&self.ways.finish()?)?;
+                    }
+                }
+                Primitive::Relation(relation) => {
+                    self.relations.append(&relation)?;
+                    self.counts.relations += 1;
+                    if self.relations.len() >= self.options.row_group_size {
+                        self.relations_writer.write(&self.relations.finish()?)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_nodes(&mut self) -> Result<(), ParquetError> {
+        let batch = self.nodes.finish()?;
+        let batch = match &self.geometry_schema {
+            Some(schema) => with_geometry(batch, schema.clone())?,
+            None => batch,
+        };
+        self.nodes_writer.write(&batch)
+    }
+
+    /// Writes out pending rows and file footers
+    pub fn close(mut self) -> Result<ParquetCounts, ParquetError> {
+        if !self.nodes.is_empty() {
+            self.flush_nodes()?;
+        }
+        if !self.ways.is_empty() {
+            self.ways_writer.write(&self.ways.finish()?)?;
+        }
+        if !self.relations.is_empty() {
+            self.relations_writer.write(&self.relations.finish()?)?;
+        }
+        if self.geometry_schema.is_some() {
+            self.nodes_writer.append_key_value_metadata(KeyValue::new(
+                "geo".to_string(),
+                self.bbox.geo_metadata(),
+            ));
+        }
+        self.nodes_writer.close()?;
+        self.ways_writer.close()?;
+        self.relations_writer.close()?;
+        Ok(self.counts)
+    }
+}
+
+/// Converts a whole file into Parquet files in `dir`
+pub fn write_parquet<R: Read, P: AsRef<Path>>(
+    reader: BlobReader<R>,
+    dir: P,
+    options: ParquetOptions,
+) -> Result<ParquetCounts, ParquetError> {
+    let mut writer = ParquetWriter::create(dir, options)?;
+    for blob in reader {
+        let data = blob.into_data();
+        writer.write_block(&PrimitiveBlock::parse(&data))?;
+    }
+    writer.close()
+}
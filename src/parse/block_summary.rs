@@ -0,0 +1,53 @@
+use std::ops::RangeInclusive;
+
+/// Number of elements of one kind in a block, and the range of their IDs
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KindSummary {
+    pub count: u64,
+    pub ids: Option<RangeInclusive<u64>>,
+}
+
+impl KindSummary {
+    pub fn add(&mut self, id: u64) {
+        self.count += 1;
+        self.ids = match self.ids.take() {
+            Some(ids) => Some(*ids.start().min(&id)..=*ids.end().max(&id)),
+            None => Some(id..=id),
+        };
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.ids.as_ref().is_some_and(|ids| ids.contains(&id))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+/// What a `PrimitiveBlock` contains, obtained without decoding tags or infos
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BlockSummary {
+    pub nodes: KindSummary,
+    pub ways: KindSummary,
+    pub relations: KindSummary,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KindSummary;
+
+    #[test]
+    fn test_add() {
+        let mut summary = KindSummary::default();
+        assert!(summary.is_empty());
+        assert!(!summary.contains(0));
+        summary.add(7);
+        summary.add(3);
+        summary.add(5);
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.ids, Some(3..=7));
+        assert!(summary.contains(4));
+        assert!(!summary.contains(8));
+    }
+}
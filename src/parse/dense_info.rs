@@ -1,4 +1,4 @@
-use crate::{delta::DeltaEncodedIter, info::Info, primitive_block::PrimitiveBlock};
+use crate::{delta::DeltaEncodedIter, info::Info, primitive_block::PrimitiveBlock, varint};
 use protobuf_iter::*;
 
 pub struct DenseInfoParser<'a> {
@@ -64,3 +64,45 @@ impl<'a> Iterator for DenseInfoParser<'a> {
         })
     }
 }
+
+/// The `DenseInfo` columns of a `DenseNodes` group, decoded in one go
+#[derive(Debug, Default, Clone)]
+pub struct DenseInfoColumns {
+    pub versions: Vec<u32>,
+    /// Raw timestamps, to be passed through `PrimitiveBlock::convert_date()`
+    pub timestamps: Vec<i64>,
+    pub changesets: Vec<i64>,
+    pub uids: Vec<i32>,
+    /// Indices into the `stringtable`
+    pub user_sids: Vec<i32>,
+    /// Empty unless the block carries historical information
+    pub visibles: Vec<bool>,
+}
+
+impl DenseInfoColumns {
+    pub fn clear(&mut self) {
+        self.versions.clear();
+        self.timestamps.clear();
+        self.changesets.clear();
+        self.uids.clear();
+        self.user_sids.clear();
+        self.visibles.clear();
+    }
+
+    /// Replaces the contents with the `DenseInfo` message in `data`
+    pub fn decode(&mut self, data: &[u8]) -> Option<()> {
+        self.clear();
+        for m in MessageIter::new(data) {
+            match m.tag {
+                1 => varint::decode_packed_u32(*m.value, &mut self.versions)?,
+                2 => varint::decode_delta_i64(*m.value, &mut self.timestamps)?,
+                3 => varint::decode_delta_i64(*m.value, &mut self.changesets)?,
+                4 => varint::decode_delta_i32(*m.value, &mut self.uids)?,
+                5 => varint::decode_delta_i32(*m.value, &mut self.user_sids)?,
+                6 => varint::decode_packed_bool(*m.value, &mut self.visibles)?,
+                _ => (),
+            }
+        }
+        Some(())
+    }
+}
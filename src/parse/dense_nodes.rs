@@ -1,6 +1,9 @@
 use crate::{
-    Node, PrimitiveBlock, delimited::DelimitedIter, delta::DeltaEncodedIter,
-    dense_info::DenseInfoParser,
+    Node, PrimitiveBlock,
+    delimited::DelimitedIter,
+    delta::DeltaEncodedIter,
+    dense_info::{DenseInfoColumns, DenseInfoParser},
+    varint,
 };
 use protobuf_iter::*;
 
@@ -56,3 +59,100 @@ impl<'a> Iterator for DenseNodesParser<'a> {
         })
     }
 }
+
+/// A whole `DenseNodes` group decoded into columns, as an alternative to
+/// iterating with `DenseNodesParser`
+///
+/// Buffers are kept across calls to `decode()` so that one instance can be
+/// reused for all groups of a file.
+#[derive(Debug, Default, Clone)]
+pub struct DenseNodesColumns {
+    pub ids: Vec<i64>,
+    /// Raw latitudes, to be passed through `PrimitiveBlock::convert_lat()`
+    pub lats: Vec<i64>,
+    /// Raw longitudes, to be passed through `PrimitiveBlock::convert_lon()`
+    pub lons: Vec<i64>,
+    /// Key and value `stringtable` indices of all nodes, without delimiters
+    pub keys_vals: Vec<u32>,
+    /// The tags of node `i` are `keys_vals[tag_offsets[i]..tag_offsets[i + 1]]`
+    pub tag_offsets: Vec<usize>,
+    pub infos: Option<DenseInfoColumns>,
+}
+
+impl DenseNodesColumns {
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Replaces the contents with the `DenseNodes` message in `data`
+    pub fn decode(&mut self, data: &[u8]) -> Option<()> {
+        self.ids.clear();
+        self.lats.clear();
+        self.lons.clear();
+        self.keys_vals.clear();
+        self.tag_offsets.clear();
+        let mut has_infos = false;
+        let mut raw_keys_vals = None;
+
+        for m in MessageIter::new(data) {
+            match m.tag {
+                1 => varint::decode_delta_i64(*m.value, &mut self.ids)?,
+                5 => {
+                    has_infos = true;
+                    self.infos
+                        .get_or_insert_with(Default::default)
+                        .decode(*m.value)?;
+                }
+                8 => varint::decode_delta_i64(*m.value, &mut self.lats)?,
+                9 => varint::decode_delta_i64(*m.value, &mut self.lons)?,
+                10 => raw_keys_vals = Some(*m.value),
+                _ => (),
+            }
+        }
+        if !has_infos {
+            self.infos = None;
+        }
+
+        self.tag_offsets.reserve(self.ids.len() + 1);
+        self.tag_offsets.push(0);
+        if let Some(raw_keys_vals) = raw_keys_vals {
+            varint::decode_packed_u32(raw_keys_vals, &mut self.keys_vals)?;
+            // Compact the 0-delimited pairs in place while recording offsets
+            let mut len = 0;
+            for i in 0..self.keys_vals.len() {
+                let sid = self.keys_vals[i];
+                if sid == 0 {
+                    self.tag_offsets.push(len);
+                } else {
+                    self.keys_vals[len] = sid;
+                    len += 1;
+                }
+            }
+            self.keys_vals.truncate(len);
+        }
+        self.tag_offsets
+            .resize(self.ids.len() + 1, self.keys_vals.len());
+
+        Some(())
+    }
+
+    /// Returns the `(key, value)` tags of node `i`
+    pub fn tags<'a>(
+        &'a self,
+        stringtable: &'a [&'a str],
+        i: usize,
+    ) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.keys_vals[self.tag_offsets[i]..self.tag_offsets[i + 1]]
+            .chunks_exact(2)
+            .filter_map(|pair| {
+                Some((
+                    *stringtable.get(pair[0] as usize)?,
+                    *stringtable.get(pair[1] as usize)?,
+                ))
+            })
+    }
+}
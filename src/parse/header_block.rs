@@ -0,0 +1,113 @@
+use protobuf_iter::*;
+
+const NANO: f64 = 1.0e-9;
+
+/// Bounding box of a file, in degrees
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeaderBBox {
+    pub left: f64,
+    pub right: f64,
+    pub top: f64,
+    pub bottom: f64,
+}
+
+impl HeaderBBox {
+    pub fn parse(data: &[u8]) -> Self {
+        let mut bbox = HeaderBBox::default();
+        for m in MessageIter::new(data) {
+            let value = NANO * Into::<i64>::into(m.value) as f64;
+            match m.tag {
+                1 => bbox.left = value,
+                2 => bbox.right = value,
+                3 => bbox.top = value,
+                4 => bbox.bottom = value,
+                _ => (),
+            }
+        }
+        bbox
+    }
+}
+
+/// Contents of the `OSMHeader` blob at the start of a file
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeaderBlock {
+    pub bbox: Option<HeaderBBox>,
+    pub required_features: Vec<String>,
+    pub optional_features: Vec<String>,
+    pub writingprogram: Option<String>,
+    pub source: Option<String>,
+    pub osmosis_replication_timestamp: Option<i64>,
+    pub osmosis_replication_sequence_number: Option<i64>,
+    pub osmosis_replication_base_url: Option<String>,
+}
+
+fn parse_string(value: ParseValue) -> String {
+    String::from_utf8_lossy(value.get_data()).into_owned()
+}
+
+impl HeaderBlock {
+    pub fn parse(data: &[u8]) -> Self {
+        let mut header = HeaderBlock::default();
+        for m in MessageIter::new(data) {
+            match m.tag {
+                1 => header.bbox = Some(HeaderBBox::parse(*m.value)),
+                4 => header.required_features.push(parse_string(m.value)),
+                5 => header.optional_features.push(parse_string(m.value)),
+                16 => header.writingprogram = Some(parse_string(m.value)),
+                17 => header.source = Some(parse_string(m.value)),
+                // int64, not zigzag-encoded
+                32 => {
+                    header.osmosis_replication_timestamp = Some(Into::<u64>::into(m.value) as i64)
+                }
+                33 => {
+                    header.osmosis_replication_sequence_number =
+                        Some(Into::<u64>::into(m.value) as i64)
+                }
+                34 => header.osmosis_replication_base_url = Some(parse_string(m.value)),
+                _ => (),
+            }
+        }
+        header
+    }
+
+    /// Whether `feature` is listed as either required or optional
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.required_features
+            .iter()
+            .chain(self.optional_features.iter())
+            .any(|f| f == feature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderBlock;
+
+    #[test]
+    fn test_parse() {
+        let data = [
+            // bbox: left=-1000000000 (zigzag 1999999999), top=2000000000
+            &[
+                0x0a, 0x0c, 0x08, 0xff, 0xa7, 0xd6, 0xb9, 0x07, 0x18, 0x80, 0xd0, 0xac, 0xf3, 0x0e,
+            ][..],
+            // required_features
+            &[0x22, 0x0e],
+            b"OsmSchema-V0.6",
+            // optional_features
+            &[0x2a, 0x11],
+            b"Sort.Type_then_ID",
+            // writingprogram
+            &[0x82, 0x01, 0x04],
+            b"test",
+        ]
+        .concat();
+        let header = HeaderBlock::parse(&data);
+        let bbox = header.bbox.as_ref().unwrap();
+        assert_eq!(bbox.left, -1.0);
+        assert_eq!(bbox.top, 2.0);
+        assert_eq!(header.required_features, vec!["OsmSchema-V0.6"]);
+        assert!(header.has_feature("Sort.Type_then_ID"));
+        assert!(!header.has_feature("HistoricalInformation"));
+        assert_eq!(header.writingprogram.as_deref(), Some("test"));
+    }
+}
@@ -2,10 +2,13 @@ use std::convert::Into;
 
 use protobuf_iter::*;
 
+use crate::Timestamp;
+use crate::primitive_block::PrimitiveBlock;
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Info<'a> {
     pub version: Option<u32>,
-    pub timestamp: Option<u64>,
+    pub timestamp: Option<Timestamp>,
     pub changeset: Option<u64>,
     pub uid: Option<u32>,
     pub user: Option<&'a str>,
@@ -13,7 +16,7 @@ pub struct Info<'a> {
 }
 
 impl<'a> Info<'a> {
-    pub fn parse(stringtable: &'a [&'a str], data: &'a [u8]) -> Self {
+    pub fn parse(primitive_block: &'a PrimitiveBlock<'a>, data: &'a [u8]) -> Self {
         let mut info = Info {
             version: None,
             timestamp: None,
@@ -27,12 +30,12 @@ impl<'a> Info<'a> {
         for m in iter {
             match m.tag {
                 1 => info.version = Some(m.value.into()),
-                2 => info.timestamp = Some(m.value.into()),
+                2 => info.timestamp = Some(primitive_block.convert_date(m.value.into())),
                 3 => info.changeset = Some(m.value.into()),
                 4 => info.uid = Some(m.value.into()),
                 5 => {
                     let user_sid: u32 = m.value.into();
-                    info.user = Some(stringtable[user_sid as usize]);
+                    info.user = Some(primitive_block.stringtable[user_sid as usize]);
                 }
                 6 => {
                     let visible: u32 = m.value.into();
@@ -9,5 +9,9 @@ pub use self::relation::{Relation, RelationMemberType};
 pub mod dense_nodes;
 pub mod info;
 pub mod tags;
-pub use self::dense_nodes::DenseNodesParser;
+pub use self::dense_nodes::{DenseNodesColumns, DenseNodesParser};
+pub mod block_summary;
 pub mod dense_info;
+pub use self::block_summary::{BlockSummary, KindSummary};
+pub mod header_block;
+pub use self::header_block::{HeaderBBox, HeaderBlock};
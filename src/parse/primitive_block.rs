@@ -1,10 +1,13 @@
 use protobuf_iter::*;
 use std::str::from_utf8_unchecked;
 
+use super::block_summary::BlockSummary;
 use super::dense_nodes::DenseNodesParser;
 use super::node::Node;
 use super::relation::Relation;
 use super::way::Way;
+use crate::Timestamp;
+use crate::delta::DeltaEncodedIter;
 
 const NANO: f64 = 1.0e-9;
 
@@ -60,9 +63,61 @@ impl<'a> PrimitiveBlock<'a> {
         NANO * (self.lon_offset + self.granularity as i64 * lon) as f64
     }
 
-    // should return timestamp in milliseconds since 1970
-    pub fn convert_date(&self, date: u64) -> u64 {
-        self.date_granularity * date
+    pub fn convert_date(&self, date: u64) -> Timestamp {
+        Timestamp::from_millis(self.date_granularity * date)
+    }
+
+    /// Counts elements and their ID ranges by decoding only the ID columns
+    pub fn summary(&self) -> BlockSummary {
+        let mut summary = BlockSummary::default();
+        for group in self.iter.clone().tag::<MessageIter<'a>>(2) {
+            for m in group {
+                match m.tag {
+                    // node
+                    1 => {
+                        let id = MessageIter::new(*m.value).tag::<i64>(1).next().unwrap_or(0);
+                        summary.nodes.add(id as u64);
+                    }
+
+                    // dense_nodes
+                    2 => {
+                        if let Some(ids) =
+                            MessageIter::new(*m.value).tag::<ParseValue<'a>>(1).next()
+                        {
+                            let ids: DeltaEncodedIter<'a, PackedVarint, i64> =
+                                DeltaEncodedIter::new(ids);
+                            for id in ids {
+                                summary.nodes.add(id as u64);
+                            }
+                        }
+                    }
+
+                    // way
+                    3 => {
+                        let id = MessageIter::new(*m.value).tag::<u64>(1).next().unwrap_or(0);
+                        summary.ways.add(id);
+                    }
+
+                    // relation
+                    4 => {
+                        let id = MessageIter::new(*m.value).tag::<u64>(1).next().unwrap_or(0);
+                        summary.relations.add(id);
+                    }
+
+                    _ => (),
+                }
+            }
+        }
+        summary
+    }
+
+    /// Yields the raw `DenseNodes` messages of all primitive groups, to be
+    /// decoded with `DenseNodesColumns::decode()`
+    pub fn dense_nodes(&self) -> impl Iterator<Item = &'a [u8]> + use<'a> {
+        self.iter
+            .clone()
+            .tag::<MessageIter<'a>>(2)
+            .flat_map(|group| group.tag::<&'a [u8]>(2))
     }
 
     // TODO: just Iterator
@@ -24,6 +24,13 @@ impl<'a> TagsIter<'a> {
     pub fn set_values(&mut self, buf: &'a [u8]) {
         self.values = Some(PackedIter::new(buf));
     }
+
+    /// The `stringtable` indices of the keys and values, unresolved
+    pub fn indices(&self) -> impl Iterator<Item = (u32, u32)> + 'a {
+        let keys = self.keys.clone().into_iter().flatten();
+        let values = self.values.clone().into_iter().flatten();
+        keys.zip(values)
+    }
 }
 
 impl<'a> Iterator for TagsIter<'a> {
@@ -84,6 +91,15 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn test_indices() {
+        let mut iter = TagsIter::new(&STRINGTABLE);
+        iter.set_keys(&[0, 3, 4]);
+        iter.set_values(&[2, 1]);
+        assert_eq!(iter.indices().collect::<Vec<_>>(), vec![(0, 2), (3, 1)]);
+        assert_eq!(iter.next(), Some(("highway", "crossing")));
+    }
+
     #[test]
     fn test_no_keys() {
         let mut iter = TagsIter::new(&STRINGTABLE);
@@ -0,0 +1,566 @@
+//! Writing of PBF files
+//!
+//! Elements are collected into blocks of one element type each, with nodes
+//! written as `DenseNodes`. Blocks use the default granularities, so
+//! locations are stored to 1e-7 degrees and timestamps to seconds.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use libdeflater::{CompressionLvl, Compressor};
+
+use crate::owned::{Element, Info, Node, Relation, Way};
+use crate::{HeaderBlock, Primitive, RelationMemberType};
+
+/// Elements per block, as recommended by the format description
+pub const BLOCK_ELEMENTS: usize = 8000;
+
+const REQUIRED_FEATURES: &[&str] = &["OsmSchema-V0.6", "DenseNodes"];
+
+/// A protobuf message being encoded
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(((field as u64) << 3) | wire_type as u64);
+    }
+
+    fn uint(&mut self, field: u32, value: u64) {
+        self.key(field, 0);
+        self.varint(value);
+    }
+
+    fn sint(&mut self, field: u32, value: i64) {
+        self.uint(field, zigzag(value));
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, 2);
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+
+    fn packed<I: IntoIterator<Item = u64>>(&mut self, field: u32, values: I) {
+        let mut packed = Message::default();
+        for value in values {
+            packed.varint(value);
+        }
+        if !packed.0.is_empty() {
+            self.bytes(field, &packed.0);
+        }
+    }
+
+    fn packed_delta<I: IntoIterator<Item = i64>>(&mut self, field: u32, values: I) {
+        let mut last = 0i64;
+        self.packed(
+            field,
+            values.into_iter().map(|value| {
+                let delta = value.wrapping_sub(last);
+                last = value;
+                zigzag(delta)
+            }),
+        );
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn to_fixed(degrees: f64) -> i64 {
+    // Nanodegrees divided by the default granularity of 100
+    (degrees * 1e7).round() as i64
+}
+
+/// Strings of one block; index 0 stays empty as required
+struct StringTable {
+    indices: HashMap<String, u32>,
+    strings: Vec<String>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable {
+            indices: HashMap::new(),
+            strings: vec![String::new()],
+        }
+    }
+
+    /// Empty strings get an index of their own too, as 0 ends the tags of
+    /// a dense node
+    fn index(&mut self, s: &str) -> u32 {
+        if let Some(index) = self.indices.get(s) {
+            return *index;
+        }
+        let index = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.indices.insert(s.to_string(), index);
+        index
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut message = Message::default();
+        for s in &self.strings {
+            message.bytes(1, s.as_bytes());
+        }
+        message.0
+    }
+}
+
+fn member_type_number(member_type: &RelationMemberType) -> u64 {
+    match member_type {
+        RelationMemberType::Node => 0,
+        RelationMemberType::Way => 1,
+        RelationMemberType::Relation => 2,
+    }
+}
+
+fn encode_header(header: &HeaderBlock) -> Vec<u8> {
+    let mut message = Message::default();
+    if let Some(bbox) = &header.bbox {
+        let mut bbox_message = Message::default();
+        for (field, value) in [
+            (1, bbox.left),
+            (2, bbox.right),
+            (3, bbox.top),
+            (4, bbox.bottom),
+        ] {
+            bbox_message.sint(field, (value * 1e9).round() as i64);
+        }
+        message.bytes(1, &bbox_message.0);
+    }
+    for feature in &header.required_features {
+        message.bytes(4, feature.as_bytes());
+    }
+    for feature in &header.optional_features {
+        message.bytes(5, feature.as_bytes());
+    }
+    if let Some(writingprogram) = &header.writingprogram {
+        message.bytes(16, writingprogram.as_bytes());
+    }
+    if let Some(source) = &header.source {
+        message.bytes(17, source.as_bytes());
+    }
+    if let Some(timestamp) = header.osmosis_replication_timestamp {
+        message.uint(32, timestamp as u64);
+    }
+    if let Some(sequence_number) = header.osmosis_replication_sequence_number {
+        message.uint(33, sequence_number as u64);
+    }
+    if let Some(base_url) = &header.osmosis_replication_base_url {
+        message.bytes(34, base_url.as_bytes());
+    }
+    message.0
+}
+
+fn encode_info(info: &Info, stringtable: &mut StringTable) -> Vec<u8> {
+    let mut message = Message::default();
+    if let Some(version) = info.version {
+        message.uint(1, version as u64);
+    }
+    if let Some(timestamp) = info.timestamp {
+        message.uint(2, timestamp.secs());
+    }
+    if let Some(changeset) = info.changeset {
+        message.uint(3, changeset);
+    }
+    if let Some(uid) = info.uid {
+        message.uint(4, uid as u64);
+    }
+    if let Some(user) = &info.user {
+        message.uint(5, stringtable.index(user) as u64);
+    }
+    if let Some(visible) = info.visible {
+        message.uint(6, visible as u64);
+    }
+    message.0
+}
+
+fn encode_dense_nodes(nodes: &[Node], stringtable: &mut StringTable) -> Vec<u8> {
+    let mut message = Message::default();
+    message.packed_delta(1, nodes.iter().map(|node| node.id as i64));
+
+    if nodes.iter().any(|node| node.info.is_some()) {
+        let default_info = Info::default();
+        let infos: Vec<&Info> = nodes
+            .iter()
+            .map(|node| node.info.as_ref().unwrap_or(&default_info))
+            .collect();
+        let mut dense_info = Message::default();
+        dense_info.packed(1, infos.iter().map(|info| info.version.unwrap_or(0) as u64));
+        dense_info.packed_delta(
+            2,
+            infos
+                .iter()
+                .map(|info| info.timestamp.unwrap_or_default().secs() as i64),
+        );
+        dense_info.packed_delta(
+            3,
+            infos.iter().map(|info| info.changeset.unwrap_or(0) as i64),
+        );
+        dense_info.packed_delta(4, infos.iter().map(|info| info.uid.unwrap_or(0) as i64));
+        let user_sids: Vec<i64> = infos
+            .iter()
+            .map(|info| stringtable.index(info.user.as_deref().unwrap_or("")) as i64)
+            .collect();
+        dense_info.packed_delta(5, user_sids);
+        if infos.iter().any(|info| info.visible.is_some()) {
+            dense_info.packed(
+                6,
+                infos.iter().map(|info| info.visible.unwrap_or(true) as u64),
+            );
+        }
+        message.bytes(5, &dense_info.0);
+    }
+
+    message.packed_delta(8, nodes.iter().map(|node| to_fixed(node.lat)));
+    message.packed_delta(9, nodes.iter().map(|node| to_fixed(node.lon)));
+
+    // Written even without any tags, as the parser expects it
+    let mut keys_vals = vec![];
+    for node in nodes {
+        for (key, value) in &node.tags {
+            keys_vals.push(stringtable.index(key) as u64);
+            keys_vals.push(stringtable.index(value) as u64);
+        }
+        keys_vals.push(0);
+    }
+    message.packed(10, keys_vals);
+    message.0
+}
+
+fn encode_tags(message: &mut Message, tags: &[(String, String)], stringtable: &mut StringTable) {
+    let keys: Vec<u64> = tags
+        .iter()
+        .map(|(key, _)| stringtable.index(key) as u64)
+        .collect();
+    let values: Vec<u64> = tags
+        .iter()
+        .map(|(_, value)| stringtable.index(value) as u64)
+        .collect();
+    message.packed(2, keys);
+    message.packed(3, values);
+}
+
+fn encode_way(way: &Way, stringtable: &mut StringTable) -> Vec<u8> {
+    let mut message = Message::default();
+    message.uint(1, way.id);
+    encode_tags(&mut message, &way.tags, stringtable);
+    if let Some(info) = &way.info {
+        message.bytes(4, &encode_info(info, stringtable));
+    }
+    message.packed_delta(8, way.refs.iter().map(|id| *id as i64));
+    message.0
+}
+
+fn encode_relation(relation: &Relation, stringtable: &mut StringTable) -> Vec<u8> {
+    let mut message = Message::default();
+    message.uint(1, relation.id);
+    encode_tags(&mut message, &relation.tags, stringtable);
+    if let Some(info) = &relation.info {
+        message.bytes(4, &encode_info(info, stringtable));
+    }
+    let roles: Vec<u64> = relation
+        .members
+        .iter()
+        .map(|member| stringtable.index(&member.role) as u64)
+        .collect();
+    message.packed(8, roles);
+    message.packed_delta(9, relation.members.iter().map(|member| member.id as i64));
+    message.packed(
+        10,
+        relation
+            .members
+            .iter()
+            .map(|member| member_type_number(&member.member_type)),
+    );
+    message.0
+}
+
+/// The elements of the block being collected, all of one type
+enum Pending {
+    Nodes(Vec<Node>),
+    Ways(Vec<Way>),
+    Relations(Vec<Relation>),
+}
+
+impl Pending {
+    fn len(&self) -> usize {
+        match self {
+            Pending::Nodes(nodes) => nodes.len(),
+            Pending::Ways(ways) => ways.len(),
+            Pending::Relations(relations) => relations.len(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut stringtable = StringTable::new();
+        let mut group = Message::default();
+        match self {
+            Pending::Nodes(nodes) => group.bytes(2, &encode_dense_nodes(nodes, &mut stringtable)),
+            Pending::Ways(ways) => {
+                for way in ways {
+                    group.bytes(3, &encode_way(way, &mut stringtable));
+                }
+            }
+            Pending::Relations(relations) => {
+                for relation in relations {
+                    group.bytes(4, &encode_relation(relation, &mut stringtable));
+                }
+            }
+        }
+        let mut block = Message::default();
+        block.bytes(1, &stringtable.encode());
+        block.bytes(2, &group.0);
+        block.0
+    }
+}
+
+pub struct PbfWriter<W: Write> {
+    out: W,
+    compressor: Compressor,
+    pending: Option<Pending>,
+}
+
+impl<W: Write> PbfWriter<W> {
+    /// Starts a file with `header`, adding the features this writer requires
+    pub fn new(out: W, header: &HeaderBlock) -> io::Result<Self> {
+        let mut header = header.clone();
+        for feature in REQUIRED_FEATURES {
+            if !header.required_features.iter().any(|f| f == feature) {
+                header.required_features.push(feature.to_string());
+            }
+        }
+        let mut writer = PbfWriter {
+            out,
+            compressor: Compressor::new(CompressionLvl::default()),
+            pending: None,
+        };
+        writer.write_blob("OSMHeader", &encode_header(&header))?;
+        Ok(writer)
+    }
+
+    pub fn write_primitive(&mut self, primitive: &Primitive) -> io::Result<()> {
+        self.write_element(Element::from(primitive))
+    }
+
+    pub fn write_element(&mut self, element: Element) -> io::Result<()> {
+        let same_type = matches!(
+            (&self.pending, &element),
+            (Some(Pending::Nodes(_)), Element::Node(_))
+                | (Some(Pending::Ways(_)), Element::Way(_))
+                | (Some(Pending::Relations(_)), Element::Relation(_))
+        );
+        if !same_type {
+            self.flush_block()?;
+        }
+        match (&mut self.pending, element) {
+            (Some(Pending::Nodes(nodes)), Element::Node(node)) => nodes.push(node),
+            (Some(Pending::Ways(ways)), Element::Way(way)) => ways.push(way),
+            (Some(Pending::Relations(relations)), Element::Relation(relation)) => {
+                relations.push(relation)
+            }
+            (_, Element::Node(node)) => self.pending = Some(Pending::Nodes(vec![node])),
+            (_, Element::Way(way)) => self.pending = Some(Pending::Ways(vec![way])),
+            (_, Element::Relation(relation)) => {
+                self.pending = Some(Pending::Relations(vec![relation]))
+            }
+        }
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|pending| pending.len() >= BLOCK_ELEMENTS)
+        {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    /// Writes out the last block, and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_block()?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn flush_block(&mut self) -> io::Result<()> {
+        match self.pending.take() {
+            Some(pending) => self.write_blob("OSMData", &pending.encode()),
+            None => Ok(()),
+        }
+    }
+
+    fn write_blob(&mut self, blob_type: &str, data: &[u8]) -> io::Result<()> {
+        let mut compressed = vec![0; self.compressor.zlib_compress_bound(data.len())];
+        let len = self
+            .compressor
+            .zlib_compress(data, &mut compressed)
+            .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+        let mut blob = Message::default();
+        blob.uint(2, data.len() as u64);
+        blob.bytes(3, &compressed[..len]);
+
+        let mut blob_header = Message::default();
+        blob_header.bytes(1, blob_type.as_bytes());
+        blob_header.uint(3, blob.0.len() as u64);
+
+        self.out
+            .write_all(&(blob_header.0.len() as u32).to_be_bytes())?;
+        self.out.write_all(&blob_header.0)?;
+        self.out.write_all(&blob.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::owned::Member;
+    use crate::{BlobReader, PrimitiveBlock, Timestamp};
+
+    fn info(version: u32) -> Option<Info> {
+        Some(Info {
+            version: Some(version),
+            timestamp: Some(Timestamp::from_secs(1_259_186_353)),
+            changeset: Some(7),
+            uid: Some(3),
+            user: Some("fred".to_string()),
+            visible: None,
+        })
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let elements = vec![
+            Element::Node(Node {
+                id: 1,
+                lat: 52.5,
+                lon: -13.25,
+                info: info(1),
+                tags: vec![],
+            }),
+            Element::Node(Node {
+                id: 2,
+                lat: -1.0,
+                lon: 179.9999999,
+                info: info(2),
+                tags: vec![("amenity".to_string(), "bench".to_string())],
+            }),
+            Element::Way(Way {
+                id: 3,
+                info: None,
+                tags: vec![("highway".to_string(), "path".to_string())],
+                refs: vec![2, 1],
+            }),
+            Element::Relation(Relation {
+                id: 4,
+                info: None,
+                tags: vec![("type".to_string(), "route".to_string())],
+                members: vec![
+                    Member {
+                        member_type: RelationMemberType::Way,
+                        id: 3,
+                        role: "".to_string(),
+                    },
+                    Member {
+                        member_type: RelationMemberType::Node,
+                        id: 1,
+                        role: "stop".to_string(),
+                    },
+                ],
+            }),
+        ];
+
+        let header = HeaderBlock {
+            writingprogram: Some("test".to_string()),
+            ..HeaderBlock::default()
+        };
+        let mut writer = PbfWriter::new(vec![], &header).unwrap();
+        for element in &elements {
+            writer.write_element(element.clone()).unwrap();
+        }
+        let out = writer.finish().unwrap();
+
+        let mut reader = BlobReader::new(&out[..]);
+        let mut read = vec![];
+        for blob in reader.by_ref() {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            for primitive in primitive_block.primitives() {
+                let mut element = Element::from(&primitive);
+                if let Element::Node(node) = &mut element {
+                    node.lat = (node.lat * 1e7).round() / 1e7;
+                    node.lon = (node.lon * 1e7).round() / 1e7;
+                }
+                read.push(element);
+            }
+        }
+        assert_eq!(read, elements);
+        let header = reader.header().unwrap();
+        assert_eq!(header.writingprogram.as_deref(), Some("test"));
+        assert_eq!(header.required_features, REQUIRED_FEATURES);
+    }
+
+    #[test]
+    fn test_date_granularity() {
+        let millis = 1_259_186_353_123;
+        let mut info = Message::default();
+        info.uint(1, 1);
+        info.uint(2, millis);
+        info.uint(5, 1);
+
+        let mut node = Message::default();
+        node.sint(1, 1);
+        node.bytes(4, &info.0);
+        node.sint(8, 0);
+        node.sint(9, 0);
+        let mut way = Message::default();
+        way.uint(1, 3);
+        way.bytes(4, &info.0);
+        let mut sparse_group = Message::default();
+        sparse_group.bytes(1, &node.0);
+        sparse_group.bytes(3, &way.0);
+
+        let mut dense_info = Message::default();
+        dense_info.packed(1, [1]);
+        dense_info.packed_delta(2, [millis as i64]);
+        dense_info.packed_delta(3, [0]);
+        dense_info.packed_delta(4, [0]);
+        dense_info.packed_delta(5, [1]);
+        let mut dense = Message::default();
+        dense.packed_delta(1, [2]);
+        dense.bytes(5, &dense_info.0);
+        dense.packed_delta(8, [0]);
+        dense.packed_delta(9, [0]);
+        dense.packed(10, [0]);
+        let mut dense_group = Message::default();
+        dense_group.bytes(2, &dense.0);
+
+        let mut stringtable = StringTable::new();
+        stringtable.index("fred");
+        let mut block = Message::default();
+        block.bytes(1, &stringtable.encode());
+        block.bytes(2, &sparse_group.0);
+        block.bytes(2, &dense_group.0);
+        block.uint(18, 1);
+
+        let primitive_block = PrimitiveBlock::parse(&block.0);
+        let infos: Vec<Info> = primitive_block
+            .primitives()
+            .map(|primitive| Element::from(&primitive).info().cloned().unwrap())
+            .collect();
+        assert_eq!(infos.len(), 3);
+        for info in infos {
+            assert_eq!(info.timestamp, Some(Timestamp::from_millis(millis)));
+            assert_eq!(info.user.as_deref(), Some("fred"));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Osmosis polygon filter files, as used for the extracts of Geofabrik
+//!
+//! After a name line, the file lists rings, each as a name line, one
+//! `lon lat` line per vertex, and `END`; a final `END` closes the file.
+//! Rings whose name starts with `!` are holes in the preceding outer ring.
+
+use crate::Location;
+use crate::geometry::{MultiPolygon, Polygon, Ring};
+
+/// Returns `None` if the file is malformed, or has no outer ring
+pub fn read_poly(s: &str) -> Option<MultiPolygon> {
+    let mut lines = s.lines().map(str::trim).filter(|line| !line.is_empty());
+    // The name of the whole file
+    lines.next()?;
+
+    let mut multipolygon = MultiPolygon::default();
+    loop {
+        let name = lines.next()?;
+        if name == "END" {
+            break;
+        }
+        let ring = read_ring(&mut lines)?;
+        if name.starts_with('!') {
+            multipolygon.polygons.last_mut()?.inners.push(ring);
+        } else {
+            multipolygon.polygons.push(Polygon {
+                outer: ring,
+                inners: vec![],
+            });
+        }
+    }
+    if multipolygon.polygons.is_empty() {
+        return None;
+    }
+    Some(multipolygon)
+}
+
+/// Reads vertices up to and including `END`, closing the ring if needed
+fn read_ring<'a, I: Iterator<Item = &'a str>>(lines: &mut I) -> Option<Ring> {
+    let mut ring = Ring::new();
+    loop {
+        let line = lines.next()?;
+        if line == "END" {
+            break;
+        }
+        let mut coordinates = line.split_whitespace().map(|s| s.parse::<f64>().ok());
+        let (lon, lat) = (coordinates.next()??, coordinates.next()??);
+        if coordinates.next().is_some() {
+            return None;
+        }
+        ring.push(Location::new(lat, lon));
+    }
+    if ring.len() < 3 {
+        return None;
+    }
+    if ring.first() != ring.last() {
+        ring.push(ring[0]);
+    }
+    Some(ring)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_poly() {
+        let poly = "test
+1
+   0.0E+00   0.0E+00
+   4.0   0.0
+   4.0   4.0
+   0.0   4.0
+END
+!1
+   1 1
+   2 1
+   2 2
+   1 2
+   1 1
+END
+
+2
+   10 10
+   11 10
+   11 11
+END
+END
+";
+        let multipolygon = read_poly(poly).unwrap();
+        assert_eq!(multipolygon.polygons.len(), 2);
+        assert_eq!(multipolygon.polygons[0].outer.len(), 5);
+        assert_eq!(multipolygon.polygons[0].inners.len(), 1);
+        assert_eq!(multipolygon.polygons[0].inners[0].len(), 5);
+        assert!(multipolygon.contains(Location::new(3.0, 0.5)));
+        assert!(!multipolygon.contains(Location::new(1.5, 1.5)));
+        assert!(multipolygon.contains(Location::new(10.2, 10.5)));
+        assert!(!multipolygon.contains(Location::new(5.0, 5.0)));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(read_poly("").is_none());
+        // Missing final END
+        assert!(read_poly("x\n1\n0 0\n1 0\n1 1\nEND\n").is_none());
+        // Hole without outer ring
+        assert!(read_poly("x\n!1\n0 0\n1 0\n1 1\nEND\nEND\n").is_none());
+        assert!(read_poly("x\n1\n0 0\n1 x\n1 1\nEND\nEND\n").is_none());
+        assert!(read_poly("x\n1\n0 0\n1 0\nEND\nEND\n").is_none());
+        assert!(read_poly("x\nEND\n").is_none());
+    }
+}
@@ -0,0 +1,223 @@
+//! Two-pass resolution of relation members
+//!
+//! Members may appear anywhere in the file, so the first pass, driven with
+//! `apply()`, selects relations and records which members they need. The
+//! second pass, `RelationCollector::collect()`, gathers just those members.
+//! Members are released once every relation needing them is complete.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::owned;
+use crate::tags::TagsIter;
+use crate::{BlobReader, Handler, Primitive, PrimitiveBlock, Relation, RelationMemberType};
+
+/// Members gathered for one relation, by ID
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Members {
+    pub nodes: HashMap<u64, owned::Node>,
+    pub ways: HashMap<u64, owned::Way>,
+    pub relations: HashMap<u64, owned::Relation>,
+    /// Members that did not appear in the file; empty for complete relations
+    pub missing: Vec<(RelationMemberType, u64)>,
+}
+
+impl Members {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+struct PendingRelation {
+    relation: owned::Relation,
+    missing: usize,
+}
+
+/// Members of one type: which relations need each ID, and those already found
+struct Wanted<T> {
+    by: HashMap<u64, Vec<usize>>,
+    found: HashMap<u64, (T, usize)>,
+}
+
+impl<T> Default for Wanted<T> {
+    fn default() -> Self {
+        Wanted {
+            by: HashMap::new(),
+            found: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone> Wanted<T> {
+    fn add(&mut self, id: u64, index: usize) -> bool {
+        let by = self.by.entry(id).or_default();
+        if by.last() == Some(&index) {
+            return false;
+        }
+        by.push(index);
+        true
+    }
+
+    /// Keeps a member that was wanted, returning the relations waiting for it
+    fn found(&mut self, id: u64, element: impl FnOnce() -> T) -> Vec<usize> {
+        match self.by.remove(&id) {
+            Some(indices) => {
+                self.found.insert(id, (element(), indices.len()));
+                indices
+            }
+            None => vec![],
+        }
+    }
+
+    /// Hands out a copy, dropping the member after its last user
+    fn take(&mut self, id: u64) -> Option<T> {
+        let (element, users) = self.found.get_mut(&id)?;
+        *users -= 1;
+        if *users == 0 {
+            self.found.remove(&id).map(|(element, _)| element)
+        } else {
+            Some(element.clone())
+        }
+    }
+}
+
+pub struct RelationCollector<P> {
+    predicate: P,
+    relations: Vec<Option<PendingRelation>>,
+    nodes: Wanted<owned::Node>,
+    ways: Wanted<owned::Way>,
+    member_relations: Wanted<owned::Relation>,
+}
+
+impl<P: FnMut(TagsIter) -> bool> RelationCollector<P> {
+    /// Selects the relations for whose tags `predicate` returns true
+    pub fn new(predicate: P) -> Self {
+        RelationCollector {
+            predicate,
+            relations: vec![],
+            nodes: Wanted::default(),
+            ways: Wanted::default(),
+            member_relations: Wanted::default(),
+        }
+    }
+
+    /// Number of relations selected in the first pass
+    pub fn len(&self) -> usize {
+        self.relations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.relations.is_empty()
+    }
+
+    /// Second pass over the same file; calls `f` for every selected relation
+    /// as soon as all its members have been seen
+    ///
+    /// Relations with members absent from the file are passed at the end,
+    /// with `Members::missing` listing those.
+    pub fn collect<R, F>(mut self, reader: &mut BlobReader<R>, mut f: F)
+    where
+        R: Read,
+        F: FnMut(owned::Relation, Members),
+    {
+        for index in 0..self.relations.len() {
+            if self.relations[index]
+                .as_ref()
+                .is_some_and(|pending| pending.missing == 0)
+            {
+                self.complete(index, &mut f);
+            }
+        }
+
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            for primitive in primitive_block.primitives() {
+                let indices = match &primitive {
+                    Primitive::Node(node) => self.nodes.found(node.id, || node.into()),
+                    Primitive::Way(way) => self.ways.found(way.id, || way.into()),
+                    Primitive::Relation(relation) => {
+                        self.member_relations.found(relation.id, || relation.into())
+                    }
+                };
+                for index in indices {
+                    let pending = self.relations[index].as_mut().unwrap();
+                    pending.missing -= 1;
+                    if pending.missing == 0 {
+                        self.complete(index, &mut f);
+                    }
+                }
+            }
+        }
+
+        for index in 0..self.relations.len() {
+            if self.relations[index].is_some() {
+                self.complete(index, &mut f);
+            }
+        }
+    }
+
+    fn complete<F: FnMut(owned::Relation, Members)>(&mut self, index: usize, f: &mut F) {
+        let Some(pending) = self.relations[index].take() else {
+            return;
+        };
+        let mut members = Members::default();
+        for member in &pending.relation.members {
+            let id = member.id;
+            let found = match member.member_type {
+                RelationMemberType::Node => {
+                    members.nodes.contains_key(&id)
+                        || self
+                            .nodes
+                            .take(id)
+                            .map(|node| members.nodes.insert(id, node))
+                            .is_some()
+                }
+                RelationMemberType::Way => {
+                    members.ways.contains_key(&id)
+                        || self
+                            .ways
+                            .take(id)
+                            .map(|way| members.ways.insert(id, way))
+                            .is_some()
+                }
+                RelationMemberType::Relation => {
+                    members.relations.contains_key(&id)
+                        || self
+                            .member_relations
+                            .take(id)
+                            .map(|relation| members.relations.insert(id, relation))
+                            .is_some()
+                }
+            };
+            let missing = (member.member_type.clone(), id);
+            if !found && !members.missing.contains(&missing) {
+                members.missing.push(missing);
+            }
+        }
+        f(pending.relation, members);
+    }
+}
+
+impl<P: FnMut(TagsIter) -> bool> Handler for RelationCollector<P> {
+    fn relation(&mut self, relation: &Relation) {
+        if !(self.predicate)(relation.tags()) {
+            return;
+        }
+        let index = self.relations.len();
+        let relation = owned::Relation::from(relation);
+        let mut missing = 0;
+        for member in &relation.members {
+            let added = match member.member_type {
+                RelationMemberType::Node => self.nodes.add(member.id, index),
+                RelationMemberType::Way => self.ways.add(member.id, index),
+                RelationMemberType::Relation => self.member_relations.add(member.id, index),
+            };
+            if added {
+                missing += 1;
+            }
+        }
+        self.relations
+            .push(Some(PendingRelation { relation, missing }));
+    }
+}
@@ -0,0 +1,217 @@
+//! Checking that elements come sorted by type then ID
+//!
+//! Files declaring the `Sort.Type_then_ID` feature list nodes, then ways,
+//! then relations, each by increasing ID. In history files, the versions of
+//! an element follow each other by increasing version.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::HeaderBlock;
+use crate::owned::Element;
+
+pub const SORT_TYPE_THEN_ID: &str = "Sort.Type_then_ID";
+
+pub fn is_sorted_file(header: &HeaderBlock) -> bool {
+    header.has_feature(SORT_TYPE_THEN_ID)
+}
+
+/// What went wrong between two consecutive elements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// A node after a way or relation, or a way after a relation
+    Type,
+    /// An ID lower than the previous one of the same type
+    Id,
+    /// The same element twice, except for increasing versions in history
+    /// files
+    Duplicate,
+}
+
+/// The first element out of order, and the one before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutOfOrder {
+    pub violation: Violation,
+    /// Position of the element in the input, from 0
+    pub index: u64,
+    pub previous: (u8, u64),
+    pub previous_version: Option<u32>,
+    pub current: (u8, u64),
+    pub version: Option<u32>,
+}
+
+fn format_element(
+    f: &mut fmt::Formatter,
+    (element_type, id): (u8, u64),
+    version: Option<u32>,
+) -> fmt::Result {
+    let prefix = ["n", "w", "r"][element_type as usize];
+    write!(f, "{}{}", prefix, id)?;
+    if let Some(version) = version {
+        write!(f, " v{}", version)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for OutOfOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "element {} out of order: ", self.index)?;
+        format_element(f, self.current, self.version)?;
+        write!(f, " after ")?;
+        format_element(f, self.previous, self.previous_version)
+    }
+}
+
+impl Error for OutOfOrder {}
+
+/// Passes elements through until the first one out of order, which yields
+/// an error and ends the iteration
+pub struct CheckSorted<I: Iterator<Item = Element>> {
+    elements: I,
+    history: bool,
+    index: u64,
+    previous: Option<((u8, u64), Option<u32>)>,
+    failed: bool,
+}
+
+impl<I: Iterator<Item = Element>> CheckSorted<I> {
+    /// With `history`, the same element may repeat with increasing versions
+    pub fn new<T: IntoIterator<IntoIter = I>>(elements: T, history: bool) -> Self {
+        CheckSorted {
+            elements: elements.into_iter(),
+            history,
+            index: 0,
+            previous: None,
+            failed: false,
+        }
+    }
+
+    fn check(&self, current: (u8, u64), version: Option<u32>) -> Option<Violation> {
+        let (previous, previous_version) = self.previous?;
+        if current.0 < previous.0 {
+            Some(Violation::Type)
+        } else if current < previous {
+            Some(Violation::Id)
+        } else if current == previous {
+            let newer = match (previous_version, version) {
+                (Some(previous_version), Some(version)) => version > previous_version,
+                _ => false,
+            };
+            (!self.history || !newer).then_some(Violation::Duplicate)
+        } else {
+            None
+        }
+    }
+}
+
+impl<I: Iterator<Item = Element>> Iterator for CheckSorted<I> {
+    type Item = Result<Element, OutOfOrder>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let element = self.elements.next()?;
+        let (current, version) = (element.sort_key(), element.version());
+        if let Some(violation) = self.check(current, version) {
+            self.failed = true;
+            let (previous, previous_version) = self.previous.unwrap();
+            return Some(Err(OutOfOrder {
+                violation,
+                index: self.index,
+                previous,
+                previous_version,
+                current,
+                version,
+            }));
+        }
+        self.index += 1;
+        self.previous = Some((current, version));
+        Some(Ok(element))
+    }
+}
+
+/// Returns the number of elements, or the first one out of order
+pub fn check_sorted<I: IntoIterator<Item = Element>>(
+    elements: I,
+    history: bool,
+) -> Result<u64, OutOfOrder> {
+    let mut count = 0;
+    for element in CheckSorted::new(elements, history) {
+        element?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opl::OplReader;
+    use std::io;
+
+    fn check(opl: &str, history: bool) -> Result<u64, OutOfOrder> {
+        let elements = OplReader::new(opl.as_bytes())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        check_sorted(elements, history)
+    }
+
+    #[test]
+    fn test_sorted() {
+        assert_eq!(check("", false), Ok(0));
+        assert_eq!(check("n1\nn5\nw2\nr1\nr3\n", false), Ok(5));
+
+        let error = check("n1\nw2\nn3\nw4\n", false).unwrap_err();
+        assert_eq!(error.violation, Violation::Type);
+        assert_eq!(
+            (error.index, error.previous, error.current),
+            (2, (1, 2), (0, 3))
+        );
+
+        let error = check("n1\nw5\nw4\n", false).unwrap_err();
+        assert_eq!(error.violation, Violation::Id);
+        assert_eq!(error.to_string(), "element 2 out of order: w4 after w5");
+
+        let error = check("n1\nn1\n", false).unwrap_err();
+        assert_eq!(error.violation, Violation::Duplicate);
+    }
+
+    #[test]
+    fn test_history() {
+        let opl = "n1 v1\nn1 v2\nn2 v1\nw1 v3\nw1 v4\n";
+        assert_eq!(check(opl, true), Ok(5));
+        assert_eq!(
+            check(opl, false).unwrap_err().violation,
+            Violation::Duplicate
+        );
+
+        let error = check("n1 v2\nn1 v2\n", true).unwrap_err();
+        assert_eq!(error.violation, Violation::Duplicate);
+        assert_eq!(
+            error.to_string(),
+            "element 1 out of order: n1 v2 after n1 v2"
+        );
+        assert!(check("n1 v2\nn1 v1\n", true).is_err());
+        assert!(check("n1\nn1\n", true).is_err());
+    }
+
+    #[test]
+    fn test_stops_after_error() {
+        let elements = OplReader::new("n2\nn1\nn3\n".as_bytes())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        let results: Vec<_> = CheckSorted::new(elements, false).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn test_is_sorted_file() {
+        let mut header = HeaderBlock::default();
+        assert!(!is_sorted_file(&header));
+        header.optional_features.push(SORT_TYPE_THEN_ID.to_string());
+        assert!(is_sorted_file(&header));
+    }
+}
@@ -0,0 +1,417 @@
+//! Filtering elements by their tags, with expressions like those of
+//! `osmium tags-filter`
+//!
+//! An expression is `[TYPES/]KEY[=VALUE[,VALUE...]]`, where `TYPES` is any
+//! combination of `n`, `w` and `r`, and keys and values may contain `*`
+//! wildcards. Expressions starting with `!` exclude what they match: an
+//! element matches a filter if it matches any of its other expressions, or
+//! there are none, and none of the negated ones.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::owned::Element;
+use crate::{Primitive, RelationMemberType};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidFilter(pub String);
+
+impl fmt::Display for InvalidFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid filter expression: {:?}", self.0)
+    }
+}
+
+impl Error for InvalidFilter {}
+
+/// A string with `*` wildcards, split at them
+#[derive(Debug, Clone)]
+struct Pattern {
+    parts: Vec<String>,
+}
+
+impl Pattern {
+    fn new(s: &str) -> Self {
+        Pattern {
+            parts: s.split('*').map(str::to_string).collect(),
+        }
+    }
+
+    fn matches(&self, s: &str) -> bool {
+        let (first, rest) = self.parts.split_first().unwrap();
+        let Some((last, middle)) = rest.split_last() else {
+            return s == first;
+        };
+        let Some(s) = s.strip_prefix(first.as_str()) else {
+            return false;
+        };
+        let Some(mut s) = s.strip_suffix(last.as_str()) else {
+            return false;
+        };
+        for part in middle {
+            match s.find(part.as_str()) {
+                Some(pos) => s = &s[pos + part.len()..],
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+fn type_index(member_type: &RelationMemberType) -> usize {
+    match member_type {
+        RelationMemberType::Node => 0,
+        RelationMemberType::Way => 1,
+        RelationMemberType::Relation => 2,
+    }
+}
+
+fn primitive_type(primitive: &Primitive) -> RelationMemberType {
+    match primitive {
+        Primitive::Node(_) => RelationMemberType::Node,
+        Primitive::Way(_) => RelationMemberType::Way,
+        Primitive::Relation(_) => RelationMemberType::Relation,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Expression {
+    types: [bool; 3],
+    negated: bool,
+    key: Pattern,
+    values: Option<Vec<Pattern>>,
+}
+
+impl Expression {
+    fn parse(s: &str) -> Option<Self> {
+        let (types, rest) = match s.split_once('/') {
+            Some((types, rest))
+                if !types.is_empty() && types.chars().all(|c| "nwr".contains(c)) =>
+            {
+                (
+                    [
+                        types.contains('n'),
+                        types.contains('w'),
+                        types.contains('r'),
+                    ],
+                    rest,
+                )
+            }
+            _ => ([true; 3], s),
+        };
+        let (negated, rest) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let (key, values) = match rest.split_once('=') {
+            Some((key, values)) => (key, Some(values.split(',').map(Pattern::new).collect())),
+            None => (rest, None),
+        };
+        if key.is_empty() {
+            return None;
+        }
+        Some(Expression {
+            types,
+            negated,
+            key: Pattern::new(key),
+            values,
+        })
+    }
+
+    fn matches_value(&self, value: &str) -> bool {
+        self.values
+            .as_ref()
+            .is_none_or(|values| values.iter().any(|pattern| pattern.matches(value)))
+    }
+}
+
+/// A compiled list of filter expressions
+#[derive(Debug, Clone)]
+pub struct TagsFilter {
+    expressions: Vec<Expression>,
+    has_positive: bool,
+}
+
+impl TagsFilter {
+    pub fn new<'s, I: IntoIterator<Item = &'s str>>(expressions: I) -> Result<Self, InvalidFilter> {
+        let expressions = expressions
+            .into_iter()
+            .map(|s| Expression::parse(s).ok_or_else(|| InvalidFilter(s.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+        let has_positive = expressions.iter().any(|expression| !expression.negated);
+        Ok(TagsFilter {
+            expressions,
+            has_positive,
+        })
+    }
+
+    /// Evaluates the tags of an element of the given type
+    pub fn matches<'t, I: IntoIterator<Item = (&'t str, &'t str)>>(
+        &self,
+        member_type: RelationMemberType,
+        tags: I,
+    ) -> bool {
+        let index = type_index(&member_type);
+        let mut matched = !self.has_positive;
+        for (key, value) in tags {
+            for expression in &self.expressions {
+                if expression.types[index]
+                    && expression.key.matches(key)
+                    && expression.matches_value(value)
+                {
+                    if expression.negated {
+                        return false;
+                    }
+                    matched = true;
+                }
+            }
+        }
+        matched
+    }
+
+    pub fn matches_primitive(&self, primitive: &Primitive) -> bool {
+        let member_type = primitive_type(primitive);
+        match primitive {
+            Primitive::Node(node) => self.matches(member_type, node.tags.iter().copied()),
+            Primitive::Way(way) => self.matches(member_type, way.tags()),
+            Primitive::Relation(relation) => self.matches(member_type, relation.tags()),
+        }
+    }
+
+    pub fn matches_element(&self, element: &Element) -> bool {
+        let member_type = match element {
+            Element::Node(_) => RelationMemberType::Node,
+            Element::Way(_) => RelationMemberType::Way,
+            Element::Relation(_) => RelationMemberType::Relation,
+        };
+        let tags = element.tags().iter();
+        self.matches(
+            member_type,
+            tags.map(|(key, value)| (key.as_str(), value.as_str())),
+        )
+    }
+
+    /// Resolves all expressions against the `stringtable` of a block, so
+    /// that its elements can be evaluated by string indices
+    pub fn for_block<'a>(&'a self, stringtable: &'a [&'a str]) -> BlockTagsFilter<'a> {
+        let resolved = self
+            .expressions
+            .iter()
+            .map(|expression| {
+                let keys = stringtable
+                    .iter()
+                    .map(|s| expression.key.matches(s))
+                    .collect::<Vec<_>>();
+                let values = expression.values.as_ref().map(|_| {
+                    stringtable
+                        .iter()
+                        .map(|s| expression.matches_value(s))
+                        .collect()
+                });
+                (keys, values)
+            })
+            .collect();
+        BlockTagsFilter {
+            filter: self,
+            stringtable,
+            resolved,
+        }
+    }
+}
+
+/// A `TagsFilter` resolved against the `stringtable` of one block
+pub struct BlockTagsFilter<'a> {
+    filter: &'a TagsFilter,
+    stringtable: &'a [&'a str],
+    /// Whether each string matches the key and values of each expression
+    resolved: Vec<(Vec<bool>, Option<Vec<bool>>)>,
+}
+
+impl<'a> BlockTagsFilter<'a> {
+    /// False if no element of the given type in this block can match, as
+    /// none of its strings is a wanted key
+    pub fn may_match(&self, member_type: RelationMemberType) -> bool {
+        let index = type_index(&member_type);
+        !self.filter.has_positive
+            || self
+                .filter
+                .expressions
+                .iter()
+                .zip(&self.resolved)
+                .any(|(expression, (keys, _))| {
+                    !expression.negated && expression.types[index] && keys.contains(&true)
+                })
+    }
+
+    /// Evaluates tags given as `stringtable` indices of keys and values
+    pub fn matches<I: IntoIterator<Item = (u32, u32)>>(
+        &self,
+        member_type: RelationMemberType,
+        tags: I,
+    ) -> bool {
+        let index = type_index(&member_type);
+        let mut matched = !self.filter.has_positive;
+        for (key, value) in tags {
+            let (key, value) = (key as usize, value as usize);
+            for (expression, (keys, values)) in self.filter.expressions.iter().zip(&self.resolved) {
+                if expression.types[index]
+                    && keys.get(key) == Some(&true)
+                    && values
+                        .as_ref()
+                        .is_none_or(|values| values.get(value) == Some(&true))
+                {
+                    if expression.negated {
+                        return false;
+                    }
+                    matched = true;
+                }
+            }
+        }
+        matched
+    }
+
+    /// Evaluates a primitive of the block by string indices
+    ///
+    /// The resolved tags of nodes are mapped back to their indices by
+    /// address, as they point into the `stringtable` of the block.
+    pub fn matches_primitive(&self, primitive: &Primitive) -> bool {
+        let member_type = primitive_type(primitive);
+        match primitive {
+            Primitive::Node(node) => self.matches(
+                member_type,
+                node.tags
+                    .iter()
+                    .map(|(key, value)| (self.index_of(key), self.index_of(value))),
+            ),
+            Primitive::Way(way) => self.matches(member_type, way.tags().indices()),
+            Primitive::Relation(relation) => self.matches(member_type, relation.tags().indices()),
+        }
+    }
+
+    /// The index of a string of the `stringtable`, whose entries lie in
+    /// order within the block; other strings get an index matching nothing
+    fn index_of(&self, s: &str) -> u32 {
+        let address = s.as_ptr() as usize;
+        self.stringtable
+            .binary_search_by_key(&address, |entry| entry.as_ptr() as usize)
+            .map_or(u32::MAX, |index| index as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opl::opl_to_pbf;
+    use crate::{BlobReader, PrimitiveBlock};
+    use RelationMemberType::{Node, Relation, Way};
+
+    fn filter(expressions: &[&str]) -> TagsFilter {
+        TagsFilter::new(expressions.iter().copied()).unwrap()
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert!(Pattern::new("highway").matches("highway"));
+        assert!(!Pattern::new("highway").matches("highways"));
+        assert!(Pattern::new("disused:*").matches("disused:shop"));
+        assert!(!Pattern::new("disused:*").matches("disused"));
+        assert!(Pattern::new("*:en").matches("name:en"));
+        assert!(Pattern::new("*").matches(""));
+        assert!(Pattern::new("a*b*c").matches("abc"));
+        assert!(Pattern::new("a*b*c").matches("axxbyyc"));
+        assert!(!Pattern::new("ab*b").matches("ab"));
+    }
+
+    #[test]
+    fn test_matches() {
+        let highways = filter(&["highway=primary,secondary"]);
+        assert!(highways.matches(Way, [("highway", "primary")]));
+        assert!(highways.matches(Node, [("name", "x"), ("highway", "secondary")]));
+        assert!(!highways.matches(Way, [("highway", "tertiary")]));
+        assert!(!highways.matches(Way, []));
+
+        let buildings = filter(&["wr/building", "n/amenity=*"]);
+        assert!(buildings.matches(Way, [("building", "yes")]));
+        assert!(buildings.matches(Relation, [("building", "")]));
+        assert!(!buildings.matches(Node, [("building", "yes")]));
+        assert!(buildings.matches(Node, [("amenity", "bench")]));
+        assert!(!buildings.matches(Way, [("amenity", "bench")]));
+    }
+
+    #[test]
+    fn test_negation() {
+        let not_disused = filter(&["!disused:*"]);
+        assert!(not_disused.matches(Node, []));
+        assert!(not_disused.matches(Node, [("shop", "bakery")]));
+        assert!(!not_disused.matches(Node, [("disused:shop", "bakery")]));
+
+        let shops = filter(&["shop", "w/!building=no"]);
+        assert!(shops.matches(Way, [("shop", "bakery"), ("building", "yes")]));
+        assert!(!shops.matches(Way, [("shop", "bakery"), ("building", "no")]));
+        assert!(shops.matches(Node, [("shop", "bakery"), ("building", "no")]));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            TagsFilter::new(["highway", "n/=primary"]).unwrap_err(),
+            InvalidFilter("n/=primary".to_string())
+        );
+        assert!(TagsFilter::new(["!"]).is_err());
+        // Not a type prefix, so part of the key
+        assert!(filter(&["x/y"]).matches(Node, [("x/y", "")]));
+    }
+
+    #[test]
+    fn test_block() {
+        let stringtable = ["", "highway", "primary", "disused:shop", "yes", "name"];
+        let shops = filter(&["w/highway=primary", "!disused:*"]);
+        let block = shops.for_block(&stringtable);
+        assert!(block.may_match(Way));
+        assert!(!block.may_match(Node));
+        assert!(block.matches(Way, [(5, 4), (1, 2)]));
+        assert!(!block.matches(Way, [(1, 4)]));
+        assert!(!block.matches(Way, [(1, 2), (3, 4)]));
+        assert!(!block.matches(Way, [(1, 99)]));
+        assert!(!block.matches(Relation, [(1, 2)]));
+
+        let names = filter(&["name:*"]);
+        assert!(!names.for_block(&stringtable).may_match(Node));
+
+        let shops = filter(&["n/disused:*=yes", "!name"]);
+        let block = shops.for_block(&stringtable);
+        assert!(block.may_match(Node));
+        assert!(!block.may_match(Way));
+        assert!(block.matches(Node, [(3, 4)]));
+        assert!(!block.matches(Node, [(3, 4), (5, 2)]));
+        assert!(!block.matches(Node, [(3, 2)]));
+    }
+
+    #[test]
+    fn test_block_primitives() {
+        let opl = "n1 Tshop=yes
+n2 Tdisused:shop=yes
+n3 Tdisused:shop=yes,name=x
+n4 T
+w10 Tdisused:shop=yes Nn1,n2
+r20 Tdisused:amenity=yes,type=multipolygon Mw10@
+";
+        let out = opl_to_pbf(opl);
+
+        let filter = filter(&["nr/disused:*=yes", "!name"]);
+        let mut matched = vec![];
+        for blob in BlobReader::new(&out[..]) {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            let block = filter.for_block(&primitive_block.stringtable);
+            for primitive in primitive_block.primitives() {
+                let matches = block.matches_primitive(&primitive);
+                assert_eq!(matches, filter.matches_primitive(&primitive));
+                if matches {
+                    matched.push(Element::from(&primitive).sort_key());
+                }
+            }
+        }
+        assert_eq!(matched, vec![(0, 2), (2, 20)]);
+    }
+}
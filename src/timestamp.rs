@@ -0,0 +1,209 @@
+//! Timestamps of element metadata
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH};
+
+/// Milliseconds since the Unix epoch, as produced by all parsers after
+/// applying the `date_granularity` of their block
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const fn from_millis(millis: u64) -> Self {
+        Timestamp(millis)
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Timestamp(secs * 1000)
+    }
+
+    pub const fn millis(self) -> u64 {
+        self.0
+    }
+
+    pub const fn secs(self) -> u64 {
+        self.0 / 1000
+    }
+
+    /// Parses `YYYY-MM-DDTHH:MM:SSZ`, as used by OSM XML and OPL
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.strip_suffix('Z')?;
+        let (date, time) = s.split_once('T')?;
+        let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+        let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+        let mut time = time.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+        let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+        if !(0..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+        // Allowing for a leap second
+        if !(0..=23).contains(&hour) || !(0..=59).contains(&minute) || !(0..=60).contains(&second) {
+            return None;
+        }
+
+        // Days from civil, after Howard Hinnant
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+        u64::try_from(seconds).ok().map(Timestamp::from_secs)
+    }
+}
+
+/// Formats as `YYYY-MM-DDTHH:MM:SSZ`, dropping any milliseconds
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.secs() as i64;
+        let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+        // Civil from days, after Howard Hinnant
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time / 60 % 60,
+            time % 60
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTimestamp;
+
+impl fmt::Display for InvalidTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid timestamp")
+    }
+}
+
+impl Error for InvalidTimestamp {}
+
+impl FromStr for Timestamp {
+    type Err = InvalidTimestamp;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Timestamp::parse(s).ok_or(InvalidTimestamp)
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(timestamp: Timestamp) -> Self {
+        UNIX_EPOCH + Duration::from_millis(timestamp.0)
+    }
+}
+
+impl TryFrom<SystemTime> for Timestamp {
+    type Error = SystemTimeError;
+
+    fn try_from(time: SystemTime) -> Result<Self, Self::Error> {
+        Ok(Timestamp(
+            time.duration_since(UNIX_EPOCH)?.as_millis() as u64
+        ))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<Timestamp> for chrono::DateTime<chrono::Utc> {
+    type Error = InvalidTimestamp;
+
+    /// Fails past the year 262143, the end of chrono's range
+    fn try_from(timestamp: Timestamp) -> Result<Self, Self::Error> {
+        i64::try_from(timestamp.0)
+            .ok()
+            .and_then(chrono::DateTime::from_timestamp_millis)
+            .ok_or(InvalidTimestamp)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::DateTime<chrono::Utc>> for Timestamp {
+    type Error = InvalidTimestamp;
+
+    fn try_from(time: chrono::DateTime<chrono::Utc>) -> Result<Self, Self::Error> {
+        u64::try_from(time.timestamp_millis())
+            .map(Timestamp)
+            .map_err(|_| InvalidTimestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Timestamp::parse("1970-01-01T00:00:00Z"), Some(Timestamp(0)));
+        assert_eq!(
+            "2009-11-25T21:59:13Z".parse(),
+            Ok(Timestamp::from_millis(1_259_186_353_000))
+        );
+        assert_eq!(Timestamp::parse("2009-11-25 21:59:13"), None);
+        assert_eq!(Timestamp::parse("1969-12-31T23:59:59Z"), None);
+        assert_eq!(
+            Timestamp::parse("2016-12-31T23:59:60Z"),
+            Some(Timestamp::from_secs(1_483_228_800))
+        );
+        assert_eq!(Timestamp::parse("2009-11-25T24:00:00Z"), None);
+        assert_eq!(Timestamp::parse("2009-11-25T21:60:13Z"), None);
+        assert_eq!(Timestamp::parse("2009-11-25T21:59:61Z"), None);
+        assert_eq!(Timestamp::parse("2009-11-25T21:-1:13Z"), None);
+        assert_eq!(Timestamp::parse("2009-11-25T-1:59:13Z"), None);
+        assert_eq!(Timestamp::parse("2009-11-25T21:59:-1Z"), None);
+        assert_eq!(
+            Timestamp::parse("9223372036854775807-01-01T00:00:00Z"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Timestamp(0).to_string(), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            Timestamp::from_millis(1_259_186_353_999).to_string(),
+            "2009-11-25T21:59:13Z"
+        );
+        assert_eq!(
+            Timestamp::from_secs(951_782_400).to_string(),
+            "2000-02-29T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_system_time() {
+        let timestamp = Timestamp::from_millis(1_259_186_353_123);
+        let time = SystemTime::from(timestamp);
+        assert_eq!(Timestamp::try_from(time).unwrap(), timestamp);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono() {
+        let timestamp = Timestamp::from_millis(1_259_186_353_123);
+        let time = chrono::DateTime::<chrono::Utc>::try_from(timestamp).unwrap();
+        assert_eq!(time.to_rfc3339(), "2009-11-25T21:59:13.123+00:00");
+        assert_eq!(Timestamp::try_from(time), Ok(timestamp));
+        assert_eq!(
+            chrono::DateTime::<chrono::Utc>::try_from(Timestamp::from_millis(u64::MAX)),
+            Err(InvalidTimestamp)
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Batched decoding of packed varint columns into caller-provided buffers.
+//!
+//! Long runs of single-byte varints are common in delta-coded columns, so the
+//! decoders test eight bytes at once and take a fast path when none of them
+//! has its continuation bit set.
+
+const CONTINUATION_BITS: u64 = 0x8080_8080_8080_8080;
+
+#[inline]
+fn zigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+/// Decodes one varint, returning it together with the number of bytes read
+#[inline]
+fn decode_one(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (i, byte) in data.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Calls `f` with every varint in `data`, stopping at the first malformed one
+#[inline]
+fn for_each_varint<F: FnMut(u64)>(mut data: &[u8], mut f: F) -> Option<()> {
+    while !data.is_empty() {
+        if data.len() >= 8 {
+            let chunk = u64::from_le_bytes(data[..8].try_into().unwrap());
+            if chunk & CONTINUATION_BITS == 0 {
+                for byte in &data[..8] {
+                    f(*byte as u64);
+                }
+                data = &data[8..];
+                continue;
+            }
+        }
+        let (value, len) = decode_one(data)?;
+        f(value);
+        data = &data[len..];
+    }
+    Some(())
+}
+
+/// Appends the unsigned varints in `data` to `out`
+pub fn decode_packed_u32(data: &[u8], out: &mut Vec<u32>) -> Option<()> {
+    out.reserve(data.len() / 2);
+    for_each_varint(data, |value| out.push(value as u32))
+}
+
+/// Appends the packed `bool` values in `data` to `out`
+pub fn decode_packed_bool(data: &[u8], out: &mut Vec<bool>) -> Option<()> {
+    out.reserve(data.len());
+    for_each_varint(data, |value| out.push(value != 0))
+}
+
+/// Appends the zigzag-encoded, delta-coded `sint64` values in `data` to `out`
+pub fn decode_delta_i64(data: &[u8], out: &mut Vec<i64>) -> Option<()> {
+    out.reserve(data.len() / 2);
+    let mut last = 0i64;
+    for_each_varint(data, |value| {
+        last = last.wrapping_add(zigzag(value));
+        out.push(last);
+    })
+}
+
+/// Appends the zigzag-encoded, delta-coded `sint32` values in `data` to `out`
+pub fn decode_delta_i32(data: &[u8], out: &mut Vec<i32>) -> Option<()> {
+    out.reserve(data.len() / 2);
+    let mut last = 0i32;
+    for_each_varint(data, |value| {
+        last = last.wrapping_add(zigzag(value) as i32);
+        out.push(last);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta::DeltaEncodedIter;
+    use protobuf_iter::{PackedIter, PackedVarint, ParseValue};
+
+    const DATA: &[u8] = &[
+        0, 1, 6, 3, 2, 4, 8, 10, 12, 0x96, 0x01, 5, 0xff, 0xff, 0xff, 0xff, 0x0f, 1, 2, 3, 4, 5, 6,
+        7, 8, 9,
+    ];
+
+    #[test]
+    fn test_delta_i64_matches_iter() {
+        let mut out = vec![];
+        decode_delta_i64(DATA, &mut out).unwrap();
+        let expected: Vec<i64> =
+            DeltaEncodedIter::<PackedVarint, i64>::new(ParseValue::LengthDelimited(DATA)).collect();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_packed_u32_matches_iter() {
+        let mut out = vec![42];
+        decode_packed_u32(DATA, &mut out).unwrap();
+        let expected: Vec<u32> = PackedIter::<PackedVarint, u32>::new(DATA).collect();
+        assert_eq!(out[0], 42);
+        assert_eq!(&out[1..], &expected[..]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut out = vec![];
+        assert_eq!(decode_delta_i32(&[2, 0x96], &mut out), None);
+        assert_eq!(out, vec![1]);
+    }
+}
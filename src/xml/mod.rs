@@ -0,0 +1,216 @@
+//! OSM XML (`.osm`) support
+//!
+//! Only the small subset of XML that OSM files use is understood: elements
+//! with attributes, comments, processing instructions and ignored text.
+
+use std::io::{self, BufRead};
+
+pub mod osc;
+pub use self::osc::{ChangeAction, OscReader, OscWriter};
+pub mod reader;
+pub use self::reader::XmlReader;
+pub mod writer;
+pub use self::writer::{XmlWriter, write_xml};
+
+/// A start or end tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Tag {
+    Start {
+        name: String,
+        attrs: Vec<(String, String)>,
+        empty: bool,
+    },
+    End {
+        name: String,
+    },
+}
+
+pub(crate) fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(
+    error: E,
+) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Pulls tags out of an XML document, skipping everything else
+pub(crate) struct Tokenizer<R> {
+    read: R,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> Tokenizer<R> {
+    pub fn new(read: R) -> Self {
+        Tokenizer { read, buf: vec![] }
+    }
+
+    pub fn next_tag(&mut self) -> io::Result<Option<Tag>> {
+        loop {
+            self.buf.clear();
+            // Text between tags is of no interest
+            if self.read.read_until(b'<', &mut self.buf)? == 0 || self.buf.last() != Some(&b'<') {
+                return Ok(None);
+            }
+
+            self.buf.clear();
+            self.read_tag_content()?;
+            let content = std::str::from_utf8(&self.buf).map_err(invalid_data)?;
+            if content.starts_with('?') || content.starts_with('!') {
+                continue;
+            }
+            return parse_tag(content).map(Some);
+        }
+    }
+
+    /// Reads up to the closing `>`, which may also appear in quoted values
+    /// and comments
+    fn read_tag_content(&mut self) -> io::Result<()> {
+        let mut quote = None;
+        let mut scanned = 0;
+        loop {
+            if self.read.read_until(b'>', &mut self.buf)? == 0 || self.buf.last() != Some(&b'>') {
+                return Err(invalid_data("unexpected end of XML in tag"));
+            }
+            for &b in &self.buf[scanned..self.buf.len() - 1] {
+                match quote {
+                    Some(q) if b == q => quote = None,
+                    None if b == b'"' || b == b'\'' => quote = Some(b),
+                    _ => (),
+                }
+            }
+            scanned = self.buf.len();
+            let is_comment = self.buf.starts_with(b"!--");
+            if is_comment && !self.buf.ends_with(b"-->") {
+                continue;
+            }
+            if quote.is_none() || is_comment {
+                self.buf.pop();
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn parse_tag(content: &str) -> io::Result<Tag> {
+    if let Some(name) = content.strip_prefix('/') {
+        return Ok(Tag::End {
+            name: name.trim().to_string(),
+        });
+    }
+    let (content, empty) = match content.strip_suffix('/') {
+        Some(content) => (content, true),
+        None => (content, false),
+    };
+    let name_end = content
+        .find(|c: char| c.is_ascii_whitespace())
+        .unwrap_or(content.len());
+    let name = content[..name_end].to_string();
+    let mut rest = content[name_end..].trim_start();
+    let mut attrs = vec![];
+    while !rest.is_empty() {
+        let eq = rest
+            .find('=')
+            .ok_or_else(|| invalid_data(format!("attribute without value in <{}>", name)))?;
+        let key = rest[..eq].trim().to_string();
+        let value_start = rest[eq + 1..].trim_start();
+        let quote = value_start
+            .chars()
+            .next()
+            .filter(|&c| c == '"' || c == '\'')
+            .ok_or_else(|| invalid_data(format!("unquoted attribute in <{}>", name)))?;
+        let value_end = value_start[1..]
+            .find(quote)
+            .ok_or_else(|| invalid_data(format!("unterminated attribute in <{}>", name)))?;
+        attrs.push((key, unescape(&value_start[1..1 + value_end])?));
+        rest = value_start[value_end + 2..].trim_start();
+    }
+    Ok(Tag::Start { name, attrs, empty })
+}
+
+fn unescape(s: &str) -> io::Result<String> {
+    if !s.contains('&') {
+        return Ok(s.to_string());
+    }
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        let semicolon = rest[amp..]
+            .find(';')
+            .ok_or_else(|| invalid_data("unterminated entity"))?;
+        let entity = &rest[amp + 1..amp + semicolon];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok().and_then(char::from_u32)
+                } else {
+                    None
+                }
+            }
+        };
+        result.push(c.ok_or_else(|| invalid_data(format!("unknown entity &{};", entity)))?);
+        rest = &rest[amp + semicolon + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+pub(crate) fn escape(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '&' => output.push_str("&amp;"),
+            '"' => output.push_str("&quot;"),
+            '\n' => output.push_str("&#10;"),
+            '\r' => output.push_str("&#13;"),
+            '\t' => output.push_str("&#9;"),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenizer() {
+        let xml = "<?xml version=\"1.0\"?>\n<!-- a > comment -->\n<osm a='1 > 0'>\n  \
+                   <tag k=\"&lt;&amp;&#x41;&#66;\" v=\"\"/>\n</osm>\n";
+        let mut tokenizer = Tokenizer::new(xml.as_bytes());
+        assert_eq!(
+            tokenizer.next_tag().unwrap(),
+            Some(Tag::Start {
+                name: "osm".to_string(),
+                attrs: vec![("a".to_string(), "1 > 0".to_string())],
+                empty: false,
+            })
+        );
+        assert_eq!(
+            tokenizer.next_tag().unwrap(),
+            Some(Tag::Start {
+                name: "tag".to_string(),
+                attrs: vec![
+                    ("k".to_string(), "<&AB".to_string()),
+                    ("v".to_string(), "".to_string())
+                ],
+                empty: true,
+            })
+        );
+        assert_eq!(
+            tokenizer.next_tag().unwrap(),
+            Some(Tag::End {
+                name: "osm".to_string()
+            })
+        );
+        assert_eq!(tokenizer.next_tag().unwrap(), None);
+    }
+}
@@ -0,0 +1,228 @@
+//! osmChange (`.osc`) documents, as used for replication diffs
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::path::Path;
+
+use libdeflater::Decompressor;
+
+use super::reader::XmlReader;
+use super::writer::XmlWriter;
+use super::{escape, invalid_data};
+use crate::owned::Element;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeAction {
+    Create,
+    Modify,
+    Delete,
+}
+
+impl ChangeAction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "create" => Some(ChangeAction::Create),
+            "modify" => Some(ChangeAction::Modify),
+            "delete" => Some(ChangeAction::Delete),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChangeAction::Create => "create",
+            ChangeAction::Modify => "modify",
+            ChangeAction::Delete => "delete",
+        }
+    }
+}
+
+/// Reads the changes of an osmChange document, in document order
+pub struct OscReader<R> {
+    reader: XmlReader<R>,
+}
+
+impl OscReader<Box<dyn BufRead>> {
+    /// Opens a `.osc` file, or a gzip-compressed `.osc.gz` one
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let read: Box<dyn BufRead> = if path.extension().is_some_and(|ext| ext == "gz") {
+            let mut compressed = vec![];
+            BufReader::new(file).read_to_end(&mut compressed)?;
+            Box::new(Cursor::new(gunzip(&compressed)?))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+        Ok(OscReader::new(read))
+    }
+}
+
+impl<R: BufRead> OscReader<R> {
+    pub fn new(read: R) -> Self {
+        OscReader {
+            reader: XmlReader::new(read),
+        }
+    }
+
+    pub fn generator(&self) -> Option<&str> {
+        self.reader.generator()
+    }
+}
+
+impl<R: BufRead> Iterator for OscReader<R> {
+    type Item = io::Result<(ChangeAction, Element)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let element = match self.reader.next()? {
+            Ok(element) => element,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(
+            self.reader
+                .action
+                .map(|action| (action, element))
+                .ok_or_else(|| invalid_data("element outside of create, modify or delete")),
+        )
+    }
+}
+
+/// Decompresses a whole single-member gzip file, which the size in its
+/// trailer allows doing in one go
+fn gunzip(compressed: &[u8]) -> io::Result<Vec<u8>> {
+    let trailer = compressed
+        .len()
+        .checked_sub(4)
+        .map(|start| &compressed[start..])
+        .ok_or_else(|| invalid_data("truncated gzip file"))?;
+    let size = u32::from_le_bytes(trailer.try_into().unwrap()) as usize;
+    let mut decompressed = vec![0; size];
+    let len = Decompressor::new()
+        .gzip_decompress(compressed, &mut decompressed)
+        .map_err(|e| invalid_data(format!("{:?}", e)))?;
+    decompressed.truncate(len);
+    Ok(decompressed)
+}
+
+/// Writes an osmChange document, opening a new section whenever the action
+/// changes
+pub struct OscWriter<W: Write> {
+    writer: XmlWriter<W>,
+    action: Option<ChangeAction>,
+}
+
+impl<W: Write> OscWriter<W> {
+    pub fn new(mut out: W, generator: &str) -> io::Result<Self> {
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            out,
+            "<osmChange version=\"0.6\" generator=\"{}\">",
+            escape(generator)
+        )?;
+        Ok(OscWriter {
+            writer: XmlWriter::nested(out),
+            action: None,
+        })
+    }
+
+    pub fn write(&mut self, action: ChangeAction, element: &Element) -> io::Result<()> {
+        if self.action != Some(action) {
+            self.close_section()?;
+            writeln!(self.writer.get_mut(), "  <{}>", action.name())?;
+            self.action = Some(action);
+        }
+        self.writer.write_element(element)
+    }
+
+    /// Closes the `<osmChange>` element, and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.close_section()?;
+        let mut out = self.writer.into_inner();
+        writeln!(out, "</osmChange>")?;
+        out.flush()?;
+        Ok(out)
+    }
+
+    fn close_section(&mut self) -> io::Result<()> {
+        if let Some(action) = self.action.take() {
+            writeln!(self.writer.get_mut(), "  </{}>", action.name())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OSC: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osmChange version="0.6" generator="test">
+  <create>
+    <node id="1" version="1" timestamp="2020-01-01T00:00:00Z" lat="1.0000000" lon="2.0000000"/>
+    <node id="2" version="1" timestamp="2020-01-01T00:00:00Z" lat="1.0000000" lon="2.0000000">
+      <tag k="amenity" v="bench"/>
+    </node>
+  </create>
+  <modify>
+    <way id="3" version="2">
+      <nd ref="1"/>
+      <nd ref="2"/>
+    </way>
+  </modify>
+  <delete>
+    <relation id="4" version="3" visible="false"/>
+  </delete>
+</osmChange>
+"#;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut reader = OscReader::new(OSC.as_bytes());
+        let changes = reader.by_ref().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(reader.generator(), Some("test"));
+        let actions: Vec<_> = changes
+            .iter()
+            .map(|(action, element)| (*action, element.id()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (ChangeAction::Create, 1),
+                (ChangeAction::Create, 2),
+                (ChangeAction::Modify, 3),
+                (ChangeAction::Delete, 4)
+            ]
+        );
+        assert_eq!(changes[3].1.info().unwrap().visible, Some(false));
+
+        let mut writer = OscWriter::new(vec![], "test").unwrap();
+        for (action, element) in &changes {
+            writer.write(*action, element).unwrap();
+        }
+        let out = writer.finish().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), OSC);
+    }
+
+    #[test]
+    fn test_open_gz() {
+        let mut compressor = libdeflater::Compressor::new(libdeflater::CompressionLvl::default());
+        let mut compressed = vec![0; compressor.gzip_compress_bound(OSC.len())];
+        let len = compressor
+            .gzip_compress(OSC.as_bytes(), &mut compressed)
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("osm_pbf_iter_{}.osc.gz", std::process::id()));
+        std::fs::write(&path, &compressed[..len]).unwrap();
+        let changes = OscReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(changes.len(), 4);
+    }
+
+    #[test]
+    fn test_outside_section() {
+        let mut reader = OscReader::new(&b"<osmChange><node id=\"1\"/></osmChange>"[..]);
+        assert!(reader.next().unwrap().is_err());
+    }
+}
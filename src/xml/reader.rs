@@ -0,0 +1,355 @@
+use std::io::{self, BufRead};
+
+use super::osc::ChangeAction;
+use super::{Tag, Tokenizer, invalid_data};
+use crate::HeaderBBox;
+use crate::RelationMemberType;
+use crate::Timestamp;
+use crate::owned::{Element, Info, Member, Node, Relation, Way};
+
+/// Reads the elements of an OSM XML document, in document order
+///
+/// The sections of osmChange documents are skipped over; use `OscReader`
+/// to learn about them.
+pub struct XmlReader<R> {
+    tokenizer: Tokenizer<R>,
+    bounds: Option<HeaderBBox>,
+    generator: Option<String>,
+    pub(crate) action: Option<ChangeAction>,
+    done: bool,
+}
+
+impl<R: BufRead> XmlReader<R> {
+    pub fn new(read: R) -> Self {
+        XmlReader {
+            tokenizer: Tokenizer::new(read),
+            bounds: None,
+            generator: None,
+            action: None,
+            done: false,
+        }
+    }
+
+    /// The `<bounds>`, once read past them
+    pub fn bounds(&self) -> Option<&HeaderBBox> {
+        self.bounds.as_ref()
+    }
+
+    /// The `generator` attribute of `<osm>` or `<osmChange>`, once read past it
+    pub fn generator(&self) -> Option<&str> {
+        self.generator.as_deref()
+    }
+
+    pub(crate) fn read_element(&mut self) -> io::Result<Option<Element>> {
+        let mut current: Option<Element> = None;
+        while let Some(tag) = self.tokenizer.next_tag()? {
+            match tag {
+                Tag::Start { name, attrs, empty } => match (name.as_str(), &mut current) {
+                    ("osm" | "osmChange", None) => {
+                        self.generator = attr(&attrs, "generator").map(str::to_string)
+                    }
+                    ("create" | "modify" | "delete", None) if !empty => {
+                        self.action = ChangeAction::from_name(&name);
+                    }
+                    ("bounds", None) => self.bounds = Some(parse_bounds(&attrs)?),
+                    ("node" | "way" | "relation", None) => {
+                        let element = parse_element(&name, &attrs)?;
+                        if empty {
+                            return Ok(Some(element));
+                        }
+                        current = Some(element);
+                    }
+                    ("tag", Some(element)) => {
+                        let tag = (required(&attrs, "k")?, required(&attrs, "v")?);
+                        match element {
+                            Element::Node(node) => node.tags.push(tag),
+                            Element::Way(way) => way.tags.push(tag),
+                            Element::Relation(relation) => relation.tags.push(tag),
+                        }
+                    }
+                    ("nd", Some(Element::Way(way))) => {
+                        way.refs.push(parse_id(&attrs, "ref")?);
+                    }
+                    ("member", Some(Element::Relation(relation))) => {
+                        let member_type = match required(&attrs, "type")?.as_str() {
+                            "node" => RelationMemberType::Node,
+                            "way" => RelationMemberType::Way,
+                            "relation" => RelationMemberType::Relation,
+                            other => {
+                                return Err(invalid_data(format!("unknown member type {}", other)));
+                            }
+                        };
+                        relation.members.push(Member {
+                            member_type,
+                            id: parse_id(&attrs, "ref")?,
+                            role: attr(&attrs, "role").unwrap_or_default().to_string(),
+                        });
+                    }
+                    // Such as the <note> and <meta> of Overpass, or <changeset>
+                    (_, None) => {
+                        if !empty {
+                            self.skip_element()?;
+                        }
+                    }
+                    (name, _) => return Err(invalid_data(format!("unexpected <{}>", name))),
+                },
+                Tag::End { name } => match (name.as_str(), &current) {
+                    ("tag" | "nd" | "member", Some(_)) => (),
+                    ("node", Some(Element::Node(_)))
+                    | ("way", Some(Element::Way(_)))
+                    | ("relation", Some(Element::Relation(_))) => return Ok(current),
+                    ("create" | "modify" | "delete", None) => self.action = None,
+                    ("osm" | "osmChange", None) => (),
+                    (name, _) => return Err(invalid_data(format!("unexpected </{}>", name))),
+                },
+            }
+        }
+        match current {
+            Some(_) => Err(invalid_data("unexpected end of XML in element")),
+            None => Ok(None),
+        }
+    }
+
+    /// Skips the contents of an element, up to its end tag
+    fn skip_element(&mut self) -> io::Result<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.tokenizer.next_tag()? {
+                Some(Tag::Start { empty: false, .. }) => depth += 1,
+                Some(Tag::Start { .. }) => (),
+                Some(Tag::End { .. }) => depth -= 1,
+                None => return Err(invalid_data("unexpected end of XML in element")),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for XmlReader<R> {
+    type Item = io::Result<Element>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_element().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+fn required(attrs: &[(String, String)], key: &str) -> io::Result<String> {
+    attr(attrs, key)
+        .map(str::to_string)
+        .ok_or_else(|| invalid_data(format!("missing attribute {}", key)))
+}
+
+fn parse_attr<T: std::str::FromStr>(attrs: &[(String, String)], key: &str) -> io::Result<T> {
+    required(attrs, key)?
+        .parse()
+        .map_err(|_| invalid_data(format!("invalid attribute {}", key)))
+}
+
+/// IDs may be negative, as for new elements in editors, and are stored cast
+/// to `u64` like in PBF files
+fn parse_id(attrs: &[(String, String)], key: &str) -> io::Result<u64> {
+    Ok(parse_attr::<i64>(attrs, key)? as u64)
+}
+
+fn parse_optional<T: std::str::FromStr>(
+    attrs: &[(String, String)],
+    key: &str,
+) -> io::Result<Option<T>> {
+    attr(attrs, key)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| invalid_data(format!("invalid attribute {}", key)))
+        })
+        .transpose()
+}
+
+fn parse_bounds(attrs: &[(String, String)]) -> io::Result<HeaderBBox> {
+    Ok(HeaderBBox {
+        left: parse_attr(attrs, "minlon")?,
+        right: parse_attr(attrs, "maxlon")?,
+        top: parse_attr(attrs, "maxlat")?,
+        bottom: parse_attr(attrs, "minlat")?,
+    })
+}
+
+/// `None` if the element has none of the metadata attributes
+fn parse_info(attrs: &[(String, String)]) -> io::Result<Option<Info>> {
+    let timestamp = attr(attrs, "timestamp")
+        .map(|value| Timestamp::parse(value).ok_or_else(|| invalid_data("invalid timestamp")))
+        .transpose()?;
+    let info = Info {
+        version: parse_optional(attrs, "version")?,
+        timestamp,
+        changeset: parse_optional(attrs, "changeset")?,
+        uid: parse_optional(attrs, "uid")?,
+        user: attr(attrs, "user").map(str::to_string),
+        visible: parse_optional(attrs, "visible")?,
+    };
+    Ok(if info == Info::default() {
+        None
+    } else {
+        Some(info)
+    })
+}
+
+fn parse_element(name: &str, attrs: &[(String, String)]) -> io::Result<Element> {
+    let id = parse_id(attrs, "id")?;
+    let info = parse_info(attrs)?;
+    Ok(match name {
+        "node" => Element::Node(Node {
+            id,
+            // Deleted nodes in history files have no location
+            lat: parse_optional(attrs, "lat")?.unwrap_or_default(),
+            lon: parse_optional(attrs, "lon")?.unwrap_or_default(),
+            info,
+            tags: vec![],
+        }),
+        "way" => Element::Way(Way {
+            id,
+            info,
+            tags: vec![],
+            refs: vec![],
+        }),
+        _ => Element::Relation(Relation {
+            id,
+            info,
+            tags: vec![],
+            members: vec![],
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6" generator="test">
+  <bounds minlat="1.5" minlon="2.5" maxlat="3.5" maxlon="4.5"/>
+  <node id="1" version="2" timestamp="1970-01-01T00:00:10Z" uid="3" user="a &amp; b" visible="true" lat="1.6" lon="2.6"/>
+  <way id="2">
+    <nd ref="1"/>
+    <tag k="highway" v="path"/>
+  </way>
+  <relation id="3">
+    <member type="way" ref="2" role="outer"/>
+  </relation>
+</osm>
+"#;
+        let mut reader = XmlReader::new(xml.as_bytes());
+        let elements = reader
+            .by_ref()
+            .collect::<io::Result<Vec<Element>>>()
+            .unwrap();
+        assert_eq!(reader.generator(), Some("test"));
+        assert_eq!(reader.bounds().unwrap().right, 4.5);
+        assert_eq!(
+            elements,
+            vec![
+                Element::Node(Node {
+                    id: 1,
+                    lat: 1.6,
+                    lon: 2.6,
+                    info: Some(Info {
+                        version: Some(2),
+                        timestamp: Some(Timestamp::from_secs(10)),
+                        changeset: None,
+                        uid: Some(3),
+                        user: Some("a & b".to_string()),
+                        visible: Some(true),
+                    }),
+                    tags: vec![],
+                }),
+                Element::Way(Way {
+                    id: 2,
+                    info: None,
+                    tags: vec![("highway".to_string(), "path".to_string())],
+                    refs: vec![1],
+                }),
+                Element::Relation(Relation {
+                    id: 3,
+                    info: None,
+                    tags: vec![],
+                    members: vec![Member {
+                        member_type: RelationMemberType::Way,
+                        id: 2,
+                        role: "outer".to_string(),
+                    }],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_end_tags() {
+        let xml = r#"<osm>
+  <node id="-1" lat="1" lon="2"><tag k="a" v="b"></tag></node>
+  <way id="-2"><nd ref="-1"></nd><nd ref="2"/></way>
+  <relation id="3"><member type="way" ref="-2" role=""></member></relation>
+</osm>"#;
+        let elements = XmlReader::new(xml.as_bytes())
+            .collect::<io::Result<Vec<Element>>>()
+            .unwrap();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements[0].id(), -1i64 as u64);
+        assert_eq!(elements[0].tags().len(), 1);
+        let Element::Way(way) = &elements[1] else {
+            panic!("not a way");
+        };
+        assert_eq!(way.id, -2i64 as u64);
+        assert_eq!(way.refs, vec![-1i64 as u64, 2]);
+        let Element::Relation(relation) = &elements[2] else {
+            panic!("not a relation");
+        };
+        assert_eq!(relation.members[0].id, -2i64 as u64);
+
+        let mut reader = XmlReader::new(&b"<osm><way id=\"1\"></node></osm>"[..]);
+        assert!(reader.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_overpass() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6" generator="Overpass API 0.7.62">
+<note>The data included in this document is from www.openstreetmap.org.</note>
+<meta osm_base="2024-01-01T00:00:00Z"/>
+<changeset id="5"><tag k="comment" v="x"/><discussion><comment/></discussion></changeset>
+  <node id="1" lat="1" lon="2"/>
+</osm>"#;
+        let elements = XmlReader::new(xml.as_bytes())
+            .collect::<io::Result<Vec<Element>>>()
+            .unwrap();
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].id(), 1);
+
+        // Unknown elements are still errors within elements
+        let mut reader = XmlReader::new(&b"<osm><node id=\"1\"><note/></node></osm>"[..]);
+        assert!(reader.next().unwrap().is_err());
+        let mut reader = XmlReader::new(&b"<osm><note>"[..]);
+        assert!(reader.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut reader = XmlReader::new(&b"<osm><way id=\"x\"/></osm>"[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,294 @@
+use std::io::{self, Read, Write};
+
+use super::escape;
+use crate::owned::{self, Element};
+use crate::{
+    BlobReader, HeaderBBox, Node, Primitive, PrimitiveBlock, Relation, RelationMemberType,
+    Timestamp, Way, info,
+};
+
+/// Metadata attributes, borrowed from either kind of `Info`
+struct InfoAttrs<'a> {
+    version: Option<u32>,
+    timestamp: Option<Timestamp>,
+    changeset: Option<u64>,
+    uid: Option<u32>,
+    user: Option<&'a str>,
+    visible: Option<bool>,
+}
+
+impl<'a> From<&'a info::Info<'a>> for InfoAttrs<'a> {
+    fn from(info: &'a info::Info<'a>) -> Self {
+        InfoAttrs {
+            version: info.version,
+            timestamp: info.timestamp,
+            changeset: info.changeset,
+            uid: info.uid,
+            user: info.user,
+            visible: info.visible,
+        }
+    }
+}
+
+impl<'a> From<&'a owned::Info> for InfoAttrs<'a> {
+    fn from(info: &'a owned::Info) -> Self {
+        InfoAttrs {
+            version: info.version,
+            timestamp: info.timestamp,
+            changeset: info.changeset,
+            uid: info.uid,
+            user: info.user.as_deref(),
+            visible: info.visible,
+        }
+    }
+}
+
+fn member_type_name(member_type: &RelationMemberType) -> &'static str {
+    match member_type {
+        RelationMemberType::Node => "node",
+        RelationMemberType::Way => "way",
+        RelationMemberType::Relation => "relation",
+    }
+}
+
+/// Writes an `<osm>` document in the layout of osmium and osmosis
+pub struct XmlWriter<W: Write> {
+    out: W,
+    indent: &'static str,
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub fn new(mut out: W, generator: &str) -> io::Result<Self> {
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            out,
+            "<osm version=\"0.6\" generator=\"{}\">",
+            escape(generator)
+        )?;
+        Ok(XmlWriter { out, indent: "  " })
+    }
+
+    /// For writing elements nested one level deeper, as in osmChange
+    pub(crate) fn nested(out: W) -> Self {
+        XmlWriter {
+            out,
+            indent: "    ",
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.out
+    }
+
+    pub fn write_bounds(&mut self, bbox: &HeaderBBox) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{}<bounds minlat=\"{:.7}\" minlon=\"{:.7}\" maxlat=\"{:.7}\" maxlon=\"{:.7}\"/>",
+            self.indent, bbox.bottom, bbox.left, bbox.top, bbox.right
+        )
+    }
+
+    pub fn write_primitive(&mut self, primitive: &Primitive) -> io::Result<()> {
+        match primitive {
+            Primitive::Node(node) => self.write_node(node),
+            Primitive::Way(way) => self.write_way(way),
+            Primitive::Relation(relation) => self.write_relation(relation),
+        }
+    }
+
+    pub fn write_node(&mut self, node: &Node) -> io::Result<()> {
+        self.start("node", node.id, node.info.as_ref().map(InfoAttrs::from))?;
+        // Deleted nodes have no location
+        if node.info.as_ref().and_then(|info| info.visible) != Some(false) {
+            write!(self.out, " lat=\"{:.7}\" lon=\"{:.7}\"", node.lat, node.lon)?;
+        }
+        self.children("node", &[], &[], node.tags.iter().copied())
+    }
+
+    pub fn write_way(&mut self, way: &Way) -> io::Result<()> {
+        self.start("way", way.id, way.info.as_ref().map(InfoAttrs::from))?;
+        let refs: Vec<u64> = way.refs().map(|id| id as u64).collect();
+        self.children("way", &refs, &[], way.tags())
+    }
+
+    pub fn write_relation(&mut self, relation: &Relation) -> io::Result<()> {
+        self.start(
+            "relation",
+            relation.id,
+            relation.info.as_ref().map(InfoAttrs::from),
+        )?;
+        let members: Vec<_> = relation.members().collect();
+        self.children("relation", &[], &members, relation.tags())
+    }
+
+    pub fn write_element(&mut self, element: &Element) -> io::Result<()> {
+        let tags = element.tags().iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        match element {
+            Element::Node(node) => {
+                self.start("node", node.id, node.info.as_ref().map(InfoAttrs::from))?;
+                // Deleted nodes have no location
+                if node.info.as_ref().and_then(|info| info.visible) != Some(false) {
+                    write!(self.out, " lat=\"{:.7}\" lon=\"{:.7}\"", node.lat, node.lon)?;
+                }
+                self.children("node", &[], &[], tags)
+            }
+            Element::Way(way) => {
+                self.start("way", way.id, way.info.as_ref().map(InfoAttrs::from))?;
+                self.children("way", &way.refs, &[], tags)
+            }
+            Element::Relation(relation) => {
+                self.start(
+                    "relation",
+                    relation.id,
+                    relation.info.as_ref().map(InfoAttrs::from),
+                )?;
+                let members: Vec<_> = relation
+                    .members
+                    .iter()
+                    .map(|member| (member.role.as_str(), member.id, member.member_type.clone()))
+                    .collect();
+                self.children("relation", &[], &members, tags)
+            }
+        }
+    }
+
+    /// Closes the `<osm>` element, and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        writeln!(self.out, "</osm>")?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    /// Writes the attributes in the order of osmium and osmosis, so that
+    /// their output can be compared
+    fn start(&mut self, element: &str, id: u64, info: Option<InfoAttrs>) -> io::Result<()> {
+        // Negative IDs, as used by editors, are stored cast to `u64`
+        write!(self.out, "{}<{} id=\"{}\"", self.indent, element, id as i64)?;
+        let Some(info) = info else {
+            return Ok(());
+        };
+        if let Some(version) = info.version {
+            write!(self.out, " version=\"{}\"", version)?;
+        }
+        if let Some(timestamp) = info.timestamp {
+            write!(self.out, " timestamp=\"{}\"", timestamp)?;
+        }
+        if let Some(uid) = info.uid {
+            write!(self.out, " uid=\"{}\"", uid)?;
+        }
+        if let Some(user) = info.user {
+            write!(self.out, " user=\"{}\"", escape(user))?;
+        }
+        if let Some(changeset) = info.changeset {
+            write!(self.out, " changeset=\"{}\"", changeset)?;
+        }
+        if let Some(visible) = info.visible {
+            write!(self.out, " visible=\"{}\"", visible)?;
+        }
+        Ok(())
+    }
+
+    fn children<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(
+        &mut self,
+        element: &str,
+        refs: &[u64],
+        members: &[(&str, u64, RelationMemberType)],
+        tags: I,
+    ) -> io::Result<()> {
+        let mut tags = tags.into_iter().peekable();
+        if refs.is_empty() && members.is_empty() && tags.peek().is_none() {
+            return writeln!(self.out, "/>");
+        }
+        writeln!(self.out, ">")?;
+        for id in refs {
+            writeln!(self.out, "{}  <nd ref=\"{}\"/>", self.indent, *id as i64)?;
+        }
+        for (role, id, member_type) in members {
+            writeln!(
+                self.out,
+                "{}  <member type=\"{}\" ref=\"{}\" role=\"{}\"/>",
+                self.indent,
+                member_type_name(member_type),
+                *id as i64,
+                escape(role)
+            )?;
+        }
+        for (k, v) in tags {
+            writeln!(
+                self.out,
+                "{}  <tag k=\"{}\" v=\"{}\"/>",
+                self.indent,
+                escape(k),
+                escape(v)
+            )?;
+        }
+        writeln!(self.out, "{}</{}>", self.indent, element)
+    }
+}
+
+/// Converts a whole PBF file, with `<bounds>` if its header has them
+pub fn write_xml<R: Read, W: Write>(
+    reader: &mut BlobReader<R>,
+    out: W,
+    generator: &str,
+) -> io::Result<W> {
+    let mut writer = XmlWriter::new(out, generator)?;
+    if let Some(bbox) = reader.header().and_then(|header| header.bbox.as_ref()) {
+        writer.write_bounds(bbox)?;
+    }
+    for blob in reader {
+        let data = blob.into_data();
+        let primitive_block = PrimitiveBlock::parse(&data);
+        for primitive in primitive_block.primitives() {
+            writer.write_primitive(&primitive)?;
+        }
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opl::opl_to_pbf;
+    use crate::xml::XmlReader;
+
+    #[test]
+    fn test_roundtrip() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6" generator="test">
+  <bounds minlat="1.5000000" minlon="2.5000000" maxlat="3.5000000" maxlon="4.5000000"/>
+  <node id="1" version="2" timestamp="2009-11-25T21:59:13Z" uid="3" user="a &amp; &quot;b&quot;" changeset="4" visible="true" lat="1.6000000" lon="2.6000000"/>
+  <way id="2">
+    <nd ref="1"/>
+    <nd ref="-5"/>
+    <tag k="note" v="&lt;1&gt;&#10;"/>
+  </way>
+  <relation id="3" version="1">
+    <member type="way" ref="2" role="outer"/>
+  </relation>
+</osm>
+"#;
+        let mut reader = XmlReader::new(xml.as_bytes());
+        let elements = reader.by_ref().collect::<io::Result<Vec<_>>>().unwrap();
+        let mut writer = XmlWriter::new(vec![], "test").unwrap();
+        writer.write_bounds(reader.bounds().unwrap()).unwrap();
+        for element in &elements {
+            writer.write_element(element).unwrap();
+        }
+        let out = writer.finish().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), xml);
+    }
+
+    #[test]
+    fn test_deleted_node() {
+        let out = opl_to_pbf("n1 v2 dD x y\nn2 v1 dV x1 y2\n");
+        let xml = write_xml(&mut BlobReader::new(&out[..]), vec![], "test").unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains("changeset=\"0\" visible=\"false\"/>"));
+        assert!(xml.contains("visible=\"true\" lat=\"2.0000000\" lon=\"1.0000000\"/>"));
+    }
+}
@@ -1,7 +1,26 @@
 #[cfg(test)]
 mod tests {
-    use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
-    use osm_pbf_iter::{BlobReader, Primitive, PrimitiveBlock, RelationMemberType, info::Info};
+    use osm_pbf_iter::area::{AreaAssembler, AreaError, AreaSource, BrokenArea};
+    use osm_pbf_iter::check_refs::CheckRefs;
+    use osm_pbf_iter::extract::{Region, Strategy, extract};
+    use osm_pbf_iter::geojson::{GeoJsonOptions, GeoJsonWriter, read_multipolygon};
+    use osm_pbf_iter::geometry::ring_contains;
+    use osm_pbf_iter::getid::{GetIdOptions, Ids, get_ids};
+    use osm_pbf_iter::history::{
+        HISTORICAL_INFORMATION, Histories, elements, is_history_file, time_filter,
+    };
+    use osm_pbf_iter::location::{SortedLocationStore, StoreLocations};
+    use osm_pbf_iter::o5m::{O5mReader, write_o5m};
+    use osm_pbf_iter::opl::{OplReader, write_opl};
+    use osm_pbf_iter::owned::Element;
+    use osm_pbf_iter::poly::read_poly;
+    use osm_pbf_iter::sort_order::{CheckSorted, Violation, check_sorted, is_sorted_file};
+    use osm_pbf_iter::xml::{XmlReader, write_xml};
+    use osm_pbf_iter::{
+        BlobReader, BlockSummary, DenseNodesColumns, Handler, HeaderBBox, HeaderBlock, Location,
+        Node, NodeLocationStore, PbfWriter, Primitive, PrimitiveBlock, Relation, RelationCollector,
+        TagsFilter, Timestamp, Way, apply, apply_parallel,
+    };
     use std::fs::{File, read_to_string};
     use std::io::{BufReader, Read};
     use std::path::PathBuf;
@@ -48,146 +67,736 @@ mod tests {
         );
     }
 
-    fn new_blob_reader(filename: &str) -> BlobReader<BufReader<File>> {
-        let path = test_data_path(filename);
-        let file = File::open(&path).expect(&format!("cannot open {:?}", path));
-        BlobReader::new(BufReader::new(file))
+    #[test]
+    fn test_summary() {
+        let summaries: Vec<BlockSummary> = new_blob_reader("multipolygon.osm.pbf")
+            .map(|blob| PrimitiveBlock::parse(&blob.into_data()).summary())
+            .collect();
+        assert_eq!(summaries.len(), 3);
+        assert_eq!(summaries[0].nodes.count, 4);
+        assert_eq!(summaries[0].nodes.ids, Some(5816..=6362));
+        assert!(summaries[0].ways.is_empty());
+        assert_eq!(summaries[1].ways.count, 2);
+        assert_eq!(summaries[1].ways.ids, Some(9772..=9774));
+        assert_eq!(summaries[2].relations.count, 1);
+        assert_eq!(summaries[2].relations.ids, Some(9775..=9775));
+
+        let summaries: Vec<BlockSummary> = new_blob_reader("two_primitive_groups.osm.pbf")
+            .map(|blob| PrimitiveBlock::parse(&blob.into_data()).summary())
+            .collect();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].nodes.count, 3);
+        assert_eq!(summaries[0].nodes.ids, Some(11..=22));
     }
 
-    fn test_data_path(filename: &str) -> PathBuf {
-        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        path.push("tests");
-        path.push("data");
-        path.push(filename);
-        path
+    #[test]
+    fn test_dense_nodes_columns() {
+        for filename in ["64bit_ids.osm.pbf", "tag_lengths.osm.pbf"] {
+            let mut columns = DenseNodesColumns::default();
+            for blob in new_blob_reader(filename) {
+                let data = blob.into_data();
+                let primitive_block = PrimitiveBlock::parse(&data);
+                let mut nodes = primitive_block.primitives().filter_map(|p| match p {
+                    Primitive::Node(node) => Some(node),
+                    _ => None,
+                });
+                for dense_nodes in primitive_block.dense_nodes() {
+                    columns.decode(dense_nodes).unwrap();
+                    for i in 0..columns.len() {
+                        let node = nodes.next().unwrap();
+                        assert_eq!(columns.ids[i] as u64, node.id);
+                        assert_eq!(primitive_block.convert_lat(columns.lats[i]), node.lat);
+                        assert_eq!(primitive_block.convert_lon(columns.lons[i]), node.lon);
+                        let tags: Vec<_> = columns.tags(&primitive_block.stringtable, i).collect();
+                        assert_eq!(tags, node.tags);
+                        let infos = columns.infos.as_ref().unwrap();
+                        let info = node.info.unwrap();
+                        assert_eq!(Some(infos.versions[i]), info.version);
+                        assert_eq!(Some(infos.changesets[i] as u64), info.changeset);
+                    }
+                }
+                assert!(nodes.next().is_none());
+            }
+        }
     }
 
-    fn dump<R: Read>(reader: BlobReader<R>) -> String {
-        let mut buf = String::new();
-        buf.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
-        buf.push_str("<osm generator=\"integration_test\" upload=\"false\">\n");
-        for blob in reader {
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_record_batches() {
+        use osm_pbf_iter::arrow::arrow_array::{Array, Int64Array, ListArray, MapArray};
+
+        let mut batches = vec![];
+        for blob in new_blob_reader("multipolygon.osm.pbf") {
+            let data = blob.into_data();
+            batches.push(PrimitiveBlock::parse(&data).to_record_batches().unwrap());
+        }
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0].nodes.num_rows(), 4);
+        assert_eq!(batches[0].ways.num_rows(), 0);
+        assert_eq!(batches[1].ways.num_rows(), 2);
+        assert_eq!(batches[2].relations.num_rows(), 1);
+
+        let ways = &batches[1].ways;
+        let ids = ways
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(ids.values(), &[9772, 9774]);
+        let refs = ways.column(1).as_any().downcast_ref::<ListArray>().unwrap();
+        assert_eq!(refs.value(0).len(), 5);
+        assert_eq!(refs.value(1).len(), 9);
+        let tags = ways.column(2).as_any().downcast_ref::<MapArray>().unwrap();
+        assert_eq!(tags.value(0).len(), 1);
+
+        let relations = &batches[2].relations;
+        let members = relations
+            .column(1)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        assert_eq!(members.value(0).len(), 2);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_counts() {
+        use osm_pbf_iter::parquet::parquet::file::reader::{FileReader, SerializedFileReader};
+        use osm_pbf_iter::parquet::{
+            NODES_FILE, ParquetOptions, RELATIONS_FILE, WAYS_FILE, write_parquet,
+        };
+
+        for filename in ["64bit_ids.osm.pbf", "multipolygon.osm.pbf"] {
+            let output = std::process::Command::new(env!("CARGO_BIN_EXE_count"))
+                .arg(test_data_path(filename))
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            let expected: Vec<i64> = stdout
+                .lines()
+                .last()
+                .unwrap()
+                .split(" - ")
+                .nth(1)
+                .unwrap()
+                .split(", ")
+                .map(|count| count.split(' ').next().unwrap().parse().unwrap())
+                .collect();
+
+            let dir = std::env::temp_dir().join(format!(
+                "osm_pbf_iter-parquet-{}-{}",
+                std::process::id(),
+                filename
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            let options = ParquetOptions {
+                row_group_size: 100,
+                geoparquet: true,
+                ..Default::default()
+            };
+            let counts = write_parquet(new_blob_reader(filename), &dir, options).unwrap();
+            assert_eq!(
+                vec![
+                    counts.nodes as i64,
+                    counts.ways as i64,
+                    counts.relations as i64
+                ],
+                expected
+            );
+
+            let mut rows = vec![];
+            for name in [NODES_FILE, WAYS_FILE, RELATIONS_FILE] {
+                let reader =
+                    SerializedFileReader::new(File::open(dir.join(name)).unwrap()).unwrap();
+                let metadata = reader.metadata();
+                assert!(metadata.row_groups().iter().all(|rg| rg.num_rows() <= 100));
+                if name == NODES_FILE {
+                    let geo = metadata.file_metadata().key_value_metadata().unwrap();
+                    assert!(geo.iter().any(|kv| kv.key == "geo"));
+                }
+                rows.push(metadata.file_metadata().num_rows());
+            }
+            assert_eq!(rows, expected);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[derive(Default)]
+    struct CountingHandler {
+        headers: usize,
+        blocks: (usize, usize),
+        ids: Vec<u64>,
+    }
+
+    impl Handler for CountingHandler {
+        fn header(&mut self, _header: &HeaderBlock) {
+            self.headers += 1;
+        }
+
+        fn block_start(&mut self, _primitive_block: &PrimitiveBlock) {
+            self.blocks.0 += 1;
+        }
+
+        fn node(&mut self, node: &Node) {
+            self.ids.push(node.id);
+        }
+
+        fn way(&mut self, way: &Way) {
+            self.ids.push(way.id);
+        }
+
+        fn relation(&mut self, relation: &Relation) {
+            self.ids.push(relation.id);
+        }
+
+        fn block_end(&mut self, _primitive_block: &PrimitiveBlock) {
+            self.blocks.1 += 1;
+        }
+    }
+
+    #[test]
+    fn test_handler() {
+        let mut handler = CountingHandler::default();
+        apply(&mut new_blob_reader("multipolygon.osm.pbf"), &mut handler);
+        assert_eq!(handler.headers, 1);
+        assert_eq!(handler.blocks, (3, 3));
+        assert_eq!(handler.ids, vec![5816, 5822, 6362, 6356, 9772, 9774, 9775]);
+
+        let handlers = apply_parallel(
+            &mut new_blob_reader("multipolygon.osm.pbf"),
+            2,
+            CountingHandler::default,
+        );
+        assert_eq!(handlers.len(), 2);
+        assert!(handlers.iter().all(|handler| handler.headers == 1));
+        let mut ids: Vec<u64> = handlers.into_iter().flat_map(|h| h.ids).collect();
+        ids.sort();
+        assert_eq!(ids, vec![5816, 5822, 6356, 6362, 9772, 9774, 9775]);
+    }
+
+    #[test]
+    fn test_header() {
+        let mut reader = new_blob_reader("multipolygon.osm.pbf");
+        let header = reader.header().expect("header");
+        assert!(
+            header
+                .required_features
+                .iter()
+                .any(|f| f == "OsmSchema-V0.6")
+        );
+        assert_eq!(reader.count(), 3);
+
+        // No OSMHeader blob: the data blob read ahead must not get lost
+        let mut reader = new_blob_reader("two_primitive_groups.osm.pbf");
+        assert!(reader.header().is_none());
+        assert_eq!(reader.count(), 1);
+    }
+
+    #[test]
+    fn test_way_locations() {
+        let mut store = SortedLocationStore::new();
+        apply(
+            &mut new_blob_reader("multipolygon.osm.pbf"),
+            &mut StoreLocations(&mut store),
+        );
+        store.prepare();
+        assert_eq!(store.len(), 4);
+
+        for blob in new_blob_reader("multipolygon.osm.pbf") {
             let data = blob.into_data();
             let primitive_block = PrimitiveBlock::parse(&data);
             for primitive in primitive_block.primitives() {
-                let element: &str;
-                let mut attrs = Vec::<String>::new();
-                let mut nodes = Vec::<i64>::new();
-                let mut members = Vec::<(String, RelationMemberType, u64)>::new();
-                let mut tags = Vec::<(String, String)>::new();
-                match primitive {
-                    Primitive::Node(node) => {
-                        element = "node";
-                        attrs.push(format!("id=\"{}\"", node.id));
-                        dump_info(node.info, &mut attrs);
-                        attrs.push(format!("lat=\"{:.7?}\"", node.lat));
-                        attrs.push(format!("lon=\"{:.7?}\"", node.lon));
-                        tags.extend(
-                            node.tags
-                                .iter()
-                                .map(|(k, v)| (escape_xml(k), escape_xml(v))),
-                        );
-                    }
-                    Primitive::Way(way) => {
-                        element = "way";
-                        attrs.push(format!("id=\"{}\"", way.id));
-                        nodes.extend(way.refs());
-                        tags.extend(way.tags().map(|(k, v)| (escape_xml(k), escape_xml(v))));
-                        dump_info(way.info, &mut attrs);
-                    }
-                    Primitive::Relation(rel) => {
-                        element = "relation";
-                        attrs.push(format!("id=\"{}\"", rel.id));
-                        members
-                            .extend(rel.members().map(|(role, id, member_type)| {
-                                (escape_xml(role), member_type, id)
-                            }));
-                        tags.extend(rel.tags().map(|(k, v)| (escape_xml(k), escape_xml(v))));
-                    }
-                };
-                buf.push_str("  <");
-                buf.push_str(element);
-                let has_children = !nodes.is_empty() || !members.is_empty() || !tags.is_empty();
-                if !attrs.is_empty() {
-                    buf.push(' ');
-                    buf.push_str(&attrs.join(" "));
-                    if !has_children {
-                        buf.push('/');
+                if let Primitive::Way(way) = primitive {
+                    match way.id {
+                        9772 => {
+                            let locations = store.way_locations(&way).unwrap();
+                            assert_eq!(locations.len(), 5);
+                            assert_eq!(locations[0], Location::new(18.0, -108.0));
+                            assert_eq!(locations[0], locations[4]);
+                        }
+                        9774 => {
+                            assert_eq!(
+                                store.way_locations(&way),
+                                Err(vec![6271, 6091, 6090, 6180, 6178, 6088, 6087, 6267, 6271])
+                            );
+                        }
+                        _ => unreachable!(),
                     }
-                    buf.push_str(">\n");
                 }
-                if has_children {
-                    for n in nodes {
-                        buf.push_str(&format!("    <nd ref=\"{}\"/>\n", n));
-                    }
-                    for (role, member_type, id) in members {
-                        buf.push_str(&format!(
-                            "    <member type=\"{}\" ref=\"{}\" role=\"{}\"/>\n",
-                            relation_member_type(member_type),
-                            id,
-                            role
-                        ));
-                    }
-                    for (k, v) in tags {
-                        buf.push_str(&format!("    <tag k=\"{}\" v=\"{}\"/>\n", k, v));
-                    }
-                    buf.push_str("  </");
-                    buf.push_str(element);
-                    buf.push_str(">\n");
+            }
+        }
+    }
+
+    #[test]
+    fn test_area_assembler() {
+        let mut assembler = AreaAssembler::new();
+        apply(&mut new_blob_reader("multipolygon.osm.pbf"), &mut assembler);
+        assert_eq!(assembler.relations_len(), 1);
+
+        let mut results = vec![];
+        assembler.assemble(
+            &mut new_blob_reader("multipolygon.osm.pbf"),
+            &mut SortedLocationStore::new(),
+            |result| results.push(result),
+        );
+        assert_eq!(
+            results,
+            vec![Err(BrokenArea {
+                source: AreaSource::Relation(9775),
+                error: AreaError::MissingNodes {
+                    way_id: 9774,
+                    node_ids: vec![6271, 6091, 6090, 6180, 6178, 6088, 6087, 6267, 6271],
+                },
+            })]
+        );
+    }
+
+    #[test]
+    fn test_relation_collector() {
+        let mut collector =
+            RelationCollector::new(|mut tags| tags.any(|tag| tag == ("type", "multipolygon")));
+        apply(&mut new_blob_reader("multipolygon.osm.pbf"), &mut collector);
+        assert_eq!(collector.len(), 1);
+
+        let mut collected = vec![];
+        collector.collect(
+            &mut new_blob_reader("multipolygon.osm.pbf"),
+            |relation, members| collected.push((relation, members)),
+        );
+        assert_eq!(collected.len(), 1);
+        let (relation, members) = &collected[0];
+        assert_eq!(relation.id, 9775);
+        assert!(members.is_complete());
+        assert_eq!(members.nodes.len(), 0);
+        assert_eq!(members.ways.len(), 2);
+        assert_eq!(members.ways[&9772].refs.len(), 5);
+        assert_eq!(
+            members.ways[&9772].tags,
+            vec![("testcase".to_string(), "4800".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_geojson_ways() {
+        let mut store = SortedLocationStore::new();
+        apply(
+            &mut new_blob_reader("multipolygon.osm.pbf"),
+            &mut StoreLocations(&mut store),
+        );
+        store.prepare();
+
+        let options = GeoJsonOptions {
+            seq: true,
+            metadata: true,
+        };
+        let mut writer = GeoJsonWriter::new(vec![], options).unwrap();
+        let mut skipped = vec![];
+        for blob in new_blob_reader("multipolygon.osm.pbf") {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            for primitive in primitive_block.primitives() {
+                if let Primitive::Way(way) = primitive
+                    && !writer.write_way(&way, &store).unwrap()
+                {
+                    skipped.push(way.id);
                 }
             }
         }
-        buf.push_str("</osm>\n");
-        buf
+        assert_eq!(skipped, vec![9774]);
+        let out = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert!(out.starts_with(
+            r#"{"type":"Feature","id":"way/9772","properties":{"@type":"way","@id":9772,"@version":1,"testcase":"4800"},"geometry":{"type":"LineString","coordinates":[[-108,18],"#
+        ));
+        assert_eq!(out.lines().count(), 1);
     }
 
-    fn relation_member_type(t: RelationMemberType) -> &'static str {
-        match t {
-            RelationMemberType::Node => "node",
-            RelationMemberType::Way => "way",
-            RelationMemberType::Relation => "relation",
+    #[test]
+    fn test_xml_reader() {
+        for name in [
+            "64bit_ids",
+            "multipolygon",
+            "tag_lengths",
+            "two_primitive_groups",
+        ] {
+            let file = File::open(test_data_path(&format!("{}.xml", name))).unwrap();
+            let from_xml = XmlReader::new(BufReader::new(file))
+                .map(|element| normalize(element.unwrap()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                from_xml,
+                pbf_elements(&format!("{}.osm.pbf", name)),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_pbf_writer() {
+        for name in [
+            "64bit_ids",
+            "multipolygon",
+            "tag_lengths",
+            "two_primitive_groups",
+        ] {
+            let filename = format!("{}.osm.pbf", name);
+            let mut reader = new_blob_reader(&filename);
+            let header = reader.header().cloned().unwrap_or_default();
+            let mut writer = PbfWriter::new(vec![], &header).unwrap();
+            for element in pbf_elements(&filename) {
+                writer.write_element(element).unwrap();
+            }
+            let out = writer.finish().unwrap();
+
+            let mut written = BlobReader::new(&out[..]);
+            assert_eq!(written.header().unwrap().bbox, header.bbox, "{}", name);
+            let mut elements = vec![];
+            for blob in written {
+                let data = blob.into_data();
+                let primitive_block = PrimitiveBlock::parse(&data);
+                for primitive in primitive_block.primitives() {
+                    elements.push(normalize(Element::from(&primitive)));
+                }
+            }
+            assert_eq!(elements, pbf_elements(&filename), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_o5m() {
+        for name in [
+            "64bit_ids",
+            "multipolygon",
+            "tag_lengths",
+            "two_primitive_groups",
+        ] {
+            let filename = format!("{}.osm.pbf", name);
+            let mut reader = new_blob_reader(&filename);
+            let out = write_o5m(&mut reader, vec![]).unwrap();
+            let elements = O5mReader::new(&out[..])
+                .map(|element| normalize(element.unwrap()))
+                .collect::<Vec<_>>();
+            assert_eq!(elements, pbf_elements(&filename), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_opl() {
+        for name in [
+            "64bit_ids",
+            "multipolygon",
+            "tag_lengths",
+            "two_primitive_groups",
+        ] {
+            let filename = format!("{}.osm.pbf", name);
+            let out = write_opl(&mut new_blob_reader(&filename), vec![]).unwrap();
+            let elements = OplReader::new(&out[..])
+                .map(|element| normalize(element.unwrap()))
+                .collect::<Vec<_>>();
+            assert_eq!(elements, pbf_elements(&filename), "{}", name);
         }
+
+        let out = write_opl(&mut new_blob_reader("multipolygon.osm.pbf"), vec![]).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .starts_with("n5816 v1 c1 t2009-11-25T21:59:13Z i1 ufred T x-108 y12\n")
+        );
     }
 
-    fn dump_info(info: Option<Info>, attrs: &mut Vec<String>) {
-        let Some(info) = info else {
-            return;
+    #[test]
+    fn test_history() {
+        let opl = "n1 v1 dV c1 t2020-01-01T00:00:00Z i1 ua T x1 y1
+n1 v2 dD c2 t2020-02-01T00:00:00Z i1 ua T x y
+n2 v1 dV c1 t2020-01-01T00:00:00Z i1 ua Tname=x x2 y2
+n2 v2 dV c3 t2020-03-01T00:00:00Z i1 ua Tname=y x2 y2
+";
+        let header = HeaderBlock {
+            required_features: vec![HISTORICAL_INFORMATION.to_string()],
+            ..HeaderBlock::default()
         };
-        if let Some(version) = info.version {
-            attrs.push(format!("version=\"{}\"", version));
+        let mut writer = PbfWriter::new(vec![], &header).unwrap();
+        for element in OplReader::new(opl.as_bytes()) {
+            writer.write_element(element.unwrap()).unwrap();
         }
-        if let Some(timestamp) = info.timestamp {
-            let seconds: i64 = (timestamp / 1000).try_into().unwrap();
-            let datetime: DateTime<Utc> = Utc.timestamp_opt(seconds, 0).unwrap();
-            let timestamp_str =
-                datetime.to_rfc3339_opts(SecondsFormat::Secs, /* use_z */ true);
-            attrs.push(format!("timestamp=\"{}\"", timestamp_str));
+        let out = writer.finish().unwrap();
+
+        let mut reader = BlobReader::new(&out[..]);
+        assert!(is_history_file(reader.header().unwrap()));
+        let histories: Vec<_> = Histories::new(elements(&mut reader)).collect();
+        assert_eq!(histories.len(), 2);
+        assert!(histories[0].is_deleted());
+        assert!(!histories[1].is_deleted());
+
+        let at = Timestamp::parse("2020-02-15T00:00:00Z").unwrap();
+        let current: Vec<_> = time_filter(elements(&mut BlobReader::new(&out[..])), at).collect();
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].id(), 2);
+        assert_eq!(current[0].tags()[0].1, "x");
+    }
+
+    #[test]
+    fn test_tags_filter() {
+        let filter = TagsFilter::new(["w/highway", "building=yes", "!created_by=JOSM"]).unwrap();
+        let mut matched = vec![];
+        for blob in new_blob_reader("64bit_ids.osm.pbf") {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            let block_filter = filter.for_block(&primitive_block.stringtable);
+            for primitive in primitive_block.primitives() {
+                let element = normalize(Element::from(&primitive));
+                assert_eq!(
+                    block_filter.matches_primitive(&primitive),
+                    filter.matches_element(&element)
+                );
+                if filter.matches_primitive(&primitive) {
+                    matched.push(element);
+                }
+            }
         }
-        if let Some(changeset) = info.changeset {
-            attrs.push(format!("changeset=\"{}\"", changeset));
+
+        let expected: Vec<_> = pbf_elements("64bit_ids.osm.pbf")
+            .into_iter()
+            .filter(|element| {
+                let has = |key: &str, value: Option<&str>| {
+                    element
+                        .tags()
+                        .iter()
+                        .any(|(k, v)| k == key && value.is_none_or(|value| v == value))
+                };
+                (matches!(element, Element::Way(_)) && has("highway", None)
+                    || has("building", Some("yes")))
+                    && !has("created_by", Some("JOSM"))
+            })
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(matched, expected);
+    }
+
+    #[test]
+    fn test_extract() {
+        let bbox = HeaderBBox {
+            left: -61.80,
+            right: -61.78,
+            top: 17.15,
+            bottom: 17.13,
+        };
+        let polygon = read_multipolygon(
+            r#"{"type":"Polygon","coordinates":[[[-61.80,17.13],[-61.78,17.13],[-61.78,17.15],[-61.80,17.15]]]}"#,
+        )
+        .unwrap();
+        let run = |region, strategy| {
+            let out = extract_pbf("64bit_ids.osm.pbf", region, strategy);
+            blob_elements(BlobReader::new(&out[..]))
+        };
+
+        let all = pbf_elements("64bit_ids.osm.pbf");
+        let simple = run(Region::BBox(bbox.clone()), Strategy::Simple);
+        assert!(simple.len() < all.len());
+        for element in &simple {
+            if let Element::Node(node) = element {
+                assert!((bbox.bottom..=bbox.top).contains(&node.lat));
+                assert!((bbox.left..=bbox.right).contains(&node.lon));
+            }
         }
-        if let Some(uid) = info.uid {
-            attrs.push(format!("uid=\"{}\"", uid));
+        assert!(
+            simple
+                .iter()
+                .any(|element| matches!(element, Element::Way(_)))
+        );
+        assert_eq!(run(Region::MultiPolygon(polygon), Strategy::Simple), simple);
+
+        let complete = run(Region::BBox(bbox), Strategy::CompleteWays);
+        assert!(complete.len() > simple.len());
+        let node_ids: Vec<u64> = complete
+            .iter()
+            .filter(|element| matches!(element, Element::Node(_)))
+            .map(Element::id)
+            .collect();
+        for element in &complete {
+            if let Element::Way(way) = element {
+                assert!(way.refs.iter().all(|id| node_ids.contains(id)));
+            }
         }
-        if let Some(user) = info.user {
-            attrs.push(format!("user=\"{}\"", escape_xml(user)));
+    }
+
+    #[test]
+    fn test_poly() {
+        let poly = read_poly(&read_to_string(test_data_path("antigua.poly")).unwrap()).unwrap();
+        assert_eq!(poly.polygons.len(), 2);
+        assert_eq!(poly.polygons[0].inners.len(), 1);
+
+        let mut inside = 0;
+        let mut in_hole = 0;
+        for element in pbf_elements("64bit_ids.osm.pbf") {
+            if let Element::Node(node) = element {
+                let location = Location::new(node.lat, node.lon);
+                if poly.contains(location) {
+                    inside += 1;
+                } else if ring_contains(&poly.polygons[0].inners[0], location) {
+                    in_hole += 1;
+                }
+            }
         }
-        if let Some(visible) = info.visible {
-            attrs.push(format!("visible=\"{}\"", visible));
+        assert!(inside > 0);
+        assert!(in_hole > 0);
+
+        let out = extract_pbf(
+            "64bit_ids.osm.pbf",
+            Region::MultiPolygon(poly),
+            Strategy::Simple,
+        );
+        let nodes = blob_elements(BlobReader::new(&out[..]))
+            .into_iter()
+            .filter(|element| matches!(element, Element::Node(_)))
+            .count();
+        assert_eq!(nodes, inside);
+    }
+
+    #[test]
+    fn test_get_ids() {
+        let run = |filename: &str, ids: Ids, options| {
+            let mut writer = PbfWriter::new(vec![], &HeaderBlock::default()).unwrap();
+            let open = || Ok(new_blob_reader(filename));
+            let missing = get_ids(open, ids, options, &mut writer).unwrap();
+            assert!(missing.is_empty());
+            blob_elements(BlobReader::new(&writer.finish().unwrap()[..]))
+        };
+
+        let all = pbf_elements("64bit_ids.osm.pbf");
+        let refs = all
+            .iter()
+            .find_map(|element| match element {
+                Element::Way(way) if way.id == 1 => Some(way.refs.clone()),
+                _ => None,
+            })
+            .unwrap();
+        let mut ids = Ids::default();
+        ids.ways.insert(1);
+        let recursive = GetIdOptions {
+            recursive: true,
+            parents: false,
+        };
+        let elements = run("64bit_ids.osm.pbf", ids, recursive);
+        let expected: Vec<_> = all
+            .iter()
+            .filter(|element| match element {
+                Element::Node(node) => refs.contains(&node.id),
+                Element::Way(way) => way.id == 1,
+                Element::Relation(_) => false,
+            })
+            .cloned()
+            .collect();
+        assert_eq!(elements, expected);
+
+        let mut ids = Ids::default();
+        ids.ways.insert(9774);
+        let parents = GetIdOptions {
+            recursive: false,
+            parents: true,
+        };
+        let elements = run("multipolygon.osm.pbf", ids, parents);
+        let keys: Vec<_> = elements.iter().map(Element::sort_key).collect();
+        assert_eq!(keys, vec![(1, 9774), (2, 9775)]);
+    }
+
+    #[test]
+    fn test_check_refs() {
+        let mut check_refs = CheckRefs::new(false);
+        apply(&mut new_blob_reader("64bit_ids.osm.pbf"), &mut check_refs);
+        let report = check_refs.finish();
+        assert!(report.is_complete());
+        assert_eq!((report.nodes, report.ways), (1774, 227));
+
+        let bbox = HeaderBBox {
+            left: -61.80,
+            right: -61.78,
+            top: 17.15,
+            bottom: 17.13,
+        };
+        for (strategy, complete) in [(Strategy::Simple, false), (Strategy::CompleteWays, true)] {
+            let out = extract_pbf("64bit_ids.osm.pbf", Region::BBox(bbox.clone()), strategy);
+
+            let mut check_refs = CheckRefs::new(true);
+            apply(&mut BlobReader::new(&out[..]), &mut check_refs);
+            let report = check_refs.finish();
+            assert_eq!(report.is_complete(), complete);
+            assert_eq!(report.missing.len() as u64, report.missing_way_nodes);
+        }
+    }
+
+    #[test]
+    fn test_sort_order() {
+        for filename in [
+            "64bit_ids.osm.pbf",
+            "tag_lengths.osm.pbf",
+            "two_primitive_groups.osm.pbf",
+        ] {
+            let mut reader = new_blob_reader(filename);
+            let history = reader.header().is_some_and(is_history_file);
+            let count = check_sorted(elements(&mut reader), history).unwrap();
+            assert_eq!(count as usize, pbf_elements(filename).len());
         }
+
+        // Its nodes are not in ID order
+        let mut reader = new_blob_reader("multipolygon.osm.pbf");
+        assert!(!reader.header().is_some_and(is_sorted_file));
+        let results: Vec<_> = CheckSorted::new(elements(&mut reader), false).collect();
+        let error = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(error.violation, Violation::Id);
+        assert_eq!((error.previous, error.current), ((0, 6362), (0, 6356)));
+        assert_eq!(error.index as usize, results.len() - 1);
     }
 
-    fn escape_xml(input: &str) -> String {
-        let mut output = String::with_capacity(input.len());
-        for c in input.chars() {
-            match c {
-                '<' => output.push_str("&lt;"),
-                '>' => output.push_str("&gt;"),
-                '&' => output.push_str("&amp;"),
-                '"' => output.push_str("&quot;"),
-                _ => output.push(c),
+    fn pbf_elements(filename: &str) -> Vec<Element> {
+        blob_elements(new_blob_reader(filename))
+    }
+
+    fn blob_elements<R: Read>(reader: BlobReader<R>) -> Vec<Element> {
+        let mut elements = vec![];
+        for blob in reader {
+            let data = blob.into_data();
+            let primitive_block = PrimitiveBlock::parse(&data);
+            for primitive in primitive_block.primitives() {
+                elements.push(normalize(Element::from(&primitive)));
             }
         }
-        output
+        elements
+    }
+
+    /// Extracts `region` from a test file into a PBF file in memory
+    fn extract_pbf(filename: &str, region: Region, strategy: Strategy) -> Vec<u8> {
+        let mut writer = PbfWriter::new(vec![], &HeaderBlock::default()).unwrap();
+        let open = || Ok(new_blob_reader(filename));
+        extract(open, region, strategy, &mut writer).unwrap();
+        writer.finish().unwrap()
+    }
+
+    /// Rounds locations to the precision of XML
+    fn normalize(mut element: Element) -> Element {
+        if let Element::Node(node) = &mut element {
+            node.lat = (node.lat * 1e7).round() / 1e7;
+            node.lon = (node.lon * 1e7).round() / 1e7;
+        }
+        element
+    }
+
+    fn new_blob_reader(filename: &str) -> BlobReader<BufReader<File>> {
+        let path = test_data_path(filename);
+        let file = File::open(&path).unwrap_or_else(|_| panic!("cannot open {:?}", path));
+        BlobReader::new(BufReader::new(file))
+    }
+
+    fn test_data_path(filename: &str) -> PathBuf {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests");
+        path.push("data");
+        path.push(filename);
+        path
+    }
+
+    fn dump<R: Read>(mut reader: BlobReader<R>) -> String {
+        let out = write_xml(&mut reader, vec![], "integration_test").unwrap();
+        String::from_utf8(out).unwrap()
     }
 }